@@ -1,5 +1,6 @@
 use std::error::Error as StdError;
 use pyano::{ agent::agent_builder::AgentBuilder, chain::sequential_chain::Chain, ModelManager };
+use pyano::llm::{ context::ContextPolicy, options::LLMHTTPCallOptions };
 use log::{ info, error };
 use std::sync::{ Arc, Mutex };
 
@@ -16,9 +17,12 @@ async fn main() -> Result<(), Box<dyn StdError>> {
 
     let model_manager = Arc::new(ModelManager::new());
 
+    // The paper is longer than the context, so the part that doesn't fit is summarized
+    let long_context = LLMHTTPCallOptions::new().with_context_policy(ContextPolicy::SummarizeOverflow);
+
     let researcher_llm = model_manager
         .clone()
-        .get_llm("deepseek-R1-7B", None).await
+        .get_llm("deepseek-R1-7B", Some(long_context.clone())).await
         .map_err(|e| {
             error!("Failed to Get DeepSeek model: {}", e);
             e
@@ -28,7 +32,7 @@ async fn main() -> Result<(), Box<dyn StdError>> {
 
     let novice_llm = model_manager
         .clone()
-        .get_llm("granite", None).await
+        .get_llm("granite", Some(long_context)).await
         .map_err(|e| {
             error!("Failed to Get Qwen model: {}", e);
             e
@@ -1,11 +1,249 @@
-use pyano::model::{ ModelManager, ModelManagerServer };
+use pyano::model::persistence::StateFile;
+use pyano::model::pull::{ pull_model, PullOptions };
+use pyano::model::{
+    ModelInfo,
+    ModelManager,
+    ModelManagerClient,
+    ModelManagerInterface,
+    ModelManagerServer,
+    ModelStatus,
+    ServerSecurity,
+};
+use pyano::tools::Downloader;
+use std::collections::HashMap;
 use std::sync::Arc;
 
+const USAGE: &str =
+    "Usage: pyano-model-manager [options] <command>
+
+Commands:
+  serve                           Run the manager server
+  load <name>                     Load a registry model
+  unload <name>                   Unload a model
+  status [name]                   Status of one model, or of every loaded model
+  ls                              List loaded models
+  logs <name> [--lines 100]       Recent llama-server output of a model
+  pull <org/repo> --quant Q4_K_M [--name NAME] [--revision main] [--force]
+                                  Download a model, register it and reload the
+                                  server's registry
+
+Options:
+  --addr <host:port>      Address to serve on and to connect to (PYANO_MANAGER_ADDR,
+                          default 127.0.0.1:8090)
+  --url <url>             Server URL for client commands (PYANO_MANAGER_URL)
+  --api-key <key>         Key sent to the server (PYANO_API_KEY)
+  --config-dir <dir>      Model config directory (MODEL_CONFIG_DIR)
+  --adapters-dir <dir>    llama.cpp binaries directory (ADAPTERS_HOME)
+  --model-home <dir>      Downloaded models directory (MODEL_HOME)
+  --preload <a,b>         Models loaded when serve starts (PYANO_PRELOAD)
+  --json                  Print JSON instead of a table";
+
+// Flags that take no value
+const SWITCHES: [&str; 2] = ["--json", "--force"];
+
+struct Args {
+    positional: Vec<String>,
+    flags: HashMap<String, String>,
+    switches: Vec<String>,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Args { positional: Vec::new(), flags: HashMap::new(), switches: Vec::new() };
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            if SWITCHES.contains(&arg.as_str()) {
+                args.switches.push(arg);
+            } else if let Some(flag) = arg.strip_prefix("--") {
+                let value = iter.next().ok_or_else(|| format!("Missing value for --{}", flag))?;
+                args.flags.insert(flag.to_string(), value);
+            } else {
+                args.positional.push(arg);
+            }
+        }
+        Ok(args)
+    }
+
+    /// A flag's value, falling back to an environment variable.
+    fn value(&self, flag: &str, env: &str) -> Option<String> {
+        self.flags
+            .get(flag)
+            .cloned()
+            .or_else(|| std::env::var(env).ok().filter(|value| !value.is_empty()))
+    }
+
+    fn switch(&self, name: &str) -> bool {
+        self.switches.iter().any(|switch| switch == name)
+    }
+
+    fn arg(&self, index: usize) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("Missing argument for {}", self.positional[0]))
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Create and start the server
-    let manager = Arc::new(ModelManager::new());
-    let server = ModelManagerServer::new(manager);
-    server.run("127.0.0.1:8090").await?;
+    let args = match Args::parse() {
+        Ok(args) if !args.positional.is_empty() => args,
+        Ok(_) => usage(None),
+        Err(e) => usage(Some(e)),
+    };
+
+    // Directories are read once by PyanoConfig, so they must be set before anything runs
+    for (flag, env) in [
+        ("config-dir", "MODEL_CONFIG_DIR"),
+        ("adapters-dir", "ADAPTERS_HOME"),
+        ("model-home", "MODEL_HOME"),
+    ] {
+        if let Some(dir) = args.flags.get(flag) {
+            std::env::set_var(env, dir);
+        }
+    }
+
+    let addr = args
+        .value("addr", "PYANO_MANAGER_ADDR")
+        .unwrap_or_else(|| "127.0.0.1:8090".to_string());
+    let json = args.switch("--json");
+    let client = || {
+        let url = args.value("url", "PYANO_MANAGER_URL").unwrap_or_else(|| format!("http://{}", addr));
+        let client = ModelManagerClient::new(url.trim_end_matches('/'));
+        match args.value("api-key", "PYANO_API_KEY") {
+            Some(key) => client.with_api_key(key),
+            None => client,
+        }
+    };
+
+    match args.positional[0].as_str() {
+        "serve" => {
+            let preload: Vec<String> = args
+                .value("preload", "PYANO_PRELOAD")
+                .map(|list| {
+                    list.split(',')
+                        .map(|name| name.trim().to_string())
+                        .filter(|name| !name.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default();
+            // API keys, TLS and the load allowlist come from PYANO_* variables
+            let security = ServerSecurity::from_env()?;
+
+            // The daemon owns the state file, so it can adopt its servers after a restart
+            let manager = Arc::new(ModelManager::new().with_state_file(Some(StateFile::new())));
+            let server = ModelManagerServer::new(manager)
+                .with_security(security)
+                .with_preload(preload);
+            server.run(&addr).await?;
+        }
+        "load" => {
+            let name = args.arg(1)?;
+            client().load_model_by_name(name).await?;
+            println!("Loaded {}", name);
+        }
+        "unload" => {
+            let name = args.arg(1)?;
+            client().unload_model(name).await?;
+            println!("Unloaded {}", name);
+        }
+        "status" if args.positional.len() > 1 => {
+            let name = args.arg(1)?;
+            let status = client().get_model_status(name).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+            } else {
+                println!("{}: {}", name, status_label(&status));
+            }
+        }
+        "status" | "ls" => {
+            let models = client().list_models().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&models)?);
+            } else {
+                print_models(&models);
+            }
+        }
+        "logs" => {
+            let name = args.arg(1)?;
+            let lines = match args.flags.get("lines") {
+                Some(lines) => lines.parse()?,
+                None => 100,
+            };
+            let logs = client().logs(name, lines).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&logs)?);
+            } else {
+                for line in logs {
+                    println!("{}", line);
+                }
+            }
+        }
+        "pull" => {
+            let repo = args.arg(1)?;
+            let Some(quant) = args.flags.get("quant") else {
+                usage(Some("pull needs --quant".to_string()));
+            };
+            let mut options = PullOptions::new(quant);
+            options.name = args.flags.get("name").cloned();
+            options.force = args.switch("--force");
+            if let Some(revision) = args.flags.get("revision") {
+                options.revision = revision.clone();
+            }
+
+            let downloader = Downloader::new().with_progress_bar();
+            let pulled = pull_model(repo, &options, &downloader).await?;
+            // Pulling works without a server; it just won't see the model until reloaded
+            let reloaded = client().reload_registry().await.is_ok();
+
+            if json {
+                let files: Vec<String> = pulled.files
+                    .iter()
+                    .map(|file| file.display().to_string())
+                    .collect();
+                let summary = serde_json::json!({
+                    "name": pulled.name,
+                    "files": files,
+                    "config_path": pulled.config_path.display().to_string(),
+                    "registry_reloaded": reloaded,
+                });
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            } else {
+                println!("Model '{}' registered in {}", pulled.name, pulled.config_path.display());
+                if !reloaded {
+                    println!("Server not reachable; it picks the model up on its next registry reload");
+                }
+            }
+        }
+        command => usage(Some(format!("Unknown command: {}", command))),
+    }
     Ok(())
 }
+
+fn usage(error: Option<String>) -> ! {
+    if let Some(error) = error {
+        eprintln!("{}\n", error);
+    }
+    eprintln!("{}", USAGE);
+    std::process::exit(1);
+}
+
+fn status_label(status: &ModelStatus) -> String {
+    match status {
+        ModelStatus::Error(e) => format!("error ({})", e),
+        status => format!("{:?}", status).to_lowercase(),
+    }
+}
+
+fn print_models(models: &[ModelInfo]) {
+    println!("{:<30}  {:<10}  {:>6}  LAST USED", "NAME", "STATUS", "PORT");
+    for model in models {
+        println!(
+            "{:<30}  {:<10}  {:>6}  {}",
+            model.name,
+            status_label(&model.status),
+            model.server_port.map_or("-".to_string(), |port| port.to_string()),
+            model.last_used.format("%Y-%m-%d %H:%M:%S")
+        );
+    }
+}
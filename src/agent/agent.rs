@@ -9,6 +9,7 @@ use colored::Colorize;
 use std::io::Write;
 use std::thread;
 use std::time::Duration;
+use tracing::Instrument;
 
 pub struct Agent {
     pub(crate) system_prompt: Option<String>,
@@ -53,6 +54,13 @@ impl AgentTrait for Agent {
                 '_
         >
     > {
+        let span = tracing::info_span!(
+            "agent.invoke",
+            agent = self.name.as_deref().unwrap_or("unnamed"),
+            stream = self.stream.unwrap_or(false),
+            prompt_chars = self.user_prompt.as_ref().map_or(0, |prompt| prompt.len()),
+            output_chars = tracing::field::Empty
+        );
         Box::pin(async move {
             let llm = self.llm.as_ref().expect("LLM is required");
             let system_prompt = self.system_prompt.as_ref().expect("System prompt is missing");
@@ -123,20 +131,16 @@ impl AgentTrait for Agent {
                     println!("");
                 }
             } else {
-                let response = llm.response(user_prompt, system_prompt).await?;
-                // Safely extract and convert `response["content"]` to a string
-                if let Some(content) = response.get("content").and_then(|v| v.as_str()) {
-                    println!("");
-                    println!("Response: {}", content);
-                    println!("");
-                    output.push_str(content); // Append content to output buffer
-                } else {
-                    eprintln!("Error: `content` field is missing or not a string in the response");
-                }
+                let completion = llm.response(user_prompt, system_prompt).await?;
+                println!("");
+                println!("Response: {}", completion.content);
+                println!("");
+                output.push_str(&completion.content); // Append content to output buffer
             }
 
+            tracing::Span::current().record("output_chars", output.len());
             Ok(output) // Return the complete output
-        })
+        }.instrument(span))
     }
 
     /// Generates a formatted string representation of all tools available in the Agent.
@@ -1,90 +1,94 @@
-use reqwest::Client;
-use std::fs::File;
-use std::io::{ copy, Write };
-use std::path::Path;
-use std::env;
-use log::info;
 use tokio::fs::create_dir_all;
-use indicatif::{ ProgressBar, ProgressStyle };
-use futures_util::StreamExt;
 use dotenv::dotenv;
+use pyano::PyanoConfig;
+use pyano::model::pull::{ pull_model, PullOptions };
+use pyano::tools::{ available_quants, download_model_files, Downloader, HuggingFaceHub };
 
-async fn download_model_files(
-    model_path: &str,
-    save_dir: &str
-) -> Result<(), Box<dyn std::error::Error>> {
-    let client = Client::new();
-    let response = client.get(model_path).send().await?;
-
-    if response.status().is_success() {
-        let file_name = model_path.split('/').last().unwrap_or("model");
-        let file_path = Path::new(save_dir).join(file_name);
-        let mut file = File::create(file_path)?;
-
-        let total_size = response.content_length().unwrap_or(0);
-        let pb = ProgressBar::new(total_size);
-        let style = ProgressStyle::default_bar()
-            .template(
-                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})"
-            )?
-            .progress_chars("#>-");
-        pb.set_style(style);
-
-        let mut stream = response.bytes_stream();
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk?;
-            file.write_all(&chunk)?;
-            pb.inc(chunk.len() as u64);
-        }
-
-        pb.finish_with_message("Download complete");
-        println!("Model downloaded successfully to {}", save_dir);
-    } else {
-        eprintln!("Failed to download model: {}", response.status());
-    }
-
-    Ok(())
-}
+const USAGE: &str =
+    "Usage: cargo run --bin pull <org/repo | url> [--quant Q4_K_M] [--revision main] [--mirror URL] [--name NAME] [--connections N] [--force]";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
-    // Parse the argument
+    // Parse the arguments
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 2 {
-        eprintln!("Usage: cargo run --bin pull <model_path>");
+        eprintln!("{}", USAGE);
         std::process::exit(1);
     }
 
-    // will update integration of quants more smoother.
+    let model_path = &args[1];
     let mut quant = None;
-    for i in 1..args.len() {
-        if args[i] == "--quant" && i + 1 < args.len() {
-            quant = Some(args[i + 1].clone());
+    let mut options = PullOptions::new("");
+    let mut connections = 4;
+    let mut i = 2;
+    while i < args.len() {
+        let value = args.get(i + 1).cloned();
+        match (args[i].as_str(), value) {
+            ("--quant", Some(v)) => {
+                quant = Some(v);
+            }
+            ("--revision", Some(v)) => {
+                options.revision = v;
+            }
+            ("--mirror", Some(v)) => {
+                options.endpoint = Some(v);
+            }
+            ("--name", Some(v)) => {
+                options.name = Some(v);
+            }
+            ("--connections", Some(v)) => {
+                connections = v.parse()?;
+            }
+            ("--force", _) => {
+                options.force = true;
+                i += 1;
+                continue;
+            }
+            (flag, _) => {
+                eprintln!("Unknown or incomplete argument: {}\n{}", flag, USAGE);
+                std::process::exit(1);
+            }
         }
+        i += 2;
     }
 
-    // Define the model path
-    let model_path = &args[1];
-
-    // Define the directory to save the model files
-    let model_name = model_path.split('/').last().unwrap().split('-').next().unwrap();
+    // Plain URLs are downloaded as-is, without writing a model config
+    if model_path.starts_with("http://") || model_path.starts_with("https://") {
+        let model_name = model_path.split('/').last().unwrap().split('-').next().unwrap();
+        let save_dir = match &quant {
+            Some(quant_value) => format!("{}/{}", model_name, quant_value),
+            None => model_name.to_string(),
+        };
+        let save_dir = PyanoConfig::global().model_home.join(&save_dir).display().to_string();
 
-    let save_dir: String;
-    if let Some(quant_value) = quant {
-        save_dir = format!("{}/{}", model_name, quant_value);
-        println!("Quant value provided: {}", quant_value);
-    } else {
-        save_dir = format!("{}", model_name);
-        println!("No quant value provided, proceeding without it.");
+        println!("Saving model files to: {}", save_dir);
+        create_dir_all(save_dir.clone()).await?;
+        download_model_files(model_path, &save_dir, None).await?;
+        println!("Model files downloaded successfully.");
+        return Ok(());
     }
-    let save_dir =
-        env::var("MODEL_HOME").unwrap_or("./pyano_home/models".to_string()) + "/" + &save_dir;
 
-    println!("Saving model files to: {}", save_dir);
-    create_dir_all(save_dir.clone()).await?;
-    // Download the model files
-    download_model_files(model_path, &save_dir).await?;
-    print!("Model files downloaded successfully.");
+    let Some(quant) = quant else {
+        let mut hub = HuggingFaceHub::new();
+        if let Some(endpoint) = &options.endpoint {
+            hub = hub.with_endpoint(endpoint);
+        }
+        let files = hub.list_files(model_path, &options.revision).await?;
+        eprintln!("--quant is required. Available quants in {}:", model_path);
+        for quant in available_quants(&files) {
+            eprintln!("  {}", quant);
+        }
+        std::process::exit(1);
+    };
+    options.quant = quant;
+
+    let downloader = Downloader::new().with_connections(connections).with_progress_bar();
+    let pulled = pull_model(model_path, &options, &downloader).await?;
+
+    for file in &pulled.files {
+        println!("Downloaded {}", file.display());
+    }
+    println!("Model '{}' registered in {}", pulled.name, pulled.config_path.display());
     Ok(())
 }
@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use dotenv::dotenv;
+use indicatif::HumanBytes;
+use pyano::model::config_loader::ModelRegistry;
+use pyano::model::error::{ ModelError, ModelResult };
+use pyano::model::store::{ parse_size, GcOptions, ModelStore, VerifyStatus };
+use pyano::model::{ ModelManagerClient, ModelManagerInterface, ModelStatus };
+
+#[path = "pyano/chat.rs"]
+mod chat;
+
+const USAGE: &str =
+    "Usage: cargo run --bin pyano chat <model> [--system TEXT] [--temp T] [--session FILE]
+       cargo run --bin pyano models <command>
+
+Model commands:
+  ls                              List downloaded files and the models using them
+  du                              Show disk usage per model
+  verify [name]                   Check files against their configured sha256
+  rm <name>                       Delete the downloaded files of a model
+  gc [--budget 50G] [--dry-run]   Remove orphaned files, stale partial downloads,
+                                  and the oldest models until under the budget";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("chat") {
+        return chat::run(&args[2..]).await;
+    }
+    if args.len() < 3 || args[1] != "models" {
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+
+    let registry = ModelRegistry::new();
+    let store = ModelStore::new();
+
+    match args[2].as_str() {
+        "ls" => {
+            println!("{:>10}  {:<9}  {:<30}  PATH", "SIZE", "KIND", "MODELS");
+            for entry in store.list(&registry)? {
+                let models = if entry.models.is_empty() {
+                    "(orphaned)".to_string()
+                } else {
+                    entry.models.join(",")
+                };
+                println!(
+                    "{:>10}  {:<9}  {:<30}  {}",
+                    HumanBytes(entry.size).to_string(),
+                    format!("{:?}", entry.kind).to_lowercase(),
+                    models,
+                    entry.path.display()
+                );
+            }
+        }
+        "du" => {
+            let usage = store.du(&registry)?;
+            for (model, size) in &usage.per_model {
+                println!("{:>10}  {}", HumanBytes(*size).to_string(), model);
+            }
+            println!();
+            println!("{:>10}  gguf", HumanBytes(usage.gguf).to_string());
+            println!("{:>10}  embeddings", HumanBytes(usage.embeddings).to_string());
+            println!("{:>10}  partial downloads", HumanBytes(usage.partial).to_string());
+            println!("{:>10}  other", HumanBytes(usage.other).to_string());
+            println!("{:>10}  orphaned", HumanBytes(usage.orphaned).to_string());
+            println!("{:>10}  total", HumanBytes(usage.total).to_string());
+        }
+        "verify" => {
+            let mut failed = false;
+            for report in store.verify(&registry, args.get(3).map(String::as_str)).await {
+                let status = match &report.status {
+                    VerifyStatus::Ok => "ok".to_string(),
+                    VerifyStatus::NoChecksum => "present (no checksum configured)".to_string(),
+                    VerifyStatus::Missing => "missing".to_string(),
+                    VerifyStatus::Mismatch { expected, actual } => {
+                        failed = true;
+                        format!("CHECKSUM MISMATCH (expected {}, got {})", expected, actual)
+                    }
+                    VerifyStatus::Incomplete { missing } => {
+                        failed = true;
+                        format!("incomplete, missing {}", missing.join(", "))
+                    }
+                    VerifyStatus::Error { error } => {
+                        failed = true;
+                        format!("error: {}", error)
+                    }
+                };
+                println!("{:<30}  {}", report.name, status);
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        "rm" => {
+            let Some(name) = args.get(3) else {
+                eprintln!("{}", USAGE);
+                std::process::exit(1);
+            };
+            for path in store.remove(&registry, name)? {
+                println!("Removed {}", path.display());
+            }
+        }
+        "gc" => {
+            let mut options = GcOptions::default();
+            let mut i = 3;
+            while i < args.len() {
+                match args[i].as_str() {
+                    "--dry-run" => {
+                        options.dry_run = true;
+                    }
+                    "--budget" => {
+                        let budget = args.get(i + 1).and_then(|size| parse_size(size));
+                        if budget.is_none() {
+                            eprintln!("--budget expects a size such as 500M or 20G");
+                            std::process::exit(1);
+                        }
+                        options.budget_bytes = budget;
+                        i += 1;
+                    }
+                    other => {
+                        eprintln!("Unknown argument: {}\n{}", other, USAGE);
+                        std::process::exit(1);
+                    }
+                }
+                i += 1;
+            }
+
+            options.keep = running_models().await?;
+            let report = store.gc(&registry, &options)?;
+            let verb = if report.dry_run { "Would remove" } else { "Removed" };
+            for path in &report.removed {
+                println!("{} {}", verb, path.display());
+            }
+            if !report.evicted_models.is_empty() {
+                println!("Evicted models: {}", report.evicted_models.join(", "));
+            }
+            println!(
+                "{} {}, {} left",
+                if report.dry_run { "Would free" } else { "Freed" },
+                HumanBytes(report.freed_bytes),
+                HumanBytes(report.remaining_bytes)
+            );
+        }
+        other => {
+            eprintln!("Unknown command: {}\n{}", other, USAGE);
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Models the manager daemon has loaded or is loading, which gc must keep.
+/// Empty when no daemon is listening.
+async fn running_models() -> ModelResult<HashSet<String>> {
+    let url = std::env::var("PYANO_MANAGER_URL").unwrap_or_else(|_| {
+        let addr = std::env::var("PYANO_MANAGER_ADDR")
+            .unwrap_or_else(|_| "127.0.0.1:8090".to_string());
+        format!("http://{}", addr)
+    });
+    let mut client = ModelManagerClient::new(url.trim_end_matches('/'));
+    if let Ok(key) = std::env::var("PYANO_API_KEY") {
+        client = client.with_api_key(key);
+    }
+    match client.list_models().await {
+        Ok(models) =>
+            Ok(
+                models
+                    .into_iter()
+                    .filter(|model| {
+                        matches!(model.status, ModelStatus::Running | ModelStatus::Loading)
+                    })
+                    .map(|model| model.name)
+                    .collect()
+            ),
+        Err(ModelError::RequestError(e)) if e.is_connect() => Ok(HashSet::new()),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,213 @@
+use std::error::Error as StdError;
+use std::io::{ self, BufRead, Write };
+use std::path::Path;
+use std::sync::Arc;
+use colored::Colorize;
+use futures::StreamExt;
+use pyano::ModelManager;
+use pyano::llm::chat::ChatSession;
+use pyano::llm::llm_builder::LLM;
+use pyano::model::metrics::Metrics;
+
+const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant.";
+
+const HELP: &str =
+    "Commands:
+  /system [text]     Show or set the system prompt
+  /temp [value]      Show or set the temperature
+  /model <name>      Switch to another registry model, keeping the history
+  /save <file>       Save the session as JSON
+  /load <file>       Load a saved session
+  /reset             Forget the history
+  /stats             Tokens and generation speed
+  /exit              Quit
+Start and end a multiline message with \"\"\".";
+
+/// Runs `pyano chat <model> [--system TEXT] [--temp T] [--session FILE]`.
+pub async fn run(args: &[String]) -> Result<(), Box<dyn StdError>> {
+    let Some(model) = args.first() else {
+        return Err("Usage: pyano chat <model> [--system TEXT] [--temp T] [--session FILE]".into());
+    };
+    let mut session = ChatSession::new(model, DEFAULT_SYSTEM_PROMPT);
+    let mut i = 1;
+    while i < args.len() {
+        let Some(value) = args.get(i + 1) else {
+            return Err(format!("Missing value for {}", args[i]).into());
+        };
+        match args[i].as_str() {
+            "--system" => {
+                session.system_prompt = value.clone();
+            }
+            "--temp" => {
+                session.temperature = Some(value.parse()?);
+            }
+            "--session" => {
+                session = ChatSession::load(Path::new(value))?;
+            }
+            other => {
+                return Err(format!("Unknown argument: {}", other).into());
+            }
+        }
+        i += 2;
+    }
+
+    // Servers started here belong to this session, so nothing is recorded for a later run
+    let manager = Arc::new(ModelManager::new().with_state_file(None));
+    let result = chat(&manager, session).await;
+    manager.shutdown().await;
+    result
+}
+
+/// Reads messages and commands until /exit or the end of input.
+async fn chat(
+    manager: &Arc<ModelManager>,
+    mut session: ChatSession
+) -> Result<(), Box<dyn StdError>> {
+    let mut llm = manager.clone().get_llm(&session.model, None).await?;
+    println!("Chatting with {}. Type /help for commands.", session.model.bold());
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("{} ", ">".bright_green());
+        io::stdout().flush()?;
+        let Some(input) = read_message(&mut lines)? else {
+            break;
+        };
+        let input = input.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        if let Some(command) = input.strip_prefix('/') {
+            let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+            let arg = arg.trim();
+            match command {
+                "exit" | "quit" => {
+                    break;
+                }
+                "help" => println!("{}", HELP),
+                "system" if arg.is_empty() => println!("{}", session.system_prompt),
+                "system" => {
+                    session.system_prompt = arg.to_string();
+                }
+                "temp" if arg.is_empty() => {
+                    let temperature = session.temperature.or(llm.options().temperature);
+                    println!("{}", temperature.map_or("default".to_string(), |t| t.to_string()));
+                }
+                "temp" =>
+                    match arg.parse::<f32>() {
+                        Ok(temperature) => {
+                            session.temperature = Some(temperature);
+                        }
+                        Err(_) => eprintln!("/temp expects a number"),
+                    }
+                "model" if !arg.is_empty() => {
+                    match manager.clone().get_llm(arg, None).await {
+                        Ok(next) => {
+                            llm = next;
+                            session.model = arg.to_string();
+                            println!("Switched to {}", arg.bold());
+                        }
+                        Err(e) => eprintln!("Cannot switch to {}: {}", arg, e),
+                    }
+                }
+                "save" if !arg.is_empty() => {
+                    match session.save(Path::new(arg)) {
+                        Ok(()) => println!("Saved {} turn(s) to {}", session.turns.len(), arg),
+                        Err(e) => eprintln!("Cannot save {}: {}", arg, e),
+                    }
+                }
+                "load" if !arg.is_empty() => {
+                    match ChatSession::load(Path::new(arg)) {
+                        Ok(loaded) => {
+                            if loaded.model != session.model {
+                                match manager.clone().get_llm(&loaded.model, None).await {
+                                    Ok(next) => {
+                                        llm = next;
+                                    }
+                                    Err(e) => {
+                                        eprintln!("Cannot switch to {}: {}", loaded.model, e);
+                                        continue;
+                                    }
+                                }
+                            }
+                            session = loaded;
+                            println!("Loaded {} turn(s) with {}", session.turns.len(), session.model);
+                        }
+                        Err(e) => eprintln!("Cannot load {}: {}", arg, e),
+                    }
+                }
+                "reset" => {
+                    session.reset();
+                    println!("History cleared");
+                }
+                "stats" => {
+                    let tokens = Metrics::global().token_stats(&session.model);
+                    println!("turns:            {}", session.turns.len());
+                    println!("prompt tokens:    {}", tokens.prompt_tokens);
+                    println!("generated tokens: {}", tokens.predicted_tokens);
+                    match tokens.tokens_per_second {
+                        Some(rate) => println!("tokens/sec:       {:.2}", rate),
+                        None => println!("tokens/sec:       -"),
+                    }
+                }
+                _ => eprintln!("Unknown command /{}. Type /help for commands.", command),
+            }
+            continue;
+        }
+
+        match reply(&llm, &session, input).await {
+            Ok(answer) => session.push_turn(input, answer.trim()),
+            Err(e) => eprintln!("{} {}", "Error:".red(), e),
+        }
+    }
+    Ok(())
+}
+
+/// Reads one message; lines between `"""` markers form a single message.
+fn read_message(
+    lines: &mut impl Iterator<Item = io::Result<String>>
+) -> io::Result<Option<String>> {
+    let Some(first) = lines.next().transpose()? else {
+        return Ok(None);
+    };
+    if first.trim() != "\"\"\"" {
+        return Ok(Some(first));
+    }
+    let mut message = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.trim() == "\"\"\"" {
+            break;
+        }
+        message.push(line);
+    }
+    Ok(Some(message.join("\n")))
+}
+
+/// Streams the answer to `input` given the history so far and returns it.
+async fn reply(
+    llm: &LLM,
+    session: &ChatSession,
+    input: &str
+) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    // The whole conversation is rendered here, so the request itself uses no template
+    let template = llm.options().prompt_template.clone().unwrap_or_default();
+    let prompt = session.render(&template, input);
+    let mut options = llm.options().clone().with_prompt_template("{user_prompt}".to_string());
+    if let Some(temperature) = session.temperature {
+        options = options.with_temperature(temperature);
+    }
+
+    let mut stream = llm.clone().with_options(options).response_stream(&prompt, "").await?;
+    let mut answer = String::new();
+    while let Some(chunk) = stream.next().await {
+        let text = String::from_utf8_lossy(&chunk?).to_string();
+        print!("{}", text);
+        io::stdout().flush()?;
+        answer.push_str(&text);
+    }
+    println!();
+    Ok(answer)
+}
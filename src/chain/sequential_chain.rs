@@ -4,6 +4,7 @@ use log::{ debug, error, info };
 use crate::agent::agent_trait::AgentTrait;
 use std::sync::{ Arc, Mutex };
 use colored::Colorize;
+use tracing::Instrument;
 #[derive(Clone)]
 pub struct ExecutionRecord {
     pub agent_name: String,
@@ -50,6 +51,11 @@ impl Chain {
     /// Run all agents in sequence.
     /// The output of agent i is passed as user_prompt to agent i+1.
     pub async fn run(&mut self) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        let span = tracing::info_span!("chain.run", agents = self.agents.len());
+        self.run_agents().instrument(span).await
+    }
+
+    async fn run_agents(&mut self) -> Result<(), Box<dyn StdError + Send + Sync>> {
         let mut previous_output: Option<String> = None;
 
         for agent in &self.agents {
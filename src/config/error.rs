@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("IO error reading {0}: {1}")] Io(PathBuf, std::io::Error),
+
+    #[error("Unsupported config format: {0}")] UnsupportedFormat(PathBuf),
+
+    #[error("Failed to parse {0}: {1}")] Parse(PathBuf, String),
+
+    #[error("Environment variable `{0}` is not set and has no default")] MissingEnvVar(String),
+
+    #[error("Config `{0}` extends unknown config `{1}`")] UnknownBase(String, String),
+
+    #[error("Cyclic `extends` chain: {0}")] CyclicExtends(String),
+
+    #[error("Invalid config: {0}")] Invalid(String),
+}
+
+pub type ConfigResult<T> = std::result::Result<T, ConfigError>;
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use regex::{ Captures, Regex };
+use serde_json::Value;
+
+use super::error::{ ConfigError, ConfigResult };
+
+/// Key a config uses to inherit every field from another config by name.
+pub const EXTENDS_KEY: &str = "extends";
+
+const SUPPORTED_EXTENSIONS: [&str; 4] = ["json", "toml", "yaml", "yml"];
+
+pub fn is_config_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| SUPPORTED_EXTENSIONS.contains(&ext))
+}
+
+/// Reads a JSON, TOML or YAML file into a JSON value, picking the parser from
+/// the file extension.
+pub fn parse_file(path: &Path) -> ConfigResult<Value> {
+    let content = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+    let parse_err = |e: String| ConfigError::Parse(path.to_path_buf(), e);
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content).map_err(|e| parse_err(e.to_string())),
+        Some("toml") => toml::from_str(&content).map_err(|e| parse_err(e.to_string())),
+        Some("yaml") | Some("yml") =>
+            serde_yaml::from_str(&content).map_err(|e| parse_err(e.to_string())),
+        _ => Err(ConfigError::UnsupportedFormat(path.to_path_buf())),
+    }
+}
+
+/// Replaces `${VAR}` and `${VAR:-default}` in every string of `value`.
+/// `$${VAR}` is left as the literal text `${VAR}`.
+pub fn interpolate_env(value: &mut Value) -> ConfigResult<()> {
+    interpolate_with(value, env_reference_re(), &|name| env::var(name).ok())
+}
+
+fn env_reference_re() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"\$(\$)?\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").unwrap())
+}
+
+fn interpolate_with(
+    value: &mut Value,
+    re: &Regex,
+    lookup: &dyn Fn(&str) -> Option<String>
+) -> ConfigResult<()> {
+    match value {
+        Value::String(s) => {
+            let mut missing = None;
+            let replaced = re.replace_all(s, |caps: &Captures| {
+                if caps.get(1).is_some() {
+                    return caps[0][1..].to_string();
+                }
+                let name = &caps[2];
+                match (lookup(name), caps.get(3)) {
+                    (Some(v), _) => v,
+                    (None, Some(default)) => default.as_str().to_string(),
+                    (None, None) => {
+                        missing.get_or_insert_with(|| name.to_string());
+                        String::new()
+                    }
+                }
+            });
+            if let Some(name) = missing {
+                return Err(ConfigError::MissingEnvVar(name));
+            }
+            *s = replaced.into_owned();
+        }
+        Value::Array(items) => {
+            for item in items {
+                interpolate_with(item, re, lookup)?;
+            }
+        }
+        Value::Object(map) => {
+            for (_, item) in map.iter_mut() {
+                interpolate_with(item, re, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Deep-merges `overlay` into `base`. Objects merge key by key; any other
+/// value in `overlay` replaces the one in `base`.
+pub fn merge(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Object(base_map), Value::Object(overlay_map)) => {
+            for (key, overlay_value) in overlay_map {
+                match base_map.get_mut(key) {
+                    Some(base_value) => merge(base_value, overlay_value),
+                    None => {
+                        base_map.insert(key.clone(), overlay_value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => {
+            *base = overlay.clone();
+        }
+    }
+}
+
+/// Builds the full config for `name` by merging it on top of its `extends`
+/// chain. `raw` maps config names to their unresolved values.
+pub fn resolve_extends(name: &str, raw: &HashMap<String, Value>) -> ConfigResult<Value> {
+    let mut chain: Vec<String> = Vec::new();
+    let mut current = name.to_string();
+
+    loop {
+        if chain.contains(&current) {
+            chain.push(current);
+            return Err(ConfigError::CyclicExtends(chain.join(" -> ")));
+        }
+        let value = raw
+            .get(&current)
+            .ok_or_else(|| {
+                ConfigError::UnknownBase(
+                    chain.last().cloned().unwrap_or_else(|| name.to_string()),
+                    current.clone()
+                )
+            })?;
+        chain.push(current.clone());
+        match value.get(EXTENDS_KEY).and_then(|v| v.as_str()) {
+            Some(base) => {
+                current = base.to_string();
+            }
+            None => {
+                break;
+            }
+        }
+    }
+
+    // Apply from the root base down to the config itself
+    let mut resolved = Value::Object(serde_json::Map::new());
+    for link in chain.iter().rev() {
+        merge(&mut resolved, &raw[link]);
+    }
+    if let Value::Object(map) = &mut resolved {
+        map.remove(EXTENDS_KEY);
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_interpolate_env() {
+        let re = env_reference_re();
+        let lookup = |name: &str| (name == "HOME_DIR").then(|| "/opt/pyano".to_string());
+
+        let mut value = json!({ "path": "${HOME_DIR}/models", "port": "${PORT:-5010}" });
+        interpolate_with(&mut value, re, &lookup).unwrap();
+        assert_eq!(value, json!({ "path": "/opt/pyano/models", "port": "5010" }));
+
+        // An escaped reference is kept literally, even when the variable is unset
+        let mut escaped = json!("$${NOT_SET} and $${HOME_DIR}");
+        interpolate_with(&mut escaped, re, &lookup).unwrap();
+        assert_eq!(escaped, json!("${NOT_SET} and ${HOME_DIR}"));
+
+        let mut missing = json!("${NOT_SET}");
+        assert!(matches!(
+            interpolate_with(&mut missing, re, &lookup),
+            Err(ConfigError::MissingEnvVar(name)) if name == "NOT_SET"
+        ));
+    }
+
+    #[test]
+    fn test_resolve_extends() {
+        let mut raw = HashMap::new();
+        raw.insert(
+            "qwen-base".to_string(),
+            json!({ "server_config": { "ctx_size": 8192, "batch_size": 512 } })
+        );
+        raw.insert(
+            "qwen-q4".to_string(),
+            json!({ "extends": "qwen-base", "server_config": { "ctx_size": 4096 } })
+        );
+
+        let resolved = resolve_extends("qwen-q4", &raw).unwrap();
+        assert_eq!(resolved, json!({ "server_config": { "ctx_size": 4096, "batch_size": 512 } }));
+
+        raw.insert("loop".to_string(), json!({ "extends": "loop" }));
+        assert!(matches!(resolve_extends("loop", &raw), Err(ConfigError::CyclicExtends(_))));
+    }
+}
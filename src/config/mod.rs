@@ -0,0 +1,6 @@
+pub mod error;
+pub mod layering;
+pub mod pyano_config;
+
+pub use error::{ ConfigError, ConfigResult };
+pub use pyano_config::PyanoConfig;
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::path::{ Path, PathBuf };
+use std::sync::OnceLock;
+use log::{ debug, warn };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+
+use super::error::{ ConfigError, ConfigResult };
+use super::layering::{ interpolate_env, parse_file };
+use crate::model::system_memory::DEFAULT_HEADROOM_GB;
+use crate::model::utils::get_env_var;
+
+const CONFIG_FILE_NAMES: [&str; 4] = ["config.toml", "config.yaml", "config.yml", "config.json"];
+
+/// Crate-wide settings, resolved once from (lowest to highest precedence):
+/// built-in defaults, the global config file, and the `PYANO_HOME`,
+/// `MODEL_HOME`, `MODEL_CONFIG_DIR`, `ADAPTERS_HOME`, `DATABASE_URL` and
+/// `PYANO_*` environment variables.
+///
+/// The config file is taken from `PYANO_CONFIG`, or the first
+/// `config.{toml,yaml,yml,json}` found in the pyano home or `~/.pyano`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PyanoConfig {
+    pub pyano_home: PathBuf,
+    pub model_home: PathBuf,
+    pub model_config_dir: PathBuf,
+    pub adapters_home: PathBuf,
+    pub database_url: Option<String>,
+    /// Memory left free when deciding whether a model fits, in GB
+    pub memory_headroom_gb: f32,
+    /// Which loaded models to unload first: `lru`, `lfu`, `priority` or `smallest`
+    pub eviction_policy: String,
+    /// How long a load waits for memory held by busy or pinned models
+    pub load_queue_timeout_secs: u64,
+    /// Partial model configs keyed by model name, merged over the registry
+    /// entry, e.g. `[models."Qwen2.5-1.5B".server_config] ctx_size = 4096`.
+    pub models: HashMap<String, Value>,
+    /// The file these settings were read from, if any.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+/// What may appear in the config file; every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PyanoConfigFile {
+    pyano_home: Option<PathBuf>,
+    model_home: Option<PathBuf>,
+    model_config_dir: Option<PathBuf>,
+    adapters_home: Option<PathBuf>,
+    database_url: Option<String>,
+    memory_headroom_gb: Option<f32>,
+    eviction_policy: Option<String>,
+    load_queue_timeout_secs: Option<u64>,
+    #[serde(default)]
+    models: HashMap<String, Value>,
+}
+
+static GLOBAL_CONFIG: OnceLock<PyanoConfig> = OnceLock::new();
+
+impl Default for PyanoConfig {
+    fn default() -> Self {
+        Self::resolve(PyanoConfigFile::default(), None)
+    }
+}
+
+impl PyanoConfig {
+    /// The process-wide config, loaded on first use. A broken config file is
+    /// logged and replaced by defaults plus environment overrides.
+    pub fn global() -> &'static PyanoConfig {
+        GLOBAL_CONFIG.get_or_init(|| {
+            Self::load().unwrap_or_else(|e| {
+                warn!("Failed to load pyano config, using defaults: {}", e);
+                Self::default()
+            })
+        })
+    }
+
+    /// Locates and loads the global config file.
+    pub fn load() -> ConfigResult<Self> {
+        match Self::locate() {
+            Some(path) => Self::from_file(&path),
+            None => {
+                debug!("No pyano config file found, using defaults");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub fn from_file(path: &Path) -> ConfigResult<Self> {
+        debug!("Loading pyano config from {}", path.display());
+        let mut value = parse_file(path)?;
+        interpolate_env(&mut value)?;
+        let file: PyanoConfigFile = serde_json
+            ::from_value(value)
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?;
+        Ok(Self::resolve(file, Some(path.to_path_buf())))
+    }
+
+    fn locate() -> Option<PathBuf> {
+        if let Some(path) = get_env_var("PYANO_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        let mut dirs_to_search = vec![
+            PathBuf::from(get_env_var("PYANO_HOME").unwrap_or("pyano_home".to_string()))
+        ];
+        if let Some(home) = dirs::home_dir() {
+            dirs_to_search.push(home.join(".pyano"));
+        }
+
+        dirs_to_search
+            .iter()
+            .flat_map(|dir| CONFIG_FILE_NAMES.iter().map(move |name| dir.join(name)))
+            .find(|path| path.is_file())
+    }
+
+    fn resolve(file: PyanoConfigFile, source: Option<PathBuf>) -> Self {
+        let env_path = |key: &str| get_env_var(key).map(PathBuf::from);
+
+        let pyano_home = env_path("PYANO_HOME")
+            .or(file.pyano_home)
+            .unwrap_or_else(|| PathBuf::from("pyano_home"));
+        let model_home = env_path("MODEL_HOME")
+            .or(file.model_home)
+            .unwrap_or_else(|| pyano_home.join("models"));
+        let model_config_dir = env_path("MODEL_CONFIG_DIR")
+            .or(file.model_config_dir)
+            .unwrap_or_else(|| pyano_home.join("configs"));
+        let adapters_home = env_path("ADAPTERS_HOME")
+            .or(file.adapters_home)
+            .unwrap_or_else(|| pyano_home.join("adapters"));
+        let database_url = get_env_var("DATABASE_URL").or(file.database_url);
+        let memory_headroom_gb = get_env_var("PYANO_MEMORY_HEADROOM_GB")
+            .and_then(|value| value.parse().ok())
+            .or(file.memory_headroom_gb)
+            .unwrap_or(DEFAULT_HEADROOM_GB);
+        let eviction_policy = get_env_var("PYANO_EVICTION_POLICY")
+            .or(file.eviction_policy)
+            .unwrap_or_else(|| "lru".to_string());
+        let load_queue_timeout_secs = get_env_var("PYANO_LOAD_QUEUE_TIMEOUT_SECS")
+            .and_then(|value| value.parse().ok())
+            .or(file.load_queue_timeout_secs)
+            .unwrap_or(60);
+
+        Self {
+            pyano_home,
+            model_home,
+            model_config_dir,
+            adapters_home,
+            database_url,
+            memory_headroom_gb,
+            eviction_policy,
+            load_queue_timeout_secs,
+            models: file.models,
+            source,
+        }
+    }
+
+    /// Partial overrides configured for one model, if any.
+    pub fn model_override(&self, name: &str) -> Option<&Value> {
+        self.models.get(name)
+    }
+}
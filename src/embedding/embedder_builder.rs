@@ -5,7 +5,7 @@ use super::embedder_trait::Embedder;
 use super::embedding_models::EmbeddingModels;
 use super::embedder::DefaultEmbedder;
 use super::error::EmbedderError;
-use dirs;
+use log::{ info, warn };
 
 pub struct EmbeddingBuilder {
     model: EmbeddingModels,
@@ -36,14 +36,20 @@ impl EmbeddingBuilder {
     /// Build a `DefaultEmbedder` instance. This ensures the model files are present
     /// and sets everything up. Note that this does not generate embeddings yet.
     pub async fn build_embedder(&self) -> Result<DefaultEmbedder, EmbedderError> {
-        let model_path = dirs
-            ::home_dir()
-            .expect("Unable to get home directory")
-            .join(self.model.model_path());
+        let model_dir = self.model.model_dir();
+        let category_dir = model_dir.parent().unwrap_or(&model_dir);
+        match self.model.migrate_flat_install(category_dir) {
+            Ok(true) => info!("Moved {} into {}", self.model.model_name(), model_dir.display()),
+            Ok(false) => {}
+            Err(e) => {
+                warn!("Cannot move {} into its own directory: {}", self.model.model_name(), e)
+            }
+        }
 
+        // The embedder downloads into <category_dir>/<model_name>, i.e. `model_dir`
         let embbedder = DefaultEmbedder::new(
             self.model.model_name(),
-            &model_path,
+            category_dir,
             self.model.clone(),
             self.model.base_url(),
             self.model
@@ -1,3 +1,8 @@
+use std::fs;
+use std::io;
+use std::path::{ Path, PathBuf };
+use serde_json::Value;
+
 #[derive(Clone)]
 pub enum TextEmbeddingModels {
     MiniLMV6,
@@ -46,6 +51,76 @@ impl EmbeddingModels {
         }
     }
 
+    /// Directory the model's files are downloaded into. Text and image models
+    /// share a parent, so each one gets its own subdirectory named after it.
+    pub fn model_dir(&self) -> PathBuf {
+        dirs::home_dir()
+            .expect("Unable to get home directory")
+            .join(self.model_path())
+            .join(self.model_name())
+    }
+
+    /// Subdirectory of the embedding home holding models of this kind.
+    pub fn category(&self) -> &'static str {
+        match self {
+            EmbeddingModels::Text(_) => "text",
+            EmbeddingModels::Image(_) => "image",
+        }
+    }
+
+    /// Moves this model's files out of a flat install, where they were written
+    /// straight into `category_dir`, into `<category_dir>/<model_name>`.
+    /// Returns whether anything was moved.
+    pub fn migrate_flat_install(&self, category_dir: &Path) -> io::Result<bool> {
+        if !self.holds_flat_install(category_dir) {
+            return Ok(false);
+        }
+        let model_dir = category_dir.join(self.model_name());
+        let mut moved = false;
+        for file in self.required_files() {
+            let source = category_dir.join(file);
+            let target = model_dir.join(file);
+            if !source.is_file() || target.exists() {
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&source, &target)?;
+            moved = true;
+            // Subdirectories such as 1_Pooling are left empty
+            if let Some(parent) = source.parent().filter(|parent| *parent != category_dir) {
+                let _ = fs::remove_dir(parent);
+            }
+        }
+        Ok(moved)
+    }
+
+    // Text models share file names, so their layer count tells them apart
+    fn holds_flat_install(&self, category_dir: &Path) -> bool {
+        let Ok(contents) = fs::read_to_string(category_dir.join("config.json")) else {
+            return false;
+        };
+        let layers = serde_json
+            ::from_str::<Value>(&contents)
+            .ok()
+            .and_then(|config| config.get("num_hidden_layers")?.as_u64());
+        match self {
+            EmbeddingModels::Text(TextEmbeddingModels::MiniLMV6) => layers == Some(6),
+            EmbeddingModels::Text(TextEmbeddingModels::MiniLMV12) => layers == Some(12),
+            EmbeddingModels::Image(ImageEmbeddingModels::CLIP) => true,
+        }
+    }
+
+    /// Every embedding model that can be downloaded.
+    pub fn all() -> Vec<EmbeddingModels> {
+        vec![
+            EmbeddingModels::Text(TextEmbeddingModels::MiniLMV6),
+            EmbeddingModels::Text(TextEmbeddingModels::MiniLMV12),
+            EmbeddingModels::Image(ImageEmbeddingModels::CLIP)
+        ]
+    }
+
     /// Returns the list of files required for each embedding model
     pub fn required_files(&self) -> &'static [&'static str] {
         match self {
@@ -5,13 +5,16 @@ pub fn init() {
 }
 
 pub mod agent;
+pub mod config;
 pub mod model;
 pub mod types;
 pub mod llm;
 pub mod tools;
 pub mod chain;
 pub use model::manager::ModelManager;
+pub use config::PyanoConfig;
 pub mod embedding;
 pub mod vectorstore;
 pub mod schemas;
+pub mod telemetry;
 pub use types::*;
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::fs::{ File, OpenOptions };
+use std::io::Write;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
+use log::warn;
+use serde::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
+
+use super::cache::answer_settings;
+use super::completion::Completion;
+use super::error::LLMError;
+use super::options::LLMHTTPCallOptions;
+
+/// One prompt of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchRequest {
+    pub prompt: String,
+    pub system_prompt: String,
+}
+
+impl BatchRequest {
+    pub fn new(prompt: &str, system_prompt: &str) -> Self {
+        Self { prompt: prompt.to_string(), system_prompt: system_prompt.to_string() }
+    }
+
+    /// Identifies the request in a checkpoint, so a changed input, model or
+    /// option isn't mistaken for one that was already answered.
+    pub fn fingerprint(&self, model: &str, options: &LLMHTTPCallOptions) -> String {
+        let mut material = answer_settings(model, options);
+        material["prompt_template"] = serde_json::json!(options.prompt_template);
+        material["system_prompt"] = serde_json::json!(self.system_prompt);
+        material["prompt"] = serde_json::json!(self.prompt);
+        format!("{:x}", Sha256::digest(material.to_string().as_bytes()))
+    }
+}
+
+/// Where a batch stands, passed to the progress callback after every request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchProgress {
+    pub total: usize,
+    /// Requests answered, including those restored from the checkpoint
+    pub completed: usize,
+    pub failed: usize,
+    /// Requests answered in an earlier run
+    pub resumed: usize,
+}
+
+type ProgressFn = Arc<dyn Fn(&BatchProgress) + Send + Sync>;
+
+/// How `LLM::batch_with` runs a batch.
+#[derive(Clone, Default)]
+pub struct BatchOptions {
+    /// Requests in flight at once; 0 uses every slot of every replica
+    pub concurrency: usize,
+    /// JSONL file results are appended to; requests answered there are skipped
+    pub checkpoint: Option<PathBuf>,
+    pub on_progress: Option<ProgressFn>,
+}
+
+impl BatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(path.into());
+        self
+    }
+
+    pub fn with_progress<F>(mut self, on_progress: F) -> Self
+        where F: Fn(&BatchProgress) + Send + Sync + 'static
+    {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+}
+
+/// A line of the checkpoint file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CheckpointEntry {
+    index: usize,
+    fingerprint: String,
+    #[serde(default)]
+    completion: Option<Completion>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Results of a batch, appended to a JSONL file as they arrive. Only
+/// successful answers are restored; failed requests are retried.
+pub(crate) struct Checkpoint {
+    file: Option<Mutex<File>>,
+}
+
+impl Checkpoint {
+    /// A checkpoint that records nothing.
+    pub(crate) fn disabled() -> Self {
+        Self { file: None }
+    }
+
+    /// Opens `path` and returns the completions it holds for the requests
+    /// with these fingerprints, by index. Fails when an entry was made for a
+    /// different request.
+    pub(crate) fn open(
+        path: &Path,
+        fingerprints: &[String]
+    ) -> Result<(Self, HashMap<usize, Completion>), LLMError> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(checkpoint_error(path, e));
+            }
+        };
+        let mut done = HashMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A run that was killed mid-write leaves a partial last line
+            let Ok(entry) = serde_json::from_str::<CheckpointEntry>(line) else {
+                warn!("Skipping unreadable line {} of {}", number + 1, path.display());
+                continue;
+            };
+            let matches = fingerprints.get(entry.index) == Some(&entry.fingerprint);
+            if !matches {
+                let message = format!("entry for request {} doesn't match the input", entry.index);
+                return Err(checkpoint_error(path, message));
+            }
+            if let Some(completion) = entry.completion {
+                done.insert(entry.index, completion);
+            }
+        }
+
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).map_err(|e| checkpoint_error(path, e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| checkpoint_error(path, e))?;
+        // Start on a fresh line after a partial one
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            writeln!(file).map_err(|e| checkpoint_error(path, e))?;
+        }
+        Ok((Self { file: Some(Mutex::new(file)) }, done))
+    }
+
+    pub(crate) fn record(
+        &self,
+        index: usize,
+        fingerprint: &str,
+        result: &Result<Completion, LLMError>
+    ) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let entry = CheckpointEntry {
+            index,
+            fingerprint: fingerprint.to_string(),
+            completion: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        let written = serde_json::to_string(&entry).map_err(|e| e.to_string()).and_then(|line| {
+            let mut file = file.lock().unwrap();
+            writeln!(file, "{}", line).and_then(|_| file.flush()).map_err(|e| e.to_string())
+        });
+        if let Err(e) = written {
+            warn!("Cannot write checkpoint entry for request {}: {}", index, e);
+        }
+    }
+}
+
+fn checkpoint_error(path: &Path, error: impl std::fmt::Display) -> LLMError {
+    LLMError::Checkpoint(format!("{}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let path = std::env::temp_dir().join(format!("pyano-batch-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let options = LLMHTTPCallOptions::new().with_temperature(0.0);
+        let fingerprints = |requests: &[BatchRequest], model: &str, options: &LLMHTTPCallOptions| {
+            requests
+                .iter()
+                .map(|request| request.fingerprint(model, options))
+                .collect::<Vec<_>>()
+        };
+        let requests = vec![BatchRequest::new("a", "sys"), BatchRequest::new("b", "sys")];
+        let prints = fingerprints(&requests, "qwen", &options);
+
+        let (checkpoint, done) = Checkpoint::open(&path, &prints).unwrap();
+        assert!(done.is_empty());
+        let completion = Completion::from_response(json!({ "content": "A" }));
+        checkpoint.record(0, &prints[0], &Ok(completion));
+        checkpoint.record(1, &prints[1], &Err(LLMError::RequestFailed("down".to_string())));
+        drop(checkpoint);
+
+        // Only the successful answer is restored
+        let (_, done) = Checkpoint::open(&path, &prints).unwrap();
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[&0].content, "A");
+
+        // A different input, model or option is rejected
+        let changed = vec![BatchRequest::new("c", "sys"), BatchRequest::new("b", "sys")];
+        assert!(Checkpoint::open(&path, &fingerprints(&changed, "qwen", &options)).is_err());
+        assert!(Checkpoint::open(&path, &fingerprints(&requests, "llama", &options)).is_err());
+        let longer = options.clone().with_max_tokens(64);
+        assert!(Checkpoint::open(&path, &fingerprints(&requests, "qwen", &longer)).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
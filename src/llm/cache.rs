@@ -0,0 +1,284 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{ Duration, Instant, SystemTime, UNIX_EPOCH };
+use log::warn;
+use rusqlite::{ params, Connection, OptionalExtension };
+use sha2::{ Digest, Sha256 };
+
+use super::completion::Completion;
+use super::options::LLMHTTPCallOptions;
+
+/// Stores completions of deterministic requests so repeated runs of the same
+/// prompt skip the model. Entries older than the cache's TTL are misses.
+pub trait ResponseCache: Send + Sync {
+    fn get(&self, key: &str) -> Option<Completion>;
+    fn put(&self, key: &str, completion: &Completion);
+    fn clear(&self);
+}
+
+/// The cache key of a request: a hash of the model, the rendered prompt and
+/// every option that changes the answer. None when sampling isn't
+/// deterministic (temperature above 0 without a fixed seed), so the request
+/// always reaches the model.
+pub fn cache_key(model: &str, prompt: &str, options: &LLMHTTPCallOptions) -> Option<String> {
+    let greedy = options.temperature.map_or(false, |temperature| temperature <= 0.0);
+    if !greedy && options.seed.is_none() {
+        return None;
+    }
+
+    let mut material = answer_settings(model, options);
+    material["prompt"] = serde_json::json!(prompt);
+    Some(format!("{:x}", Sha256::digest(material.to_string().as_bytes())))
+}
+
+/// The model and every option that changes its answer, hashed into cache keys
+/// and batch checkpoint fingerprints.
+pub(crate) fn answer_settings(model: &str, options: &LLMHTTPCallOptions) -> serde_json::Value {
+    serde_json::json!({
+        "model": model,
+        "temperature": options.temperature,
+        "top_k": options.top_k,
+        "top_p": options.top_p,
+        "seed": options.seed,
+        "max_tokens": options.max_tokens,
+        "min_length": options.min_length,
+        "max_length": options.max_length,
+        "repetition_penalty": options.repetition_penalty,
+        "stop_words": options.stop_words,
+        "lora": options.lora,
+        "n_probs": options.n_probs,
+        "context_policy": options.context_policy.map(|policy| format!("{:?}", policy)),
+    })
+}
+
+struct MemoryEntry {
+    completion: Completion,
+    stored: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    entries: HashMap<String, MemoryEntry>,
+    // Keys by last use, oldest first
+    order: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+/// An in-memory cache that drops the least recently used entry when full.
+pub struct MemoryCache {
+    capacity: usize,
+    ttl: Option<Duration>,
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), ttl: None, state: Mutex::new(MemoryState::default()) }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ResponseCache for MemoryCache {
+    fn get(&self, key: &str) -> Option<Completion> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let entry = state.entries.get_mut(key)?;
+        if self.ttl.map_or(false, |ttl| entry.stored.elapsed() > ttl) {
+            state.order.remove(&entry.last_used);
+            state.entries.remove(key);
+            return None;
+        }
+        state.clock += 1;
+        state.order.remove(&entry.last_used);
+        entry.last_used = state.clock;
+        state.order.insert(state.clock, key.to_string());
+        Some(entry.completion.clone())
+    }
+
+    fn put(&self, key: &str, completion: &Completion) {
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let last_used = state.clock;
+        let entry = MemoryEntry { completion: completion.clone(), stored: Instant::now(), last_used };
+        if let Some(previous) = state.entries.insert(key.to_string(), entry) {
+            state.order.remove(&previous.last_used);
+        }
+        state.order.insert(last_used, key.to_string());
+
+        while state.entries.len() > self.capacity {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+        }
+    }
+
+    fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.order.clear();
+    }
+}
+
+/// A cache kept in a SQLite database, so answers survive restarts.
+pub struct SqliteCache {
+    conn: Mutex<Connection>,
+    ttl: Option<Duration>,
+}
+
+impl SqliteCache {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                warn!("Cannot create {}: {}", parent.display(), e);
+            }
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS response_cache (
+                key TEXT PRIMARY KEY,
+                completion TEXT NOT NULL,
+                stored_at INTEGER NOT NULL
+            )"
+        )?;
+        Ok(Self { conn: Mutex::new(conn), ttl: None })
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Deletes entries older than the TTL.
+    pub fn purge_expired(&self) -> rusqlite::Result<usize> {
+        let Some(ttl) = self.ttl else {
+            return Ok(0);
+        };
+        let cutoff = now_secs() - (ttl.as_secs() as i64);
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM response_cache WHERE stored_at < ?1", params![cutoff])
+    }
+}
+
+impl ResponseCache for SqliteCache {
+    fn get(&self, key: &str) -> Option<Completion> {
+        let conn = self.conn.lock().unwrap();
+        let row: Option<(String, i64)> = conn
+            .query_row(
+                "SELECT completion, stored_at FROM response_cache WHERE key = ?1",
+                params![key],
+                |row| Ok((row.get(0)?, row.get(1)?))
+            )
+            .optional()
+            .unwrap_or_else(|e| {
+                warn!("Response cache lookup failed: {}", e);
+                None
+            });
+        let (completion, stored_at) = row?;
+        if self.ttl.map_or(false, |ttl| now_secs() - stored_at > (ttl.as_secs() as i64)) {
+            let _ = conn.execute("DELETE FROM response_cache WHERE key = ?1", params![key]);
+            return None;
+        }
+        serde_json::from_str(&completion).ok()
+    }
+
+    fn put(&self, key: &str, completion: &Completion) {
+        let Ok(json) = serde_json::to_string(completion) else {
+            return;
+        };
+        let stored = self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO response_cache (key, completion, stored_at) VALUES (?1, ?2, ?3)",
+                params![key, json, now_secs()]
+            );
+        if let Err(e) = stored {
+            warn!("Cannot store response in cache: {}", e);
+        }
+    }
+
+    fn clear(&self) {
+        if let Err(e) = self.conn.lock().unwrap().execute("DELETE FROM response_cache", []) {
+            warn!("Cannot clear response cache: {}", e);
+        }
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn completion(content: &str) -> Completion {
+        Completion::from_response(json!({ "content": content }))
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let greedy = LLMHTTPCallOptions::new().with_temperature(0.0);
+        let key = cache_key("qwen", "Hi", &greedy).unwrap();
+        assert_eq!(cache_key("qwen", "Hi", &greedy), Some(key.clone()));
+        assert_ne!(cache_key("llama", "Hi", &greedy), Some(key.clone()));
+        assert_ne!(cache_key("qwen", "Hi", &greedy.clone().with_top_k(5)), Some(key));
+
+        // Sampling without a seed is never cached
+        let sampled = LLMHTTPCallOptions::new().with_temperature(0.7);
+        assert_eq!(cache_key("qwen", "Hi", &sampled), None);
+        assert!(cache_key("qwen", "Hi", &sampled.with_seed(42)).is_some());
+    }
+
+    #[test]
+    fn test_memory_cache_lru_and_ttl() {
+        let cache = MemoryCache::new(2);
+        cache.put("a", &completion("A"));
+        cache.put("b", &completion("B"));
+        // Reading "a" makes "b" the least recently used
+        assert_eq!(cache.get("a").unwrap().content, "A");
+        cache.put("c", &completion("C"));
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.len(), 2);
+
+        let expiring = MemoryCache::new(2).with_ttl(Duration::ZERO);
+        expiring.put("a", &completion("A"));
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(expiring.get("a").is_none());
+        assert!(expiring.is_empty());
+    }
+
+    #[test]
+    fn test_sqlite_cache() {
+        let path = std::env::temp_dir().join(format!("pyano-cache-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let cache = SqliteCache::open(&path).unwrap();
+            cache.put("a", &completion("A"));
+        }
+        let cache = SqliteCache::open(&path).unwrap();
+        assert_eq!(cache.get("a").unwrap().content, "A");
+        cache.clear();
+        assert!(cache.get("a").is_none());
+        drop(cache);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
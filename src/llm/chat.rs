@@ -0,0 +1,131 @@
+use std::path::Path;
+use serde::{ Deserialize, Serialize };
+
+/// One exchange of a chat.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub user: String,
+    pub assistant: String,
+}
+
+/// A multi-turn conversation that can be rendered into a single completion
+/// prompt with a model's `{system_prompt}`/`{user_prompt}` template and saved
+/// to or loaded from a JSON session file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatSession {
+    pub model: String,
+    pub system_prompt: String,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub turns: Vec<ChatTurn>,
+}
+
+impl ChatSession {
+    pub fn new(model: &str, system_prompt: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            system_prompt: system_prompt.to_string(),
+            temperature: None,
+            turns: Vec::new(),
+        }
+    }
+
+    pub fn push_turn(&mut self, user: &str, assistant: &str) {
+        self.turns.push(ChatTurn { user: user.to_string(), assistant: assistant.to_string() });
+    }
+
+    pub fn reset(&mut self) {
+        self.turns.clear();
+    }
+
+    /// The whole conversation followed by `user`, ready to be completed by the
+    /// model. Earlier turns reuse the template's own separators: the text
+    /// between `{system_prompt}` and `{user_prompt}` opens each user message,
+    /// and the text after `{user_prompt}` opens each answer. Templates that do
+    /// not have both placeholders in that order fall back to a plain transcript.
+    pub fn render(&self, template: &str, user: &str) -> String {
+        let parts = template
+            .split_once("{system_prompt}")
+            .and_then(|(head, rest)| {
+                rest.split_once("{user_prompt}").map(|(between, tail)| (head, between, tail))
+            });
+        let Some((head, between, tail)) = parts else {
+            return self.render_transcript(user);
+        };
+
+        let mut prompt = format!("{}{}", head, self.system_prompt);
+        for turn in &self.turns {
+            prompt.push_str(between);
+            prompt.push_str(&turn.user);
+            prompt.push_str(tail);
+            prompt.push_str(&turn.assistant);
+        }
+        prompt.push_str(between);
+        prompt.push_str(user);
+        prompt.push_str(tail);
+        prompt
+    }
+
+    fn render_transcript(&self, user: &str) -> String {
+        let mut prompt = format!("{}\n\n", self.system_prompt);
+        for turn in &self.turns {
+            prompt.push_str(&format!("User: {}\nAssistant: {}\n", turn.user, turn.assistant));
+        }
+        prompt.push_str(&format!("User: {}\nAssistant:", user));
+        prompt
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHATML: &str =
+        "<|im_start|>system\n{system_prompt}<|im_end|>\n<|im_start|>user\n{user_prompt}<|im_end|>\n<|im_start|>assistant\n";
+
+    #[test]
+    fn test_render_multi_turn() {
+        let mut session = ChatSession::new("qwen", "Be brief.");
+        assert_eq!(
+            session.render(CHATML, "Hi"),
+            CHATML.replace("{system_prompt}", "Be brief.").replace("{user_prompt}", "Hi")
+        );
+
+        session.push_turn("Hi", "Hello!");
+        assert_eq!(
+            session.render(CHATML, "How are you?"),
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\nHello!<|im_end|>\n<|im_start|>user\nHow are you?<|im_end|>\n<|im_start|>assistant\n"
+        );
+
+        // No system placeholder: plain transcript
+        assert_eq!(
+            session.render("{user_prompt}", "Bye"),
+            "Be brief.\n\nUser: Hi\nAssistant: Hello!\nUser: Bye\nAssistant:"
+        );
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("pyano-chat-{}.json", std::process::id()));
+        let mut session = ChatSession::new("qwen", "Be brief.");
+        session.temperature = Some(0.2);
+        session.push_turn("Hi", "Hello!");
+
+        session.save(&path).unwrap();
+        assert_eq!(ChatSession::load(&path).unwrap(), session);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
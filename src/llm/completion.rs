@@ -0,0 +1,233 @@
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use super::context::ContextReport;
+
+/// Why generation stopped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// The model produced its end-of-sequence token
+    Eos,
+    /// `max_tokens` was reached
+    Limit,
+    /// One of the stop words was produced
+    Word(String),
+    Unknown,
+}
+
+/// A candidate for one generated token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenAlternative {
+    /// Token id, when the server reports it
+    pub id: Option<u32>,
+    pub token: String,
+    pub logprob: f32,
+}
+
+/// A generated token with its log-probability and the most likely
+/// alternatives the server considered (`n_probs` of them).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenProbability {
+    pub id: Option<u32>,
+    pub token: String,
+    pub logprob: f32,
+    pub top: Vec<TokenAlternative>,
+}
+
+impl TokenProbability {
+    pub fn probability(&self) -> f32 {
+        self.logprob.exp()
+    }
+}
+
+/// The result of `LLM::response`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Completion {
+    pub content: String,
+    /// Ids of the generated tokens
+    pub tokens: Vec<u32>,
+    /// Per-token probabilities, filled when `n_probs` was requested
+    pub probabilities: Vec<TokenProbability>,
+    pub stop_reason: StopReason,
+    pub tokens_predicted: usize,
+    pub tokens_evaluated: usize,
+    /// What the context policy did to the prompt, if it had to shorten it
+    pub context: Option<ContextReport>,
+    /// The response as llama-server sent it
+    pub raw: Value,
+}
+
+impl Completion {
+    /// Reads a `/completion` response. Both the `completion_probabilities`
+    /// layout of current llama-server builds (`logprob`, `top_logprobs`) and
+    /// the older one (`content`, `probs` with `tok_str`/`prob`) are understood.
+    pub fn from_response(raw: Value) -> Self {
+        let probabilities: Vec<TokenProbability> = raw
+            .get("completion_probabilities")
+            .and_then(Value::as_array)
+            .map(|entries| entries.iter().filter_map(parse_token_probability).collect())
+            .unwrap_or_default();
+        let tokens = match raw.get("tokens").and_then(Value::as_array) {
+            Some(tokens) if !tokens.is_empty() => {
+                tokens
+                    .iter()
+                    .filter_map(|token| token.as_u64().map(|id| id as u32))
+                    .collect()
+            }
+            _ => probabilities.iter().filter_map(|probability| probability.id).collect(),
+        };
+
+        Self {
+            content: string_field(&raw, "content"),
+            tokens,
+            probabilities,
+            stop_reason: stop_reason(&raw),
+            tokens_predicted: count_field(&raw, "tokens_predicted"),
+            tokens_evaluated: count_field(&raw, "tokens_evaluated"),
+            context: None,
+            raw,
+        }
+    }
+}
+
+fn string_field(value: &Value, key: &str) -> String {
+    value.get(key).and_then(Value::as_str).unwrap_or_default().to_string()
+}
+
+fn count_field(value: &Value, key: &str) -> usize {
+    value.get(key).and_then(Value::as_u64).unwrap_or_default() as usize
+}
+
+fn stop_reason(raw: &Value) -> StopReason {
+    let stopping_word = string_field(raw, "stopping_word");
+    match raw.get("stop_type").and_then(Value::as_str) {
+        Some("eos") => StopReason::Eos,
+        Some("limit") => StopReason::Limit,
+        Some("word") => StopReason::Word(stopping_word),
+        Some(_) => StopReason::Unknown,
+        // Builds before stop_type reported flags
+        None => {
+            let flag = |key: &str| raw.get(key).and_then(Value::as_bool).unwrap_or(false);
+            if flag("stopped_eos") {
+                StopReason::Eos
+            } else if flag("stopped_limit") {
+                StopReason::Limit
+            } else if flag("stopped_word") {
+                StopReason::Word(stopping_word)
+            } else {
+                StopReason::Unknown
+            }
+        }
+    }
+}
+
+fn parse_token_probability(entry: &Value) -> Option<TokenProbability> {
+    if let Some(logprob) = entry.get("logprob").and_then(Value::as_f64) {
+        let top = entry
+            .get("top_logprobs")
+            .and_then(Value::as_array)
+            .map(|alternatives| {
+                alternatives
+                    .iter()
+                    .filter_map(|alternative| {
+                        Some(TokenAlternative {
+                            id: token_id(alternative),
+                            token: string_field(alternative, "token"),
+                            logprob: alternative.get("logprob")?.as_f64()? as f32,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        return Some(TokenProbability {
+            id: token_id(entry),
+            token: string_field(entry, "token"),
+            logprob: logprob as f32,
+            top,
+        });
+    }
+
+    // Older layout: probabilities only for the alternatives, the chosen token among them
+    let token = entry.get("content")?.as_str()?.to_string();
+    let top: Vec<TokenAlternative> = entry
+        .get("probs")?
+        .as_array()?
+        .iter()
+        .filter_map(|alternative| {
+            Some(TokenAlternative {
+                id: None,
+                token: string_field(alternative, "tok_str"),
+                logprob: (alternative.get("prob")?.as_f64()? as f32).ln(),
+            })
+        })
+        .collect();
+    let logprob = top
+        .iter()
+        .find(|alternative| alternative.token == token)
+        .map_or(f32::NEG_INFINITY, |alternative| alternative.logprob);
+    Some(TokenProbability { id: None, token, logprob, top })
+}
+
+fn token_id(value: &Value) -> Option<u32> {
+    value
+        .get("id")
+        .and_then(Value::as_u64)
+        .map(|id| id as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_completion() {
+        let completion = Completion::from_response(
+            json!({
+                "content": "Yes",
+                "tokens": [9454],
+                "stop_type": "eos",
+                "stopping_word": "",
+                "tokens_predicted": 1,
+                "tokens_evaluated": 42,
+                "completion_probabilities": [{
+                    "id": 9454,
+                    "token": "Yes",
+                    "logprob": -0.1,
+                    "top_logprobs": [
+                        { "id": 9454, "token": "Yes", "logprob": -0.1 },
+                        { "id": 2753, "token": "No", "logprob": -2.4 }
+                    ]
+                }]
+            })
+        );
+        assert_eq!(completion.content, "Yes");
+        assert_eq!(completion.tokens, vec![9454]);
+        assert_eq!(completion.stop_reason, StopReason::Eos);
+        assert_eq!(completion.tokens_evaluated, 42);
+        let first = &completion.probabilities[0];
+        assert!((first.probability() - (-0.1f32).exp()).abs() < 1e-6);
+        assert_eq!(first.top[1].token, "No");
+    }
+
+    #[test]
+    fn test_parse_legacy_probabilities() {
+        let completion = Completion::from_response(
+            json!({
+                "content": "No",
+                "stopped_word": true,
+                "stopping_word": "\n",
+                "completion_probabilities": [{
+                    "content": "No",
+                    "probs": [
+                        { "tok_str": "No", "prob": 0.75 },
+                        { "tok_str": "Yes", "prob": 0.25 }
+                    ]
+                }]
+            })
+        );
+        assert_eq!(completion.stop_reason, StopReason::Word("\n".to_string()));
+        assert!(completion.tokens.is_empty());
+        assert!((completion.probabilities[0].probability() - 0.75).abs() < 1e-6);
+    }
+}
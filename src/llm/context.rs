@@ -0,0 +1,226 @@
+use std::ops::Range;
+use serde::{ Deserialize, Serialize };
+use crate::model::ModelConfig;
+
+/// Tokens a prompt may use on a model's server: one slot's share of
+/// `ctx_size`, less the tokens reserved for the answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextBudget {
+    /// Context of one request slot
+    pub ctx_size: usize,
+    /// Tokens kept free for generation (`max_tokens`)
+    pub reserved: usize,
+}
+
+impl ContextBudget {
+    pub fn new(ctx_size: usize, reserved: usize) -> Self {
+        Self { ctx_size, reserved }
+    }
+
+    /// The budget of a request to `config`'s server. llama-server splits
+    /// `ctx_size` evenly between its `parallel` slots.
+    pub fn for_config(config: &ModelConfig, max_tokens: usize) -> Self {
+        let slots = config.server_config.parallel.max(1);
+        Self::new(config.server_config.ctx_size / slots, max_tokens)
+    }
+
+    pub fn available(&self) -> usize {
+        self.ctx_size.saturating_sub(self.reserved)
+    }
+
+    /// Tokens by which a prompt of `prompt_tokens` exceeds the budget.
+    pub fn overflow(&self, prompt_tokens: usize) -> usize {
+        prompt_tokens.saturating_sub(self.available())
+    }
+}
+
+/// What to do when a prompt does not fit its budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowAction {
+    /// Log a warning and send everything
+    Warn,
+    /// Drop the oldest history, then cut documents from the last one
+    Truncate,
+}
+
+/// Which parts of a prompt to keep, in tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FitPlan {
+    /// Number of oldest history entries dropped
+    pub history_dropped: usize,
+    /// Tokens kept of each document, 0 when dropped
+    pub document_tokens: Vec<usize>,
+    /// Tokens still over the budget after dropping everything that can be
+    pub overflow: usize,
+}
+
+/// Plans how to fit `fixed` tokens (template, system prompt and question,
+/// never dropped) plus history entries (oldest first) and documents (most
+/// relevant first) into `available` tokens. History goes first so retrieved
+/// context survives longest; documents are cut from the least relevant.
+pub fn plan_fit(available: usize, fixed: usize, history: &[usize], documents: &[usize]) -> FitPlan {
+    let mut total = fixed + history.iter().sum::<usize>() + documents.iter().sum::<usize>();
+
+    let mut history_dropped = 0;
+    while total > available && history_dropped < history.len() {
+        total -= history[history_dropped];
+        history_dropped += 1;
+    }
+
+    let mut document_tokens = documents.to_vec();
+    for kept in document_tokens.iter_mut().rev() {
+        if total <= available {
+            break;
+        }
+        let cut = (total - available).min(*kept);
+        *kept -= cut;
+        total -= cut;
+    }
+
+    FitPlan { history_dropped, document_tokens, overflow: total.saturating_sub(available) }
+}
+
+/// A prompt's history and documents after fitting them into the context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FittedContext {
+    pub history: Vec<String>,
+    pub documents: Vec<String>,
+    /// Tokens of the fitted prompt
+    pub prompt_tokens: usize,
+    /// Tokens removed from history and documents
+    pub dropped_tokens: usize,
+    /// Tokens the prompt still exceeds the budget by
+    pub overflow: usize,
+}
+
+/// How `LLM` handles a prompt longer than its context. The policy applies to
+/// the longer of the system and user prompts; the template and the other
+/// prompt are always sent whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextPolicy {
+    /// Drop the beginning, keeping the most recent text
+    TruncateHead,
+    /// Drop the end, keeping the opening text
+    TruncateTail,
+    /// Keep the first `keep_start` tokens and as much of the end as fits
+    SlidingWindow {
+        keep_start: usize,
+    },
+    /// Summarize the text that doesn't fit with the same model and send the
+    /// summary in its place
+    SummarizeOverflow,
+    /// Send the request once per part of the text, then answer from the
+    /// partial answers
+    MapReduce,
+}
+
+impl ContextPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContextPolicy::TruncateHead => "truncate_head",
+            ContextPolicy::TruncateTail => "truncate_tail",
+            ContextPolicy::SlidingWindow { .. } => "sliding_window",
+            ContextPolicy::SummarizeOverflow => "summarize_overflow",
+            ContextPolicy::MapReduce => "map_reduce",
+        }
+    }
+}
+
+/// What a context policy did to a request, returned in `Completion::context`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContextReport {
+    pub policy: String,
+    /// Tokens of the prompt as given
+    pub original_tokens: usize,
+    /// Tokens the prompt could use
+    pub available_tokens: usize,
+    /// Tokens left out of the request
+    pub dropped_tokens: usize,
+    /// Tokens replaced by a summary
+    #[serde(default)]
+    pub summarized_tokens: usize,
+    /// Parts the text was split into for summaries or map-reduce
+    #[serde(default)]
+    pub chunks: usize,
+}
+
+/// Token ranges a truncating policy keeps of `len` tokens when only `keep`
+/// fit, in order. Empty for policies that don't truncate.
+pub fn kept_ranges(policy: ContextPolicy, len: usize, keep: usize) -> Vec<Range<usize>> {
+    let keep = keep.min(len);
+    match policy {
+        ContextPolicy::TruncateHead => vec![len - keep..len],
+        ContextPolicy::TruncateTail => vec![0..keep],
+        ContextPolicy::SlidingWindow { keep_start } => {
+            let head = keep_start.min(keep);
+            let tail = keep - head;
+            if tail == 0 {
+                vec![0..head]
+            } else {
+                vec![0..head, len - tail..len]
+            }
+        }
+        ContextPolicy::SummarizeOverflow | ContextPolicy::MapReduce => Vec::new(),
+    }
+}
+
+/// Consecutive ranges of at most `size` covering `len` tokens.
+pub fn chunk_ranges(len: usize, size: usize) -> Vec<Range<usize>> {
+    let size = size.max(1);
+    (0..len).step_by(size).map(|start| start..(start + size).min(len)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_per_slot() {
+        let mut config = ModelConfig::default();
+        config.server_config.ctx_size = 8192;
+        config.server_config.parallel = 2;
+        let budget = ContextBudget::for_config(&config, 1024);
+        assert_eq!(budget.available(), 3072);
+        assert_eq!(budget.overflow(3000), 0);
+        assert_eq!(budget.overflow(3100), 28);
+    }
+
+    #[test]
+    fn test_plan_fit() {
+        // Fits as is
+        let plan = plan_fit(100, 10, &[20, 20], &[30]);
+        assert_eq!(plan, FitPlan { history_dropped: 0, document_tokens: vec![30], overflow: 0 });
+
+        // Oldest history goes first
+        let plan = plan_fit(70, 10, &[20, 20], &[30]);
+        assert_eq!(plan, FitPlan { history_dropped: 1, document_tokens: vec![30], overflow: 0 });
+
+        // Then the last documents are cut
+        let plan = plan_fit(50, 10, &[20, 20], &[30, 30]);
+        assert_eq!(plan.history_dropped, 2);
+        assert_eq!(plan.document_tokens, vec![30, 10]);
+
+        // The fixed part alone is too long
+        let plan = plan_fit(5, 10, &[20], &[30]);
+        assert_eq!(plan, FitPlan { history_dropped: 1, document_tokens: vec![0], overflow: 5 });
+    }
+
+    #[test]
+    fn test_kept_ranges() {
+        assert_eq!(kept_ranges(ContextPolicy::TruncateHead, 100, 30), vec![70..100]);
+        assert_eq!(kept_ranges(ContextPolicy::TruncateTail, 100, 30), vec![0..30]);
+        assert_eq!(
+            kept_ranges(ContextPolicy::SlidingWindow { keep_start: 10 }, 100, 30),
+            vec![0..10, 80..100]
+        );
+        let window = ContextPolicy::SlidingWindow { keep_start: 50 };
+        assert_eq!(kept_ranges(window, 100, 30), vec![0..30]);
+        assert!(kept_ranges(ContextPolicy::MapReduce, 100, 30).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_ranges() {
+        assert_eq!(chunk_ranges(10, 4), vec![0..4, 4..8, 8..10]);
+        assert!(chunk_ranges(0, 4).is_empty());
+    }
+}
@@ -6,4 +6,21 @@ pub enum LLMError {
     #[error("Server unavailable: {0}")] ServerUnavailable(String),
     #[error("Request failed: {0}")] RequestFailed(String),
     #[error("Unexpected error: {0}")] Unexpected(String),
+    #[error("Model has no LoRA adapter named {0}")] UnknownLoraAdapter(String),
+    #[error("Slot {slot} does not exist, the server has {parallel} slot(s)")] InvalidSlot {
+        slot: usize,
+        parallel: usize,
+    },
+    #[error("Batch checkpoint {0}")] Checkpoint(String),
+    #[error("Prompt needs {needed} tokens but only {available} fit in the context")] ContextOverflow {
+        needed: usize,
+        available: usize,
+    },
+    #[error(
+        "Timed out after {waited:?} waiting for a free slot on model {model} ({queued_ahead} request(s) still ahead)"
+    )] QueueTimeout {
+        model: String,
+        waited: std::time::Duration,
+        queued_ahead: usize,
+    },
 }
@@ -1,15 +1,43 @@
 use crate::model::error::ModelError;
 use crate::model::{ ModelManagerInterface, ModelStatus };
-use log::{ debug, error };
+use log::{ debug, error, warn };
 use super::{ options::LLMHTTPCallOptions, error::LLMError };
 use std::error::Error as StdError; // Importing the correct trait
 use std::pin::Pin;
 use bytes::Bytes;
-use futures::Stream;
+use futures::{ Stream, StreamExt };
 use log::info; // Ensure StreamExt is imported
 use std::sync::Arc;
 use crate::model::state::ModelState;
+use crate::model::LoraAdapter;
+use std::collections::{ BTreeMap, HashMap };
+use super::types::{ LoraAdapterStatus, SlotRestored, SlotSaved };
+use super::completion::Completion;
+use super::batch::{ BatchOptions, BatchProgress, BatchRequest, Checkpoint };
+use super::cache::{ cache_key, ResponseCache };
+use super::context::{
+    chunk_ranges,
+    kept_ranges,
+    plan_fit,
+    ContextBudget,
+    ContextPolicy,
+    ContextReport,
+    FittedContext,
+    OverflowAction,
+};
+use crate::model::requests::InFlightGuard;
+use crate::model::queue::{ RequestQueue, SlotPermit };
+use crate::model::replica::{ ReplicaLease, ReplicaPool };
+use crate::model::metrics::Metrics;
+use super::stream_processing::{ timings_from_chunk, LLMGenerattionTimings };
 use colored::Colorize;
+use tracing::{ field::Empty, Instrument, Span };
+
+// Tokens kept free when fitting text, for markers and re-tokenization drift
+const CONTEXT_MARGIN: usize = 16;
+
+const SUMMARY_PROMPT: &str =
+    "Summarize the following text. Keep every fact, name and number that could matter later.";
 
 #[derive(Clone)]
 pub struct LLM {
@@ -28,6 +56,9 @@ pub struct LLM {
     model_manager: Option<Arc<dyn ModelManagerInterface>>,
     model_name: Option<String>,
     auto_load: bool,
+    request_queue: Option<Arc<RequestQueue>>,
+    replica_pool: Option<Arc<ReplicaPool>>,
+    response_cache: Option<Arc<dyn ResponseCache>>,
 }
 
 impl LLM {
@@ -35,6 +66,25 @@ impl LLM {
         LLMBuilder::default()
     }
 
+    /// The call options requests are sent with.
+    pub fn options(&self) -> &LLMHTTPCallOptions {
+        &self.options
+    }
+
+    /// A copy of this LLM sending requests with different options, e.g. a
+    /// new temperature or prompt template.
+    pub fn with_options(mut self, options: LLMHTTPCallOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// A copy of this LLM that answers repeated deterministic requests from
+    /// `cache`. Only `response` uses the cache, streams always reach the model.
+    pub fn with_response_cache(mut self, cache: Arc<dyn ResponseCache>) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
     pub async fn load(self) {
         let manager = self.model_manager.unwrap();
         // Load the model
@@ -63,14 +113,13 @@ impl LLM {
         }
     }
 
-    async fn prepare_request(
+    /// The `/completion` body for a prompt with the current options.
+    fn request_payload(
         &self,
         prompt_with_context: &str,
         system_prompt: &str,
         stream: bool
-    ) -> Result<reqwest::Response, Box<dyn StdError + Send + Sync + 'static>> {
-        let server_url = self.state.server_url.as_ref();
-
+    ) -> Result<serde_json::Value, LLMError> {
         let prompt_template = self.options.prompt_template
             .as_ref()
             .expect("Prompt template is missing");
@@ -83,6 +132,10 @@ impl LLM {
         json_payload.insert("prompt".to_string(), serde_json::Value::String(full_prompt));
         json_payload.insert("stream".to_string(), serde_json::Value::Bool(stream));
         json_payload.insert("cache_prompt".to_string(), serde_json::Value::Bool(true));
+        if !stream {
+            // Token ids for `Completion::tokens`; streamed chunks would carry them unused
+            json_payload.insert("return_tokens".to_string(), serde_json::Value::Bool(true));
+        }
 
         if let Some(temperature) = self.options.temperature {
             json_payload.insert(
@@ -120,6 +173,12 @@ impl LLM {
                 serde_json::Value::Number(serde_json::Number::from(max_length as i64))
             );
         }
+        if let Some(max_tokens) = self.options.max_tokens {
+            json_payload.insert(
+                "n_predict".to_string(),
+                serde_json::Value::Number(serde_json::Number::from(max_tokens as i64))
+            );
+        }
         if let Some(repetition_penalty) = self.options.repetition_penalty {
             json_payload.insert(
                 "repetition_penalty".to_string(),
@@ -128,24 +187,493 @@ impl LLM {
                 )
             );
         }
+        if let Some(lora) = &self.options.lora {
+            json_payload.insert(
+                "lora".to_string(),
+                lora_payload(&self.state.config.server_config.lora_adapters, lora)?
+            );
+        }
 
+        if let Some(n_probs) = self.options.n_probs {
+            json_payload.insert("n_probs".to_string(), serde_json::Value::from(n_probs));
+        }
+        if let Some(slot) = self.options.slot {
+            self.check_slot(slot)?;
+            json_payload.insert("id_slot".to_string(), serde_json::Value::from(slot));
+        }
+
+        Ok(serde_json::Value::Object(json_payload))
+    }
+
+    /// The replicas a request may go to, or None to use the model's server.
+    fn request_pool(&self) -> Option<&Arc<ReplicaPool>> {
+        // Slot ids belong to one server, so slot-bound requests skip the other replicas
+        self.replica_pool.as_ref().filter(|pool| pool.len() > 1 && self.options.slot.is_none())
+    }
+
+    /// Sends the completion request. With several replicas it goes to the least
+    /// busy one and fails over to the others when a replica is unreachable or
+    /// answers with a server error; the returned lease keeps it counted as busy.
+    async fn prepare_request(
+        &self,
+        prompt_with_context: &str,
+        system_prompt: &str,
+        stream: bool
+    ) -> Result<
+        (reqwest::Response, Option<ReplicaLease>),
+        Box<dyn StdError + Send + Sync + 'static>
+    > {
+        let server_url = self.state.server_url.as_ref();
+        let payload = self.request_payload(prompt_with_context, system_prompt, stream)?;
+        let pool = self.request_pool();
+        let mut tried = Vec::new();
+
+        loop {
+            let lease = pool.and_then(|pool| pool.pick(&tried));
+            let base_url = match &lease {
+                Some(lease) => lease.url().to_string(),
+                None => server_url.lock().unwrap().clone().unwrap(),
+            };
+
+            let (err, retryable) = match
+                self.client.post(&format!("{}/completion", base_url)).json(&payload).send().await
+            {
+                Ok(resp) =>
+                    match resp.error_for_status() {
+                        Ok(resp) => {
+                            if let Some(lease) = &lease {
+                                lease.mark_healthy();
+                                Span::current().record("replica", lease.url());
+                            }
+                            return Ok((resp, lease));
+                        }
+                        Err(e) if e.status().map_or(false, |status| status.is_server_error()) => {
+                            (LLMError::ServerUnavailable(e.to_string()), true)
+                        }
+                        Err(e) => (LLMError::RequestFailed(e.to_string()), false),
+                    }
+                Err(e) => {
+                    let retryable = e.is_connect() || e.is_timeout();
+                    (LLMError::RequestFailed(e.to_string()), retryable)
+                }
+            };
+
+            let Some(lease) = lease.filter(|_| retryable) else {
+                return Err(Box::new(err));
+            };
+            lease.mark_failed();
+            tried.push(lease.index());
+            if tried.len() >= pool.map_or(0, |pool| pool.len()) {
+                return Err(Box::new(err));
+            }
+            warn!("Replica {} failed ({}), retrying on another replica", lease.url(), err);
+        }
+    }
+
+    /// The LoRA adapters loaded on the model's server with their current scales.
+    pub async fn lora_adapters(
+        &self
+    ) -> Result<Vec<LoraAdapterStatus>, Box<dyn StdError + Send + Sync + 'static>> {
         let resp = self.client
-            .post(&format!("{}/completion", server_url.lock().unwrap().as_ref().unwrap()))
-            .json(&serde_json::Value::Object(json_payload))
-            .send().await
-            .map_err(|e| LLMError::RequestFailed(e.to_string()))?
-            .error_for_status()
-            .map_err(|e| {
-                if e.status().map_or(false, |status| status.is_server_error()) {
-                    LLMError::ServerUnavailable(e.to_string())
-                } else {
-                    LLMError::RequestFailed(e.to_string())
+            .get(&format!("{}/lora-adapters", self.base_url()?))
+            .send().await?
+            .error_for_status()?;
+        Ok(resp.json().await?)
+    }
+
+    /// Sets the adapter scales used by requests that don't select adapters
+    /// themselves, on every replica. Adapters not in `scales` are disabled.
+    pub async fn set_lora_scales(
+        &self,
+        scales: &BTreeMap<String, f32>
+    ) -> Result<(), Box<dyn StdError + Send + Sync + 'static>> {
+        let payload = lora_payload(&self.state.config.server_config.lora_adapters, scales)?;
+        let urls: Vec<String> = match self.replica_pool.as_ref().filter(|pool| !pool.is_empty()) {
+            Some(pool) => pool.stats().into_iter().map(|replica| replica.url).collect(),
+            None => self.state.server_url.lock().unwrap().clone().into_iter().collect(),
+        };
+        if urls.is_empty() {
+            let error = LLMError::ServerUnavailable("Model server is not running".to_string());
+            return Err(Box::new(error));
+        }
+        for url in urls {
+            self.client
+                .post(&format!("{}/lora-adapters", url))
+                .json(&payload)
+                .send().await?
+                .error_for_status()?;
+        }
+        Ok(())
+    }
+
+    /// Answers every request with at most `concurrency` in flight, 0 for one
+    /// per server slot. Results are in request order, each with its own error.
+    pub async fn batch(
+        &self,
+        requests: Vec<BatchRequest>,
+        concurrency: usize
+    ) -> Vec<Result<Completion, LLMError>> {
+        let options = BatchOptions::new().with_concurrency(concurrency);
+        // Without a checkpoint there is nothing that can fail as a whole
+        self.batch_with(requests, options).await.unwrap_or_default()
+    }
+
+    /// Like `batch`, with progress reporting and a checkpoint file that lets
+    /// an interrupted batch resume where it stopped. Fails only when the
+    /// checkpoint can't be opened or was written for different requests.
+    pub async fn batch_with(
+        &self,
+        requests: Vec<BatchRequest>,
+        options: BatchOptions
+    ) -> Result<Vec<Result<Completion, LLMError>>, LLMError> {
+        let model = self.metrics_model();
+        let fingerprints: Vec<String> = requests
+            .iter()
+            .map(|request| request.fingerprint(&model, &self.options))
+            .collect();
+        let (checkpoint, done) = match &options.checkpoint {
+            Some(path) => Checkpoint::open(path, &fingerprints)?,
+            None => (Checkpoint::disabled(), HashMap::new()),
+        };
+        let concurrency = match options.concurrency {
+            0 => {
+                let server = &self.state.config.server_config;
+                server.parallel.max(1) * server.replicas.max(1)
+            }
+            concurrency => concurrency,
+        };
+
+        let mut progress = BatchProgress {
+            total: requests.len(),
+            completed: done.len(),
+            failed: 0,
+            resumed: done.len(),
+        };
+        if let Some(on_progress) = &options.on_progress {
+            on_progress(&progress);
+        }
+
+        let mut results: Vec<Option<Result<Completion, LLMError>>> = (0..requests.len())
+            .map(|index| done.get(&index).cloned().map(Ok))
+            .collect();
+        let pending: Vec<usize> = (0..requests.len())
+            .filter(|index| results[*index].is_none())
+            .collect();
+
+        let mut answers = futures::stream
+            ::iter(pending)
+            .map(|index| {
+                let request = &requests[index];
+                async move {
+                    let result = self
+                        .response(&request.prompt, &request.system_prompt).await
+                        .map_err(|e| {
+                            // Keep typed errors such as QueueTimeout for the caller
+                            e.downcast::<LLMError>()
+                                .map(|e| *e)
+                                .unwrap_or_else(|e| LLMError::RequestFailed(e.to_string()))
+                        });
+                    (index, result)
                 }
-            })?;
+            })
+            .buffer_unordered(concurrency);
+
+        while let Some((index, result)) = answers.next().await {
+            checkpoint.record(index, &fingerprints[index], &result);
+            progress.completed += 1;
+            if result.is_err() {
+                progress.failed += 1;
+            }
+            if let Some(on_progress) = &options.on_progress {
+                on_progress(&progress);
+            }
+            results[index] = Some(result);
+        }
+
+        Ok(results.into_iter().map(|result| result.expect("every request is answered")).collect())
+    }
+
+    /// A copy of this LLM whose requests all run in server slot `slot`, so a
+    /// conversation keeps reusing the prompt cache of its earlier turns.
+    pub fn bound_to_slot(self, slot: usize) -> Self {
+        let options = self.options.clone().with_slot(slot);
+        self.with_options(options)
+    }
+
+    /// Processes the template up to the user prompt, with `system_prompt`
+    /// filled in, in slot `slot` without generating anything. Later requests
+    /// in that slot starting with the same system prompt skip reprocessing it.
+    pub async fn prefill(
+        &self,
+        slot: usize,
+        system_prompt: &str
+    ) -> Result<(), Box<dyn StdError + Send + Sync + 'static>> {
+        self.check_slot(slot)?;
+        let template = self.options.prompt_template.clone().unwrap_or_default();
+        let prefix = template
+            .split("{user_prompt}")
+            .next()
+            .unwrap_or_default()
+            .replace("{system_prompt}", system_prompt);
+
+        // Counted as in flight so the model isn't evicted during a long prefill
+        let _guard = self.begin_request();
+        self.ensure_model_loaded().await?;
+        let _permit = self.acquire_slot().await?;
+        self.client
+            .post(&format!("{}/completion", self.base_url()?))
+            .json(
+                &serde_json::json!({
+                    "prompt": prefix,
+                    "n_predict": 0,
+                    "id_slot": slot,
+                    "cache_prompt": true,
+                })
+            )
+            .send().await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// Saves the KV cache of slot `slot` as `filename` in the server's
+    /// `slot_save_dir`.
+    pub async fn save_slot(
+        &self,
+        slot: usize,
+        filename: &str
+    ) -> Result<SlotSaved, Box<dyn StdError + Send + Sync + 'static>> {
+        let resp = self.slot_action(slot, "save", Some(filename)).await?;
+        Ok(serde_json::from_value(resp)?)
+    }
+
+    /// Loads a KV cache saved with `save_slot` into slot `slot`.
+    pub async fn restore_slot(
+        &self,
+        slot: usize,
+        filename: &str
+    ) -> Result<SlotRestored, Box<dyn StdError + Send + Sync + 'static>> {
+        let resp = self.slot_action(slot, "restore", Some(filename)).await?;
+        Ok(serde_json::from_value(resp)?)
+    }
+
+    pub async fn erase_slot(
+        &self,
+        slot: usize
+    ) -> Result<(), Box<dyn StdError + Send + Sync + 'static>> {
+        self.slot_action(slot, "erase", None).await?;
+        Ok(())
+    }
+
+    /// Restores `filename` into slot `slot`, or prefills the slot with
+    /// `system_prompt` and saves it there when it can't be restored, e.g. on
+    /// the first run. Returns whether the cache was restored.
+    pub async fn restore_or_prefill(
+        &self,
+        slot: usize,
+        system_prompt: &str,
+        filename: &str
+    ) -> Result<bool, Box<dyn StdError + Send + Sync + 'static>> {
+        // Held across the restore, prefill and save so the cache isn't lost in between
+        let _guard = self.begin_request();
+        self.ensure_model_loaded().await?;
+        match self.restore_slot(slot, filename).await {
+            Ok(restored) => {
+                info!("Restored {} cached tokens into slot {}", restored.n_restored, slot);
+                Ok(true)
+            }
+            Err(e) => {
+                debug!("Cannot restore slot {} from {}: {}", slot, filename, e);
+                self.prefill(slot, system_prompt).await?;
+                let saved = self.save_slot(slot, filename).await?;
+                info!("Prefilled slot {} and saved {} tokens to {}", slot, saved.n_saved, filename);
+                Ok(false)
+            }
+        }
+    }
+
+    async fn slot_action(
+        &self,
+        slot: usize,
+        action: &str,
+        filename: Option<&str>
+    ) -> Result<serde_json::Value, Box<dyn StdError + Send + Sync + 'static>> {
+        self.check_slot(slot)?;
+        let _guard = self.begin_request();
+        self.ensure_model_loaded().await?;
+        let body = match filename {
+            Some(filename) => serde_json::json!({ "filename": filename }),
+            None => serde_json::json!({}),
+        };
+        let resp = self.client
+            .post(&format!("{}/slots/{}?action={}", self.base_url()?, slot, action))
+            .json(&body)
+            .send().await?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            let message = format!("Slot {} {}: {} {}", action, slot, status, text);
+            return Err(Box::new(LLMError::RequestFailed(message)));
+        }
+        Ok(resp.json().await?)
+    }
+
+    fn check_slot(&self, slot: usize) -> Result<(), LLMError> {
+        let parallel = self.state.config.server_config.parallel.max(1);
+        if slot >= parallel {
+            return Err(LLMError::InvalidSlot { slot, parallel });
+        }
+        Ok(())
+    }
+
+    /// The model's token ids for `text`, from llama-server's `/tokenize`.
+    pub async fn tokenize(
+        &self,
+        text: &str
+    ) -> Result<Vec<u32>, Box<dyn StdError + Send + Sync + 'static>> {
+        self.ensure_model_loaded().await?;
+        let resp: serde_json::Value = self.client
+            .post(&format!("{}/tokenize", self.base_url()?))
+            .json(&serde_json::json!({ "content": text }))
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+        let tokens = resp
+            .get("tokens")
+            .cloned()
+            .ok_or_else(|| LLMError::Unexpected("No tokens in /tokenize response".to_string()))?;
+        Ok(serde_json::from_value(tokens)?)
+    }
+
+    /// The text of `tokens`, from llama-server's `/detokenize`.
+    pub async fn detokenize(
+        &self,
+        tokens: &[u32]
+    ) -> Result<String, Box<dyn StdError + Send + Sync + 'static>> {
+        self.ensure_model_loaded().await?;
+        let resp: serde_json::Value = self.client
+            .post(&format!("{}/detokenize", self.base_url()?))
+            .json(&serde_json::json!({ "tokens": tokens }))
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+        let content = resp
+            .get("content")
+            .and_then(|content| content.as_str())
+            .ok_or_else(|| LLMError::Unexpected("No content in /detokenize response".to_string()))?;
+        Ok(content.to_string())
+    }
+
+    pub async fn count_tokens(
+        &self,
+        text: &str
+    ) -> Result<usize, Box<dyn StdError + Send + Sync + 'static>> {
+        Ok(self.tokenize(text).await?.len())
+    }
+
+    /// The tokens a prompt may use: one slot's context less `max_tokens`,
+    /// taken from the options or else the model's defaults.
+    pub fn context_budget(&self) -> ContextBudget {
+        let max_tokens = self.options.max_tokens.unwrap_or_else(|| {
+            *self.state.max_tokens.lock().unwrap()
+        });
+        ContextBudget::for_config(&self.state.config, max_tokens)
+    }
+
+    /// Fits conversation `history` (oldest first) and `documents` (most
+    /// relevant first) into the context left by the templated system prompt
+    /// and question. With `OverflowAction::Truncate` the oldest history is
+    /// dropped first, then documents are cut from the last one; with `Warn`
+    /// everything is kept and an overflow is only logged.
+    pub async fn fit_context(
+        &self,
+        system_prompt: &str,
+        question: &str,
+        history: Vec<String>,
+        documents: Vec<String>,
+        action: OverflowAction
+    ) -> Result<FittedContext, Box<dyn StdError + Send + Sync + 'static>> {
+        let template = self.options.prompt_template.clone().unwrap_or_default();
+        let fixed = template
+            .replace("{system_prompt}", system_prompt)
+            .replace("{user_prompt}", question);
+        let fixed_tokens = self.count_tokens(&fixed).await?;
+        let mut history_tokens = Vec::with_capacity(history.len());
+        for entry in &history {
+            history_tokens.push(self.count_tokens(entry).await?);
+        }
+        let mut document_tokens = Vec::with_capacity(documents.len());
+        for document in &documents {
+            document_tokens.push(self.tokenize(document).await?);
+        }
+        let document_counts: Vec<usize> = document_tokens.iter().map(Vec::len).collect();
+        let total =
+            fixed_tokens +
+            history_tokens.iter().sum::<usize>() +
+            document_counts.iter().sum::<usize>();
 
-        Ok(resp)
+        let budget = self.context_budget();
+        let overflow = budget.overflow(total);
+        if overflow == 0 || action == OverflowAction::Warn {
+            if overflow > 0 {
+                warn!(
+                    "Prompt for {} needs {} tokens but only {} are available",
+                    self.metrics_model(),
+                    total,
+                    budget.available()
+                );
+            }
+            return Ok(FittedContext {
+                history,
+                documents,
+                prompt_tokens: total,
+                dropped_tokens: 0,
+                overflow,
+            });
+        }
+
+        let plan = plan_fit(budget.available(), fixed_tokens, &history_tokens, &document_counts);
+        let mut fitted_documents = Vec::new();
+        for ((document, tokens), keep) in documents
+            .into_iter()
+            .zip(&document_tokens)
+            .zip(&plan.document_tokens) {
+            if *keep == tokens.len() {
+                fitted_documents.push(document);
+            } else if *keep > 0 {
+                fitted_documents.push(self.detokenize(&tokens[..*keep]).await?);
+            }
+        }
+        let prompt_tokens =
+            fixed_tokens +
+            history_tokens[plan.history_dropped..].iter().sum::<usize>() +
+            plan.document_tokens.iter().sum::<usize>();
+        if plan.overflow > 0 {
+            warn!(
+                "Prompt for {} is {} tokens over its context even without history and documents",
+                self.metrics_model(),
+                plan.overflow
+            );
+        }
+        Ok(FittedContext {
+            history: history.into_iter().skip(plan.history_dropped).collect(),
+            documents: fitted_documents,
+            prompt_tokens,
+            dropped_tokens: total - prompt_tokens,
+            overflow: plan.overflow,
+        })
+    }
+
+    /// The URL of the model's server; any replica tokenizes the same way.
+    fn base_url(&self) -> Result<String, LLMError> {
+        self.state.server_url
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| LLMError::ServerUnavailable("Model server is not running".to_string()))
     }
 
+    /// Streams the completion of the prompt. A prompt longer than the context
+    /// is first handled by the options' `context_policy`, if any.
     pub async fn response_stream(
         &self,
         prompt_with_context: &str,
@@ -154,32 +682,358 @@ impl LLM {
         Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
         Box<dyn StdError + Send + Sync + 'static>
     > {
-        self.ensure_model_loaded().await?;
+        let Some(policy) = self.options.context_policy else {
+            return self.stream_completion(prompt_with_context, system_prompt).await;
+        };
+        let ((prompt, system), report) = self.apply_context_policy(
+            policy,
+            prompt_with_context,
+            system_prompt
+        ).await?;
+        // A stream has no response body to report in
+        if let Some(report) = report {
+            warn!("Prompt for {} exceeded its context: {:?}", self.metrics_model(), report);
+        }
+        self.stream_completion(&prompt, &system).await
+    }
+
+    /// Completes the prompt. When the options' `context_policy` had to shorten
+    /// it, what was done is reported in `Completion::context`. With a response
+    /// cache, deterministic requests answered before are served from it.
+    pub async fn response(
+        &self,
+        prompt_with_context: &str,
+        system_prompt: &str
+    ) -> Result<Completion, Box<dyn StdError + Send + Sync + 'static>> {
+        let cached = self.response_cache.as_ref().and_then(|cache| {
+            let template = self.options.prompt_template.clone().unwrap_or_default();
+            let rendered = template
+                .replace("{system_prompt}", system_prompt)
+                .replace("{user_prompt}", prompt_with_context);
+            cache_key(&self.metrics_model(), &rendered, &self.options).map(|key| (cache, key))
+        });
+        if let Some((cache, key)) = &cached {
+            if let Some(completion) = cache.get(key) {
+                debug!("Response cache hit for {}", self.metrics_model());
+                return Ok(completion);
+            }
+        }
 
-        let resp = self.prepare_request(prompt_with_context, system_prompt, true).await?;
+        let completion = self.respond(prompt_with_context, system_prompt).await?;
+        if let Some((cache, key)) = &cached {
+            cache.put(key, &completion);
+        }
+        Ok(completion)
+    }
 
-        let stream = resp.bytes_stream();
+    async fn respond(
+        &self,
+        prompt_with_context: &str,
+        system_prompt: &str
+    ) -> Result<Completion, Box<dyn StdError + Send + Sync + 'static>> {
+        let Some(policy) = self.options.context_policy else {
+            let response = self.complete(prompt_with_context, system_prompt).await?;
+            return Ok(Completion::from_response(response));
+        };
+        let ((prompt, system), report) = self.apply_context_policy(
+            policy,
+            prompt_with_context,
+            system_prompt
+        ).await?;
+        let mut completion = Completion::from_response(self.complete(&prompt, &system).await?);
+        completion.context = report;
+        Ok(completion)
+    }
+
+    /// Shortens the longer of the two prompts with `policy` when the rendered
+    /// prompt doesn't fit the context. Returns the prompts to send, in the
+    /// order they were given, and a report when anything changed.
+    async fn apply_context_policy(
+        &self,
+        policy: ContextPolicy,
+        prompt_with_context: &str,
+        system_prompt: &str
+    ) -> Result<
+        ((String, String), Option<ContextReport>),
+        Box<dyn StdError + Send + Sync + 'static>
+    > {
+        let available = self.context_budget().available();
+        let template = self.options.prompt_template.clone().unwrap_or_default();
+        let rendered = template
+            .replace("{system_prompt}", system_prompt)
+            .replace("{user_prompt}", prompt_with_context);
+        let total = self.count_tokens(&rendered).await?;
+        if total <= available {
+            return Ok(((prompt_with_context.to_string(), system_prompt.to_string()), None));
+        }
+
+        let system_is_long = system_prompt.len() > prompt_with_context.len();
+        let long = if system_is_long { system_prompt } else { prompt_with_context };
+        let tokens = self.tokenize(long).await?;
+        // The template and the other prompt are always sent whole
+        let fixed = total.saturating_sub(tokens.len());
+        let room = available.saturating_sub(fixed + CONTEXT_MARGIN);
+        if room == 0 {
+            return Err(Box::new(LLMError::ContextOverflow { needed: fixed, available }));
+        }
+
+        let mut report = ContextReport {
+            policy: policy.name().to_string(),
+            original_tokens: total,
+            available_tokens: available,
+            ..ContextReport::default()
+        };
+        let shortened = match policy {
+            ContextPolicy::SummarizeOverflow => {
+                self.summarize_overflow(&tokens, room, &mut report).await?
+            }
+            ContextPolicy::MapReduce => {
+                let (prompt, system) = (prompt_with_context, system_prompt);
+                let ask = |part: &str| {
+                    if system_is_long {
+                        (prompt.to_string(), part.to_string())
+                    } else {
+                        (part.to_string(), system.to_string())
+                    }
+                };
+                self.map_reduce(&tokens, room, ask, &mut report).await?
+            }
+            _ => {
+                let ranges = kept_ranges(policy, tokens.len(), room);
+                let mut parts = Vec::with_capacity(ranges.len());
+                for range in &ranges {
+                    parts.push(self.detokenize(&tokens[range.clone()]).await?);
+                }
+                let kept: usize = ranges.iter().map(|range| range.len()).sum();
+                report.dropped_tokens = tokens.len() - kept;
+                parts.join("\n...\n")
+            }
+        };
+
+        let prompts = if system_is_long {
+            (prompt_with_context.to_string(), shortened)
+        } else {
+            (shortened, system_prompt.to_string())
+        };
+        Ok((prompts, Some(report)))
+    }
+
+    /// Keeps the end of the text verbatim in half of `room` and replaces the
+    /// rest with summaries, one per part that fits a summarization request.
+    /// Summaries that still don't fit are cut from the end.
+    async fn summarize_overflow(
+        &self,
+        tokens: &[u32],
+        room: usize,
+        report: &mut ContextReport
+    ) -> Result<String, Box<dyn StdError + Send + Sync + 'static>> {
+        let tail = room / 2;
+        let overflow = &tokens[..tokens.len() - tail];
+        let ranges = chunk_ranges(overflow.len(), self.context_budget().available() / 2);
+        let summary_tokens = ((room - tail) / ranges.len().max(1)).max(CONTEXT_MARGIN);
+        let summarizer = self.without_context_policy(Some(summary_tokens));
+
+        let mut summaries = Vec::with_capacity(ranges.len());
+        for range in &ranges {
+            let part = self.detokenize(&overflow[range.clone()]).await?;
+            let response = summarizer.complete(&part, SUMMARY_PROMPT).await?;
+            summaries.push(Completion::from_response(response).content);
+        }
+        report.summarized_tokens = overflow.len();
+        report.chunks = ranges.len();
+
+        let kept = self.detokenize(&tokens[tokens.len() - tail..]).await?;
+        let summary = format!("Summary of the earlier text:\n{}\n\n", summaries.join("\n"));
+        // Summaries can run past the tokens they were given, so measure the result
+        let shortened = format!("{}{}", summary, kept);
+        let needed = self.count_tokens(&shortened).await?;
+        if needed <= room {
+            return Ok(shortened);
+        }
+        let summary_room = room.saturating_sub(self.count_tokens(&kept).await?);
+        if summary_room == 0 {
+            return Err(Box::new(LLMError::ContextOverflow { needed, available: room }));
+        }
+        let summary_tokens = self.tokenize(&summary).await?;
+        let summary_room = summary_room.min(summary_tokens.len());
+        report.dropped_tokens = summary_tokens.len() - summary_room;
+        let summary = self.detokenize(&summary_tokens[..summary_room]).await?;
+        Ok(format!("{}\n\n{}", summary.trim_end(), kept))
+    }
+
+    /// Sends the request once per part of the text that fits in `room`, with
+    /// `ask` building the prompts around a part, and returns the partial
+    /// answers to send in the text's place.
+    async fn map_reduce(
+        &self,
+        tokens: &[u32],
+        room: usize,
+        ask: impl Fn(&str) -> (String, String),
+        report: &mut ContextReport
+    ) -> Result<String, Box<dyn StdError + Send + Sync + 'static>> {
+        let ranges = chunk_ranges(tokens.len(), room);
+        let mapper = self.without_context_policy(None);
+
+        let mut answers = Vec::with_capacity(ranges.len());
+        for (i, range) in ranges.iter().enumerate() {
+            let part = self.detokenize(&tokens[range.clone()]).await?;
+            let (prompt, system) = ask(&part);
+            let answer = Completion::from_response(mapper.complete(&prompt, &system).await?).content;
+            answers.push(format!("Part {}: {}", i + 1, answer.trim()));
+        }
+        report.chunks = ranges.len();
+
+        let combined = format!(
+            "The text was too long to read at once. Answers based on each of its {} parts:\n\n{}",
+            ranges.len(),
+            answers.join("\n\n")
+        );
+        // The final request has to fit as well
+        let combined_tokens = self.tokenize(&combined).await?;
+        if combined_tokens.len() <= room {
+            return Ok(combined);
+        }
+        report.dropped_tokens = combined_tokens.len() - room;
+        Ok(self.detokenize(&combined_tokens[..room]).await?)
+    }
+
+    /// A copy for the extra requests a context policy makes, which must not
+    /// apply the policy again.
+    fn without_context_policy(&self, max_tokens: Option<usize>) -> LLM {
+        let mut options = self.options.clone();
+        options.context_policy = None;
+        if max_tokens.is_some() {
+            options.max_tokens = max_tokens;
+        }
+        self.clone().with_options(options)
+    }
+
+    async fn stream_completion(
+        &self,
+        prompt_with_context: &str,
+        system_prompt: &str
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+        Box<dyn StdError + Send + Sync + 'static>
+    > {
+        // Registered before loading so the model can't be evicted in between
+        let guard = self.begin_request();
+        let mut timer = Metrics::global().start_request(&self.metrics_model());
+        let span = self.request_span(prompt_with_context, system_prompt, true);
+        let started = std::time::Instant::now();
+
+        let (resp, permit, lease) = (async {
+            self.ensure_model_loaded().await?;
+            let permit = self.acquire_slot().await?;
+            let (resp, lease) = self.prepare_request(prompt_with_context, system_prompt, true).await?;
+            Ok::<_, Box<dyn StdError + Send + Sync>>((resp, permit, lease))
+        })
+            .instrument(span.clone()).await?;
+        timer.succeed();
+
+        // The span stays open until the stream ends with llama-server's timings
+        let model = self.metrics_model();
+        let stream = resp.bytes_stream().inspect(move |chunk| {
+            let timings = chunk
+                .as_ref()
+                .ok()
+                .and_then(|chunk| std::str::from_utf8(chunk).ok())
+                .and_then(timings_from_chunk);
+            if let Some(timings) = timings {
+                record_timings(&model, &timings, &span, started);
+            }
+        });
         let processed_stream = if let Some(process_fn) = &self.process_response {
             process_fn(Box::pin(stream))
         } else {
             Box::pin(stream)
         };
 
-        Ok(processed_stream)
+        // The request stays in flight until the caller drops the stream
+        Ok(
+            Box::pin(
+                processed_stream.map(move |chunk| {
+                    let _ = (&guard, &permit, &lease, &timer);
+                    chunk
+                })
+            )
+        )
     }
 
-    pub async fn response(
+    async fn complete(
         &self,
         prompt_with_context: &str,
         system_prompt: &str
     ) -> Result<serde_json::Value, Box<dyn StdError + Send + Sync + 'static>> {
-        self.ensure_model_loaded().await?;
+        let _guard = self.begin_request();
+        let mut timer = Metrics::global().start_request(&self.metrics_model());
+        let span = self.request_span(prompt_with_context, system_prompt, false);
+        let started = std::time::Instant::now();
 
-        let resp = self.prepare_request(prompt_with_context, system_prompt, false).await?;
-        let response_json = resp.json::<serde_json::Value>().await?;
+        let response_json = (async {
+            self.ensure_model_loaded().await?;
+            let _permit = self.acquire_slot().await?;
+            let (resp, _lease) = self.prepare_request(prompt_with_context, system_prompt, false).await?;
+            Ok::<_, Box<dyn StdError + Send + Sync>>(resp.json::<serde_json::Value>().await?)
+        })
+            .instrument(span.clone()).await?;
+        timer.succeed();
+
+        let timings = response_json
+            .get("timings")
+            .and_then(|timings| serde_json::from_value::<LLMGenerattionTimings>(timings.clone()).ok());
+        match timings {
+            Some(timings) => record_timings(&self.metrics_model(), &timings, &span, started),
+            None => {
+                span.record("latency_ms", started.elapsed().as_millis() as u64);
+            }
+        }
         Ok(response_json)
     }
 
+    fn request_span(&self, prompt_with_context: &str, system_prompt: &str, stream: bool) -> Span {
+        tracing::info_span!(
+            "llm.request",
+            model = %self.metrics_model(),
+            stream,
+            prompt_chars = prompt_with_context.len() + system_prompt.len(),
+            replica = Empty,
+            prompt_tokens = Empty,
+            predicted_tokens = Empty,
+            tokens_per_second = Empty,
+            latency_ms = Empty
+        )
+    }
+
+    // Requests are reported under the registry name when there is one
+    fn metrics_model(&self) -> String {
+        self.model_name.clone().unwrap_or_else(|| self.state.config.model_config.name.clone())
+    }
+
+    fn begin_request(&self) -> Option<InFlightGuard> {
+        match (&self.model_manager, &self.model_name) {
+            (Some(manager), Some(name)) => manager.begin_request(name),
+            _ => None,
+        }
+    }
+
+    /// Waits for a free request slot on the model's server, if it has a queue.
+    async fn acquire_slot(&self) -> Result<Option<SlotPermit>, LLMError> {
+        let Some(queue) = &self.request_queue else {
+            return Ok(None);
+        };
+        let priority = self.options.priority.unwrap_or(0);
+        match queue.acquire(priority, self.options.queue_timeout).await {
+            Ok(permit) => Ok(Some(permit)),
+            Err(timeout) =>
+                Err(LLMError::QueueTimeout {
+                    model: self.model_name.clone().unwrap_or_default(),
+                    waited: timeout.waited,
+                    queued_ahead: timeout.queued_ahead,
+                }),
+        }
+    }
+
     async fn ensure_model_loaded(&self) -> Result<(), Box<dyn StdError + Send + Sync>> {
         debug!("Checking model status");
         if let (Some(manager), Some(name)) = (&self.model_manager, &self.model_name) {
@@ -230,6 +1084,47 @@ impl LLM {
     }
 }
 
+/// The `[{"id", "scale"}]` list llama-server expects, covering every adapter
+/// of the model so that unselected ones are turned off.
+fn lora_payload(
+    adapters: &[LoraAdapter],
+    scales: &BTreeMap<String, f32>
+) -> Result<serde_json::Value, LLMError> {
+    if let Some(unknown) = scales.keys().find(|name| !adapters.iter().any(|a| &a.name == *name)) {
+        return Err(LLMError::UnknownLoraAdapter(unknown.clone()));
+    }
+    Ok(
+        serde_json::Value::Array(
+            adapters
+                .iter()
+                .enumerate()
+                .map(|(id, adapter)| {
+                    let scale = scales.get(&adapter.name).copied().unwrap_or(0.0);
+                    serde_json::json!({ "id": id, "scale": scale })
+                })
+                .collect()
+        )
+    )
+}
+
+fn record_timings(
+    model: &str,
+    timings: &LLMGenerattionTimings,
+    span: &Span,
+    started: std::time::Instant
+) {
+    Metrics::global().record_timings(
+        model,
+        timings.prompt_n as u64,
+        timings.predicted_n as u64,
+        timings.predicted_per_second
+    );
+    span.record("prompt_tokens", timings.prompt_n as u64);
+    span.record("predicted_tokens", timings.predicted_n as u64);
+    span.record("tokens_per_second", timings.predicted_per_second);
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+}
+
 pub struct LLMBuilder {
     state: ModelState,
     options: LLMHTTPCallOptions,
@@ -246,6 +1141,9 @@ pub struct LLMBuilder {
     model_manager: Option<Arc<dyn ModelManagerInterface>>,
     model_name: Option<String>,
     auto_load: bool,
+    request_queue: Option<Arc<RequestQueue>>,
+    replica_pool: Option<Arc<ReplicaPool>>,
+    response_cache: Option<Arc<dyn ResponseCache>>,
 }
 
 impl Default for LLMBuilder {
@@ -257,6 +1155,9 @@ impl Default for LLMBuilder {
             auto_load: false,
             model_manager: None,
             model_name: None,
+            request_queue: None,
+            replica_pool: None,
+            response_cache: None,
         }
     }
 }
@@ -274,6 +1175,24 @@ impl LLMBuilder {
         self
     }
 
+    /// Shares a request queue between every `LLM` talking to the same server.
+    pub fn with_request_queue(mut self, queue: Arc<RequestQueue>) -> Self {
+        self.request_queue = Some(queue);
+        self
+    }
+
+    /// Balances requests over the replicas of the model's server.
+    pub fn with_replica_pool(mut self, pool: Arc<ReplicaPool>) -> Self {
+        self.replica_pool = Some(pool);
+        self
+    }
+
+    /// Answers repeated deterministic requests from `cache`.
+    pub fn with_response_cache(mut self, cache: Arc<dyn ResponseCache>) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
     pub fn with_state(mut self, state: ModelState) -> Self {
         self.state = state;
         self
@@ -306,6 +1225,91 @@ impl LLMBuilder {
             model_manager: self.model_manager,
             model_name: self.model_name,
             auto_load: self.auto_load,
+            request_queue: self.request_queue,
+            replica_pool: self.replica_pool,
+            response_cache: self.response_cache,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn adapter(name: &str) -> LoraAdapter {
+        LoraAdapter {
+            name: name.to_string(),
+            path: PathBuf::from(format!("qwen/{}.gguf", name)),
+            url: None,
+            sha256: None,
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_lora_payload() {
+        let adapters = vec![adapter("sql"), adapter("legal")];
+        let scales = BTreeMap::from([("legal".to_string(), 0.5)]);
+        assert_eq!(
+            lora_payload(&adapters, &scales).unwrap(),
+            serde_json::json!([{ "id": 0, "scale": 0.0 }, { "id": 1, "scale": 0.5 }])
+        );
+
+        let unknown = BTreeMap::from([("medical".to_string(), 1.0)]);
+        let error = lora_payload(&adapters, &unknown).unwrap_err();
+        assert!(matches!(error, LLMError::UnknownLoraAdapter(name) if name == "medical"));
+    }
+
+    fn slotted_llm(parallel: usize, slot: Option<usize>) -> LLM {
+        let mut config = crate::model::ModelConfig::default();
+        config.server_config.parallel = parallel;
+        let mut options = LLMHTTPCallOptions::new()
+            .with_port(8001)
+            .with_prompt_template("{user_prompt}".to_string());
+        if let Some(slot) = slot {
+            options = options.with_slot(slot);
+        }
+        let pool = ReplicaPool::new(
+            vec!["http://localhost:8001".to_string(), "http://localhost:8002".to_string()]
+        );
+        LLM::builder()
+            .with_state(ModelState::new(config))
+            .with_options(options)
+            .with_replica_pool(Arc::new(pool))
+            .build()
+    }
+
+    #[test]
+    fn test_return_tokens_only_without_stream() {
+        let llm = slotted_llm(1, None);
+        assert_eq!(llm.request_payload("Hi", "", false).unwrap()["return_tokens"], true);
+        assert!(llm.request_payload("Hi", "", true).unwrap().get("return_tokens").is_none());
+    }
+
+    #[test]
+    fn test_check_slot() {
+        let llm = slotted_llm(2, None);
+        assert!(llm.check_slot(1).is_ok());
+        let error = llm.check_slot(2).unwrap_err();
+        assert!(matches!(error, LLMError::InvalidSlot { slot: 2, parallel: 2 }));
+    }
+
+    #[test]
+    fn test_slot_payload_and_replicas() {
+        let unbound = slotted_llm(2, None);
+        let payload = unbound.request_payload("Hi", "", false).unwrap();
+        assert!(payload.get("id_slot").is_none());
+        assert!(unbound.request_pool().is_some());
+
+        // Slot-bound requests name the slot and stay on the model's own server
+        let bound = slotted_llm(2, Some(1));
+        let payload = bound.request_payload("Hi", "", false).unwrap();
+        assert_eq!(payload["id_slot"], 1);
+        assert!(bound.request_pool().is_none());
+
+        let missing = slotted_llm(2, Some(2));
+        let error = missing.request_payload("Hi", "", false).unwrap_err();
+        assert!(matches!(error, LLMError::InvalidSlot { slot: 2, parallel: 2 }));
+    }
+}
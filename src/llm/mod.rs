@@ -3,3 +3,8 @@ pub mod options;
 pub mod llm_builder;
 pub mod error;
 pub mod stream_processing;
+pub mod chat;
+pub mod context;
+pub mod completion;
+pub mod batch;
+pub mod cache;
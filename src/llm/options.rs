@@ -1,3 +1,7 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use super::context::ContextPolicy;
+
 pub struct LLMServerOptions {
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
@@ -24,6 +28,18 @@ pub struct LLMHTTPCallOptions {
     pub server_url: Option<String>,
     pub prompt_template: Option<String>,
     pub port: Option<u16>,
+    /// Queue priority; higher values get a free slot first
+    pub priority: Option<i32>,
+    /// Overrides the model's `queue_timeout_secs` for this request
+    pub queue_timeout: Option<Duration>,
+    /// LoRA adapter scales by name; adapters not listed are disabled for the request
+    pub lora: Option<BTreeMap<String, f32>>,
+    /// Applied when a prompt is longer than the model's context
+    pub context_policy: Option<ContextPolicy>,
+    /// Server slot (`id_slot`) requests run in, so their prompt cache is reused
+    pub slot: Option<usize>,
+    /// Alternatives reported with the probability of each generated token
+    pub n_probs: Option<usize>,
     initialized_fields: Vec<String>,
 }
 
@@ -42,6 +58,12 @@ impl Default for LLMHTTPCallOptions {
             server_url: None,
             prompt_template: None,
             port: None,
+            priority: None,
+            queue_timeout: None,
+            lora: None,
+            context_policy: None,
+            slot: None,
+            n_probs: None,
             initialized_fields: Vec::new(),
         }
     }
@@ -126,6 +148,43 @@ impl LLMHTTPCallOptions {
         self
     }
 
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = Some(priority);
+        self.initialized_fields.push("priority".to_string());
+        self
+    }
+
+    pub fn with_queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = Some(queue_timeout);
+        self.initialized_fields.push("queue_timeout".to_string());
+        self
+    }
+
+    /// Applies the model's LoRA adapter `name` at `scale`. Can be called once per adapter.
+    pub fn with_lora(mut self, name: &str, scale: f32) -> Self {
+        self.lora.get_or_insert_with(BTreeMap::new).insert(name.to_string(), scale);
+        self.initialized_fields.push("lora".to_string());
+        self
+    }
+
+    pub fn with_context_policy(mut self, context_policy: ContextPolicy) -> Self {
+        self.context_policy = Some(context_policy);
+        self.initialized_fields.push("context_policy".to_string());
+        self
+    }
+
+    pub fn with_slot(mut self, slot: usize) -> Self {
+        self.slot = Some(slot);
+        self.initialized_fields.push("slot".to_string());
+        self
+    }
+
+    pub fn with_n_probs(mut self, n_probs: usize) -> Self {
+        self.n_probs = Some(n_probs);
+        self.initialized_fields.push("n_probs".to_string());
+        self
+    }
+
     pub fn build(mut self) -> Self {
         // Initialize only fields that have been explicitly set
         let defaults = LLMHTTPCallOptions::default();
@@ -157,6 +216,24 @@ impl LLMHTTPCallOptions {
         if !self.initialized_fields.contains(&"repetition_penalty".to_string()) {
             self.repetition_penalty = defaults.repetition_penalty;
         }
+        if !self.initialized_fields.contains(&"priority".to_string()) {
+            self.priority = defaults.priority;
+        }
+        if !self.initialized_fields.contains(&"queue_timeout".to_string()) {
+            self.queue_timeout = defaults.queue_timeout;
+        }
+        if !self.initialized_fields.contains(&"lora".to_string()) {
+            self.lora = defaults.lora;
+        }
+        if !self.initialized_fields.contains(&"context_policy".to_string()) {
+            self.context_policy = defaults.context_policy;
+        }
+        if !self.initialized_fields.contains(&"slot".to_string()) {
+            self.slot = defaults.slot;
+        }
+        if !self.initialized_fields.contains(&"n_probs".to_string()) {
+            self.n_probs = defaults.n_probs;
+        }
 
         if
             !self.initialized_fields.contains(&"server_url".to_string()) &&
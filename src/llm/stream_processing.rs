@@ -15,15 +15,26 @@ use colored::Colorize;
 
 #[allow(dead_code)]
 #[derive(Debug, Deserialize)]
-struct LLMGenerattionTimings {
-    predicted_ms: f64,
-    predicted_n: f64,
-    predicted_per_second: f64,
-    predicted_per_token_ms: f64,
-    prompt_ms: f64,
-    prompt_n: f64,
-    prompt_per_second: f64,
-    prompt_per_token_ms: f64,
+pub(crate) struct LLMGenerattionTimings {
+    pub(crate) predicted_ms: f64,
+    pub(crate) predicted_n: f64,
+    pub(crate) predicted_per_second: f64,
+    pub(crate) predicted_per_token_ms: f64,
+    pub(crate) prompt_ms: f64,
+    pub(crate) prompt_n: f64,
+    pub(crate) prompt_per_second: f64,
+    pub(crate) prompt_per_token_ms: f64,
+}
+
+/// The `timings` llama-server sends with the last chunk of a stream, if
+/// `chunk` contains it.
+pub(crate) fn timings_from_chunk(chunk: &str) -> Option<LLMGenerattionTimings> {
+    chunk
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+        .find_map(|json| json.get("timings").cloned())
+        .and_then(|timings| serde_json::from_value(timings).ok())
 }
 
 pub fn llamacpp_process_stream<'a>(stream: BoxedStream) -> BoxedStream {
@@ -2,5 +2,36 @@ use std::pin::Pin;
 use futures::Stream;
 use bytes::Bytes;
 use reqwest::Error as ReqwestError;
+use serde::{ Deserialize, Serialize };
 
 pub type AccumulatedStream = Pin<Box<dyn Stream<Item = Result<Bytes, ReqwestError>> + Send>>;
+
+/// A LoRA adapter as reported by llama-server's `/lora-adapters`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoraAdapterStatus {
+    pub id: usize,
+    pub path: String,
+    pub scale: f32,
+}
+
+/// Result of saving a slot's KV cache with `/slots/:id?action=save`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotSaved {
+    pub id_slot: usize,
+    pub filename: String,
+    /// Tokens saved
+    pub n_saved: usize,
+    /// Bytes written
+    pub n_written: usize,
+}
+
+/// Result of restoring a slot's KV cache with `/slots/:id?action=restore`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlotRestored {
+    pub id_slot: usize,
+    pub filename: String,
+    /// Tokens restored
+    pub n_restored: usize,
+    /// Bytes read
+    pub n_read: usize,
+}
@@ -1,12 +1,17 @@
 use std::path::PathBuf;
 use std::process::Command;
-use super::super::utils::get_env_var;
+use log::warn;
+use crate::config::PyanoConfig;
 use super::super::state::ModelState;
+use super::super::RopeScalingType;
+use super::super::error::{ ModelError, ModelResult };
+use super::llama_binary::{ LlamaBinary, LlamaFeature };
 
 #[derive(Debug)]
 pub(crate) struct LlamaProcess {
     pub state: ModelState,
     pub cmd: Option<Command>,
+    pub binary: Option<LlamaBinary>,
 }
 
 impl LlamaProcess {
@@ -14,32 +19,17 @@ impl LlamaProcess {
         Self {
             state: state,
             cmd: None,
+            binary: None,
         }
     }
 
-    pub async fn getcmd(&mut self) {
-        /* ToDO Implement server based on machine type */
-        let adapters_dir = get_env_var("ADAPTERS_HOME").unwrap_or(
-            "pyano_home/adapters".to_string()
-        );
-
-        let cmd_path = (
-            if cfg!(target_os = "macos") {
-                if cfg!(target_arch = "aarch64") {
-                    format!("{}/llama/macos/arm64/llama-server", adapters_dir)
-                } else {
-                    format!("{}/llama/macos/x64/llama-server", adapters_dir)
-                }
-            } else {
-                format!("{}/llama/ubuntu/llama-server", adapters_dir)
-            }
-        ).to_string();
+    pub async fn getcmd(&mut self) -> ModelResult<()> {
+        let binary = LlamaBinary::discover(&self.state.config.server_config).await?;
+        *self.state.llama_build.lock().unwrap() = binary.build;
 
-        let mut cmd = Command::new(&cmd_path);
+        let mut cmd = Command::new(&binary.path);
 
-        let model_path: PathBuf = get_env_var("MODEL_HOME")
-            .map(|path| PathBuf::from(path))
-            .expect("MODEL_HOME environment variable not set");
+        let model_path: PathBuf = PyanoConfig::global().model_home.clone();
 
         let model_path = model_path.join(&*self.state.model_path.lock().unwrap());
 
@@ -57,6 +47,19 @@ impl LlamaProcess {
             cmd.arg("--threads").arg(threads.to_string());
         }
 
+        // Pin the server to a CPU set, e.g. one per replica
+        if let Some(range) = &self.state.config.server_config.cpu_range {
+            if binary.supports(LlamaFeature::CpuRange) {
+                cmd.arg(LlamaFeature::CpuRange.flag()).arg(range);
+            } else {
+                warn!(
+                    "llama-server build {:?} does not support --cpu-range (needs b{}), skipping",
+                    binary.build,
+                    LlamaFeature::CpuRange.min_build()
+                );
+            }
+        }
+
         if self.state.config.server_config.gpu_layers > 0 {
             cmd.arg("--n-gpu-layers").arg(self.state.config.server_config.gpu_layers.to_string());
         }
@@ -68,11 +71,153 @@ impl LlamaProcess {
         // Add batch size
         cmd.arg("--batch-size").arg(self.state.config.server_config.batch_size.to_string());
 
+        // One slot per concurrent request the manager lets through
+        cmd.arg("--parallel").arg(self.state.config.server_config.parallel.max(1).to_string());
+
+        // Add optional features the detected build understands
+        let server_config = &self.state.config.server_config;
+        let requested = [
+            (server_config.jinja, LlamaFeature::Jinja),
+            (server_config.flash_attn, LlamaFeature::FlashAttn),
+            (server_config.embeddings, LlamaFeature::Embeddings),
+            (server_config.mlock, LlamaFeature::Mlock),
+            (server_config.cont_batching == Some(true), LlamaFeature::ContBatching),
+            (server_config.cont_batching == Some(false), LlamaFeature::NoContBatching),
+        ];
+        for (enabled, feature) in requested {
+            if !enabled {
+                continue;
+            }
+            if binary.supports(feature) {
+                cmd.arg(feature.flag());
+            } else {
+                warn!(
+                    "llama-server build {:?} does not support {} (needs b{}), skipping",
+                    binary.build,
+                    feature.flag(),
+                    feature.min_build()
+                );
+            }
+        }
+
+        // Directory slots are saved to and restored from
+        if let Some(dir) = &server_config.slot_save_dir {
+            if binary.supports(LlamaFeature::SlotSave) {
+                let dir = PyanoConfig::global().model_home.join(dir);
+                if let Err(e) = std::fs::create_dir_all(&dir) {
+                    warn!("Cannot create slot directory {}: {}", dir.display(), e);
+                }
+                cmd.arg(LlamaFeature::SlotSave.flag()).arg(dir);
+            } else {
+                warn!(
+                    "llama-server build {:?} does not support slot saving (needs b{}), skipping",
+                    binary.build,
+                    LlamaFeature::SlotSave.min_build()
+                );
+            }
+        }
+
+        // KV cache quantization
+        let cache_types = [
+            (server_config.cache_type_k, LlamaFeature::CacheTypeK),
+            (server_config.cache_type_v, LlamaFeature::CacheTypeV),
+        ];
+        for (cache_type, feature) in cache_types {
+            let Some(cache_type) = cache_type else {
+                continue;
+            };
+            if binary.supports(feature) {
+                cmd.arg(feature.flag()).arg(cache_type.as_str());
+            } else {
+                warn!(
+                    "llama-server build {:?} does not support {} (needs b{}), skipping",
+                    binary.build,
+                    feature.flag(),
+                    feature.min_build()
+                );
+            }
+        }
+
+        if let Some(rope) = &server_config.rope_scaling {
+            if binary.supports(LlamaFeature::RopeScaling) {
+                let kind = match rope.kind {
+                    RopeScalingType::None => "none",
+                    RopeScalingType::Linear => "linear",
+                    RopeScalingType::Yarn => "yarn",
+                };
+                cmd.arg(LlamaFeature::RopeScaling.flag()).arg(kind);
+                if let Some(factor) = rope.factor {
+                    cmd.arg("--rope-scale").arg(factor.to_string());
+                }
+                if let Some(base) = rope.freq_base {
+                    cmd.arg("--rope-freq-base").arg(base.to_string());
+                }
+                if let Some(ctx) = rope.original_ctx {
+                    cmd.arg("--yarn-orig-ctx").arg(ctx.to_string());
+                }
+            } else {
+                warn!(
+                    "llama-server build {:?} does not support rope scaling (needs b{}), skipping",
+                    binary.build,
+                    LlamaFeature::RopeScaling.min_build()
+                );
+            }
+        }
+
+        // Speculative decoding, with the draft resolved by the manager
+        let draft_path = self.state.draft_model_path.lock().unwrap().clone();
+        if let (Some(draft), Some(draft_path)) = (&server_config.draft, draft_path) {
+            if binary.supports(LlamaFeature::DraftModel) {
+                cmd.arg(LlamaFeature::DraftModel.flag()).arg(
+                    PyanoConfig::global().model_home.join(draft_path)
+                );
+                if let Some(max) = draft.max {
+                    cmd.arg("--draft-max").arg(max.to_string());
+                }
+                if let Some(min) = draft.min {
+                    cmd.arg("--draft-min").arg(min.to_string());
+                }
+                if let Some(layers) = draft.gpu_layers {
+                    cmd.arg("--n-gpu-layers-draft").arg(layers.to_string());
+                }
+            } else {
+                warn!(
+                    "llama-server build {:?} does not support draft models (needs b{}), skipping",
+                    binary.build,
+                    LlamaFeature::DraftModel.min_build()
+                );
+            }
+        }
+
+        // LoRA adapters, numbered by their order for per-request selection. A
+        // server that can't select them per request would ignore `with_lora`
+        if !server_config.lora_adapters.is_empty() && !binary.supports(LlamaFeature::Lora) {
+            return Err(
+                ModelError::ProcessError(
+                    format!(
+                        "llama-server build {:?} can't pick LoRA adapters per request (needs b{})",
+                        binary.build,
+                        LlamaFeature::Lora.min_build()
+                    )
+                )
+            );
+        }
+        for adapter in &server_config.lora_adapters {
+            let path = PyanoConfig::global().model_home.join(&adapter.path);
+            if adapter.scale == 1.0 {
+                cmd.arg(LlamaFeature::Lora.flag()).arg(path);
+            } else {
+                cmd.arg("--lora-scaled").arg(path).arg(adapter.scale.to_string());
+            }
+        }
+
         // Add extra arguments
         for (key, value) in &self.state.config.server_config.extra_args {
             cmd.arg(format!("--{}", key)).arg(value);
         }
 
         self.cmd = Some(cmd);
+        self.binary = Some(binary);
+        Ok(())
     }
 }
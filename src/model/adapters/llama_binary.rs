@@ -0,0 +1,236 @@
+use std::env;
+use std::path::{ Path, PathBuf };
+use std::sync::OnceLock;
+use std::time::Duration;
+use log::{ debug, info, warn };
+use regex::Regex;
+use tokio::process::Command;
+
+use crate::config::PyanoConfig;
+use super::super::ServerConfig;
+use super::super::error::{ ModelError, ModelResult };
+
+const LLAMA_SERVER_BIN: &str = "llama-server";
+
+/// How long `llama-server --version` may take before the binary is skipped.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Server flags that only exist in newer llama.cpp builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LlamaFeature {
+    Jinja,
+    FlashAttn,
+    Embeddings,
+    CpuRange,
+    DraftModel,
+    SlotSave,
+    CacheTypeK,
+    CacheTypeV,
+    ContBatching,
+    NoContBatching,
+    Mlock,
+    /// `--rope-scaling` with `--rope-scale`, `--rope-freq-base` and `--yarn-orig-ctx`
+    RopeScaling,
+    /// `--lora`/`--lora-scaled` with per-request adapter selection
+    Lora,
+}
+
+impl LlamaFeature {
+    /// First llama.cpp build (`bNNNN` release tag) that accepts the flag.
+    pub fn min_build(&self) -> u32 {
+        match self {
+            LlamaFeature::Jinja => 4435,
+            LlamaFeature::FlashAttn => 2999,
+            LlamaFeature::Embeddings => 1,
+            LlamaFeature::CpuRange => 3453,
+            LlamaFeature::DraftModel => 4179,
+            LlamaFeature::SlotSave => 2690,
+            LlamaFeature::CacheTypeK | LlamaFeature::CacheTypeV => 1650,
+            LlamaFeature::ContBatching => 1333,
+            LlamaFeature::NoContBatching => 3681,
+            LlamaFeature::Mlock => 1,
+            LlamaFeature::RopeScaling => 1450,
+            LlamaFeature::Lora => 4419,
+        }
+    }
+
+    pub fn flag(&self) -> &'static str {
+        match self {
+            LlamaFeature::Jinja => "--jinja",
+            LlamaFeature::FlashAttn => "--flash-attn",
+            LlamaFeature::Embeddings => "--embeddings",
+            LlamaFeature::CpuRange => "--cpu-range",
+            LlamaFeature::DraftModel => "--model-draft",
+            LlamaFeature::SlotSave => "--slot-save-path",
+            LlamaFeature::CacheTypeK => "--cache-type-k",
+            LlamaFeature::CacheTypeV => "--cache-type-v",
+            LlamaFeature::ContBatching => "--cont-batching",
+            LlamaFeature::NoContBatching => "--no-cont-batching",
+            LlamaFeature::Mlock => "--mlock",
+            LlamaFeature::RopeScaling => "--rope-scaling",
+            LlamaFeature::Lora => "--lora",
+        }
+    }
+}
+
+/// A llama-server executable that has been located and verified to run.
+#[derive(Debug, Clone)]
+pub struct LlamaBinary {
+    pub path: PathBuf,
+    pub build: Option<u32>,
+    pub version: String,
+}
+
+impl LlamaBinary {
+    /// Locates llama-server by checking, in order, `server_config.binary`,
+    /// the platform directory under the adapters home, and every entry on `PATH`.
+    /// The first candidate that answers `--version` wins.
+    pub async fn discover(server_config: &ServerConfig) -> ModelResult<Self> {
+        let mut tried = Vec::new();
+
+        for candidate in Self::candidates(server_config) {
+            if !candidate.is_file() {
+                tried.push(candidate);
+                continue;
+            }
+            match Self::probe(&candidate).await {
+                Ok(binary) => {
+                    match binary.build {
+                        Some(build) => {
+                            let path = binary.path.display();
+                            info!("Using llama-server at {} (build {})", path, build);
+                        }
+                        None => {
+                            warn!(
+                                "Using llama-server at {} of unknown build; configured flags are \
+                                 passed as is, and an older build may reject them",
+                                binary.path.display()
+                            );
+                        }
+                    }
+                    return Ok(binary);
+                }
+                Err(e) => {
+                    warn!("Skipping {}: {}", candidate.display(), e);
+                    tried.push(candidate);
+                }
+            }
+        }
+
+        Err(
+            ModelError::ProcessError(
+                format!(
+                    "Could not find a working llama-server binary. Tried: {:?}",
+                    tried
+                )
+            )
+        )
+    }
+
+    fn candidates(server_config: &ServerConfig) -> Vec<PathBuf> {
+        let mut candidates = Vec::new();
+
+        if let Some(binary) = &server_config.binary {
+            candidates.push(binary.clone());
+        }
+
+        let adapters_dir = &PyanoConfig::global().adapters_home;
+        candidates.push(adapters_dir.join(Self::adapter_subdir()).join(LLAMA_SERVER_BIN));
+
+        if let Some(paths) = env::var_os("PATH") {
+            for dir in env::split_paths(&paths) {
+                candidates.push(dir.join(LLAMA_SERVER_BIN));
+            }
+        }
+
+        candidates
+    }
+
+    fn adapter_subdir() -> &'static str {
+        if cfg!(target_os = "macos") {
+            if cfg!(target_arch = "aarch64") { "llama/macos/arm64" } else { "llama/macos/x64" }
+        } else {
+            "llama/ubuntu"
+        }
+    }
+
+    async fn probe(path: &Path) -> ModelResult<Self> {
+        let version = Command::new(path).arg("--version").kill_on_drop(true).output();
+        let output = tokio::time::timeout(PROBE_TIMEOUT, version).await.map_err(|_| {
+            ModelError::ProcessError(
+                format!("`--version` did not finish within {}s", PROBE_TIMEOUT.as_secs())
+            )
+        })??;
+
+        // llama.cpp prints its version banner on stderr
+        let mut text = String::from_utf8_lossy(&output.stderr).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stdout));
+
+        if !output.status.success() {
+            return Err(
+                ModelError::ProcessError(
+                    format!("`--version` exited with {}: {}", output.status, text.trim())
+                )
+            );
+        }
+
+        // Source builds made outside a git checkout report version 0
+        let build = parse_build_number(&text).filter(|build| *build > 0);
+        debug!("llama-server version output for {}: {}", path.display(), text.trim());
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            build,
+            version: text.lines().next().unwrap_or_default().trim().to_string(),
+        })
+    }
+
+    /// Unknown builds are assumed to support every flag, so a configured
+    /// feature is never dropped silently; `discover` warns about them.
+    pub fn supports(&self, feature: LlamaFeature) -> bool {
+        self.build.map_or(true, |build| build >= feature.min_build())
+    }
+}
+
+/// Extracts the build number from output such as `version: 4557 (b4557abc)`.
+pub fn parse_build_number(version_output: &str) -> Option<u32> {
+    static VERSION_RE: OnceLock<Regex> = OnceLock::new();
+    let re = VERSION_RE.get_or_init(|| Regex::new(r"version:\s*(\d+)").unwrap());
+    re.captures(version_output)
+        .and_then(|caps| caps.get(1))
+        .and_then(|m| m.as_str().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_build_number() {
+        let output = "version: 4557 (d6d24cd9)\nbuilt with cc (Ubuntu 11.4.0) for x86_64-linux-gnu";
+        assert_eq!(parse_build_number(output), Some(4557));
+        assert_eq!(parse_build_number("llama-server unknown"), None);
+    }
+
+    #[test]
+    fn test_supports_feature() {
+        let binary = LlamaBinary {
+            path: PathBuf::from("llama-server"),
+            build: Some(4000),
+            version: "version: 4000".to_string(),
+        };
+        assert!(binary.supports(LlamaFeature::FlashAttn));
+        assert!(!binary.supports(LlamaFeature::Jinja));
+
+        let old = LlamaBinary { build: Some(1400), ..binary.clone() };
+        assert!(old.supports(LlamaFeature::Mlock));
+        assert!(!old.supports(LlamaFeature::CacheTypeK));
+        assert!(!old.supports(LlamaFeature::RopeScaling));
+        assert!(!old.supports(LlamaFeature::NoContBatching));
+        assert!(!binary.supports(LlamaFeature::Lora));
+
+        let unknown = LlamaBinary { build: None, ..binary };
+        assert!(unknown.supports(LlamaFeature::Embeddings));
+        assert!(unknown.supports(LlamaFeature::Jinja));
+    }
+}
@@ -1,3 +1,4 @@
 // pub mod smol_vlm;
 pub mod llama;
+pub mod llama_binary;
 pub mod whisper;
@@ -0,0 +1,406 @@
+use std::collections::HashSet;
+use std::path::{ Component, Path, PathBuf };
+use std::sync::Arc;
+
+use axum::{
+    extract::{ Request, State },
+    http::{ header, HeaderMap, StatusCode },
+    middleware::Next,
+    response::{ IntoResponse, Response },
+    Json,
+};
+use serde::{ Deserialize, Serialize };
+use serde_json::json;
+use thiserror::Error;
+
+use super::ModelConfig;
+
+/// Largest request body the server accepts unless configured otherwise.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// What a key may do. `Admin` implies `Read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Status, plans, stats, events and metrics
+    Read,
+    /// Also load, unload and reload the registry
+    Admin,
+}
+
+impl std::str::FromStr for Role {
+    type Err = AccessError;
+
+    fn from_str(value: &str) -> Result<Self, AccessError> {
+        match value {
+            "read" => Ok(Role::Read),
+            "admin" => Ok(Role::Admin),
+            _ => Err(AccessError::InvalidRole(value.to_string())),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum AccessError {
+    #[error("Missing API key")] MissingKey,
+
+    #[error("Invalid API key")] InvalidKey,
+
+    #[error("This key has no {0:?} access")] Forbidden(Role),
+
+    #[error("Not allowed: {0}")] NotAllowed(String),
+
+    #[error("Invalid role `{0}`, expected read or admin")] InvalidRole(String),
+}
+
+impl AccessError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AccessError::MissingKey | AccessError::InvalidKey => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl IntoResponse for AccessError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    pub key: String,
+    pub role: Role,
+}
+
+impl std::str::FromStr for ApiKey {
+    type Err = AccessError;
+
+    /// Parses `key` (admin) or `key:role`.
+    fn from_str(value: &str) -> Result<Self, AccessError> {
+        let (key, role) = match value.rsplit_once(':') {
+            Some((key, role)) => (key, role.parse()?),
+            None => (value, Role::Admin),
+        };
+        Ok(ApiKey { key: key.to_string(), role })
+    }
+}
+
+/// Which models `/models/load` may start. By default only configs identical to
+/// a registry entry are accepted, so HTTP clients cannot choose model files,
+/// the server binary or its arguments.
+#[derive(Debug, Clone, Default)]
+pub struct LoadPolicy {
+    /// Accept configs that are not in the registry, within the limits below
+    pub allow_custom_configs: bool,
+    /// `extra_args` keys a custom config may set, without the leading dashes
+    /// (`threads`, not `--threads`), as they are written in configs
+    pub allowed_extra_args: HashSet<String>,
+}
+
+impl LoadPolicy {
+    /// Checks a config sent over HTTP against the registry entry of the same name.
+    pub fn check(&self, config: &ModelConfig, registered: Option<&ModelConfig>) -> Result<(), AccessError> {
+        if registered == Some(config) {
+            return Ok(());
+        }
+        let name = &config.model_config.name;
+        if !self.allow_custom_configs {
+            return Err(
+                AccessError::NotAllowed(format!("{} does not match a registry config", name))
+            );
+        }
+
+        check_relative("model_path", &config.model_config.model_path)?;
+        if let Some(dir) = &config.server_config.slot_save_dir {
+            check_relative("slot_save_dir", dir)?;
+        }
+        if config.server_config.binary.is_some() {
+            return Err(AccessError::NotAllowed("server_config.binary cannot be set".to_string()));
+        }
+        if config.model_config.model_url.is_some() {
+            return Err(AccessError::NotAllowed("model_url cannot be set".to_string()));
+        }
+        // Adapters are downloaded and read like the model file
+        for adapter in &config.server_config.lora_adapters {
+            check_relative("lora_adapters[].path", &adapter.path)?;
+            if adapter.url.is_some() {
+                return Err(
+                    AccessError::NotAllowed("lora_adapters[].url cannot be set".to_string())
+                );
+            }
+        }
+        let mut denied: Vec<&String> = config.server_config.extra_args
+            .keys()
+            .filter(|key| !self.allowed_extra_args.contains(*key))
+            .collect();
+        if !denied.is_empty() {
+            denied.sort();
+            return Err(AccessError::NotAllowed(format!("extra_args {:?}", denied)));
+        }
+        Ok(())
+    }
+}
+
+/// Rejects paths that could leave the model home: absolute ones and `..`.
+fn check_relative(field: &str, path: &Path) -> Result<(), AccessError> {
+    let escapes = path
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(
+            AccessError::NotAllowed(
+                format!("{} {} must be relative to the model home", field, path.display())
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Certificate and key, in PEM, for serving over HTTPS (needs the `tls` feature).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// Access control for [`ModelManagerServer`](super::ModelManagerServer).
+/// With no keys configured every request is allowed, as before.
+#[derive(Debug, Clone)]
+pub struct ServerSecurity {
+    pub api_keys: Vec<ApiKey>,
+    pub load_policy: LoadPolicy,
+    pub max_body_bytes: usize,
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for ServerSecurity {
+    fn default() -> Self {
+        Self {
+            api_keys: Vec::new(),
+            load_policy: LoadPolicy::default(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            tls: None,
+        }
+    }
+}
+
+impl ServerSecurity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_api_key(mut self, key: impl Into<String>, role: Role) -> Self {
+        self.api_keys.push(ApiKey { key: key.into(), role });
+        self
+    }
+
+    pub fn with_load_policy(mut self, policy: LoadPolicy) -> Self {
+        self.load_policy = policy;
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, bytes: usize) -> Self {
+        self.max_body_bytes = bytes;
+        self
+    }
+
+    pub fn with_tls(mut self, cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        self.tls = Some(TlsConfig { cert_path: cert_path.into(), key_path: key_path.into() });
+        self
+    }
+
+    /// Reads `PYANO_API_KEYS` (comma separated `key` or `key:role`),
+    /// `PYANO_TLS_CERT` and `PYANO_TLS_KEY`, `PYANO_MAX_BODY_BYTES`,
+    /// `PYANO_ALLOW_CUSTOM_MODELS` and `PYANO_ALLOWED_EXTRA_ARGS` (comma
+    /// separated `extra_args` keys without the leading dashes).
+    pub fn from_env() -> Result<Self, AccessError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let list = |value: String| -> Vec<String> {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        };
+
+        let mut security = Self::new();
+        if let Some(keys) = var("PYANO_API_KEYS") {
+            security.api_keys = list(keys)
+                .iter()
+                .map(|key| key.parse::<ApiKey>())
+                .collect::<Result<_, _>>()?;
+        }
+        if let (Some(cert), Some(key)) = (var("PYANO_TLS_CERT"), var("PYANO_TLS_KEY")) {
+            security = security.with_tls(cert, key);
+        }
+        if let Some(bytes) = var("PYANO_MAX_BODY_BYTES").and_then(|value| value.parse().ok()) {
+            security.max_body_bytes = bytes;
+        }
+        security.load_policy.allow_custom_configs = var("PYANO_ALLOW_CUSTOM_MODELS").map_or(
+            false,
+            |value| matches!(value.as_str(), "1" | "true" | "yes")
+        );
+        if let Some(args) = var("PYANO_ALLOWED_EXTRA_ARGS") {
+            security.load_policy.allowed_extra_args = list(args).into_iter().collect();
+        }
+        Ok(security)
+    }
+
+    pub fn auth_enabled(&self) -> bool {
+        !self.api_keys.is_empty()
+    }
+
+    /// The role granted to a request, from `Authorization: Bearer <key>` or
+    /// `X-API-Key: <key>`. Without configured keys every caller is admin.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Role, AccessError> {
+        if !self.auth_enabled() {
+            return Ok(Role::Admin);
+        }
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let api_key = headers.get("x-api-key").and_then(|value| value.to_str().ok());
+        let presented = bearer.or(api_key).ok_or(AccessError::MissingKey)?.trim();
+
+        self.api_keys
+            .iter()
+            .find(|key| constant_time_eq(key.key.as_bytes(), presented.as_bytes()))
+            .map(|key| key.role)
+            .ok_or(AccessError::InvalidKey)
+    }
+
+    pub fn authorize(&self, headers: &HeaderMap, required: Role) -> Result<Role, AccessError> {
+        let role = self.authenticate(headers)?;
+        if role < required {
+            return Err(AccessError::Forbidden(required));
+        }
+        Ok(role)
+    }
+}
+
+// Compares every byte so the time taken does not reveal how much of a key matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Route middleware rejecting requests whose key lacks the given role.
+pub(crate) async fn require_role(
+    State((security, required)): State<(Arc<ServerSecurity>, Role)>,
+    request: Request,
+    next: Next
+) -> Response {
+    match security.authorize(request.headers(), required) {
+        Ok(_) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use crate::model::LoraAdapter;
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_roles_from_keys() {
+        let security = ServerSecurity::new()
+            .with_api_key("reader", Role::Read)
+            .with_api_key("root", Role::Admin);
+
+        assert_eq!(security.authenticate(&HeaderMap::new()), Err(AccessError::MissingKey));
+        assert_eq!(
+            security.authenticate(&headers("authorization", "Bearer wrong")),
+            Err(AccessError::InvalidKey)
+        );
+        assert_eq!(security.authenticate(&headers("x-api-key", "reader")), Ok(Role::Read));
+        assert_eq!(
+            security.authorize(&headers("authorization", "Bearer reader"), Role::Admin),
+            Err(AccessError::Forbidden(Role::Admin))
+        );
+        assert_eq!(
+            security.authorize(&headers("authorization", "Bearer root"), Role::Read),
+            Ok(Role::Admin)
+        );
+
+        // No keys keeps the server open
+        assert_eq!(ServerSecurity::new().authenticate(&HeaderMap::new()), Ok(Role::Admin));
+    }
+
+    #[test]
+    fn test_parse_api_key() {
+        assert_eq!(
+            "abc:read".parse::<ApiKey>().unwrap(),
+            ApiKey { key: "abc".to_string(), role: Role::Read }
+        );
+        assert_eq!("abc".parse::<ApiKey>().unwrap().role, Role::Admin);
+        assert!("abc:owner".parse::<ApiKey>().is_err());
+    }
+
+    #[test]
+    fn test_load_policy() {
+        let mut registered = ModelConfig::default();
+        registered.model_config.model_path = "qwen.gguf".into();
+
+        let strict = LoadPolicy::default();
+        assert!(strict.check(&registered, Some(&registered)).is_ok());
+
+        let mut changed = registered.clone();
+        changed.server_config.extra_args.insert("lora".to_string(), "x".to_string());
+        assert!(strict.check(&changed, Some(&registered)).is_err());
+
+        let mut custom = LoadPolicy {
+            allow_custom_configs: true,
+            ..Default::default()
+        };
+        assert!(custom.check(&changed, None).is_err());
+        custom.allowed_extra_args.insert("lora".to_string());
+        assert!(custom.check(&changed, None).is_ok());
+
+        let mut escaping = registered.clone();
+        escaping.model_config.model_path = "../../etc/passwd".into();
+        assert!(custom.check(&escaping, None).is_err());
+        escaping.model_config.model_path = "/tmp/model.gguf".into();
+        assert!(custom.check(&escaping, None).is_err());
+
+        let mut binary = registered.clone();
+        binary.server_config.binary = Some("/bin/sh".into());
+        assert!(custom.check(&binary, None).is_err());
+
+        let mut slots = registered.clone();
+        slots.server_config.slot_save_dir = Some("slots".into());
+        assert!(custom.check(&slots, None).is_ok());
+        slots.server_config.slot_save_dir = Some("../../home/user/.ssh".into());
+        assert!(custom.check(&slots, None).is_err());
+        slots.server_config.slot_save_dir = Some("/var/tmp".into());
+        assert!(custom.check(&slots, None).is_err());
+
+        let mut lora = registered.clone();
+        lora.server_config.lora_adapters.push(LoraAdapter {
+            name: "sql".to_string(),
+            path: "adapters/sql.gguf".into(),
+            url: None,
+            sha256: None,
+            scale: 1.0,
+        });
+        assert!(custom.check(&lora, None).is_ok());
+        lora.server_config.lora_adapters[0].path = "../../home/user/.bashrc".into();
+        assert!(custom.check(&lora, None).is_err());
+        lora.server_config.lora_adapters[0].path = "/etc/cron.d/job".into();
+        assert!(custom.check(&lora, None).is_err());
+        lora.server_config.lora_adapters[0].path = "adapters/sql.gguf".into();
+        lora.server_config.lora_adapters[0].url = Some("http://example.com/x".to_string());
+        assert!(custom.check(&lora, None).is_err());
+    }
+}
@@ -1,10 +1,12 @@
 use async_trait::async_trait;
 use serde_json::json;
-use reqwest::Client;
+use reqwest::{ Client, RequestBuilder };
 
 use super::manager_trait::ModelManagerInterface;
 use super::types::{ ModelConfig, ModelInfo, ModelStatus };
 use super::error::ModelResult;
+use super::config_loader::RegistryReloadReport;
+use super::system_memory::MemoryPlan;
 use crate::llm::llm_builder::{ LLMBuilder, LLM };
 use super::state::ModelState;
 use crate::llm::options::LLMHTTPCallOptions;
@@ -13,6 +15,7 @@ use crate::llm::stream_processing::{ llamacpp_process_stream, qwen_process_strea
 pub struct ModelManagerClient {
     base_url: String,
     client: Client,
+    api_key: Option<String>,
 }
 
 impl ModelManagerClient {
@@ -20,43 +23,87 @@ impl ModelManagerClient {
         Self {
             base_url: base_url.to_string(),
             client: Client::new(),
+            api_key: None,
         }
     }
+
+    /// Sends `key` as a bearer token, for servers with API keys configured.
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.authorized(self.client.get(url))
+    }
+
+    fn post(&self, url: &str) -> RequestBuilder {
+        self.authorized(self.client.post(url))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    pub async fn reload_registry(&self) -> ModelResult<RegistryReloadReport> {
+        let url = format!("{}/models/registry/reload", self.base_url);
+        let response = self.post(&url).send().await?.error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+
+    /// The last `lines` lines of output from a loaded model's servers.
+    pub async fn logs(&self, name: &str, lines: usize) -> ModelResult<Vec<String>> {
+        let url = format!("{}/models/logs/{}", self.base_url, name);
+        let response = self.get(&url).query(&[("lines", lines)]).send().await?.error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+
+    pub async fn plan_memory(&self, required_gb: f32) -> ModelResult<MemoryPlan> {
+        let url = format!("{}/memory/plan", self.base_url);
+        let response = self.get(&url)
+            .query(&[("required_gb", required_gb)])
+            .send().await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
 }
 
 #[async_trait]
 impl ModelManagerInterface for ModelManagerClient {
-    async fn load_model(&self, _state: ModelState) -> ModelResult<()> {
-        let _url = format!("{}/models/load", self.base_url);
-        // let _ = self.client.post(&url).json(&config).send().await?.error_for_status();
+    async fn load_model(&self, state: ModelState) -> ModelResult<()> {
+        let url = format!("{}/models/load", self.base_url);
+        self.post(&url).json(&state.config).send().await?.error_for_status()?;
         Ok(())
     }
 
-    async fn load_model_by_name(&self, _name: &str) -> ModelResult<()> {
-        // todo
+    async fn load_model_by_name(&self, name: &str) -> ModelResult<()> {
+        let url = format!("{}/models/load/{}", self.base_url, name);
+        self.post(&url).send().await?.error_for_status()?;
         Ok(())
     }
 
     async fn unload_model(&self, name: &str) -> ModelResult<()> {
         let url = format!("{}/models/unload", self.base_url);
-        let _ = self.client
-            .post(&url)
-            .json(&json!({ "name": name }))
-            .send().await?
-            .error_for_status();
+        self.post(&url).json(&json!(name)).send().await?.error_for_status()?;
         Ok(())
     }
 
     async fn get_model_status(&self, name: &str) -> ModelResult<ModelStatus> {
         let url = format!("{}/models/status/{}", self.base_url, name);
-        let response = self.client.get(&url).send().await?.error_for_status()?;
+        let response = self.get(&url).send().await?.error_for_status()?;
 
         Ok(response.json().await?)
     }
 
     async fn list_models(&self) -> ModelResult<Vec<ModelInfo>> {
         let url = format!("{}/models/list", self.base_url);
-        let response = self.client.get(&url).send().await?.error_for_status()?;
+        let response = self.get(&url).send().await?.error_for_status()?;
 
         Ok(response.json().await?)
     }
@@ -73,8 +120,7 @@ impl ModelManagerInterface for ModelManagerClient {
             Err(_) => {
                 // If model is not found/loaded, get its config and load it
                 let url = format!("{}/models/config/{}", self.base_url, model_name);
-                let config: ModelConfig = self.client
-                    .get(&url)
+                let config: ModelConfig = self.get(&url)
                     .send().await?
                     .error_for_status()?
                     .json().await?;
@@ -84,8 +130,7 @@ impl ModelManagerInterface for ModelManagerClient {
         }
         // Get the model's server details
         let url = format!("{}/models/server/{}", self.base_url, model_name);
-        let server_info: serde_json::Value = self.client
-            .get(&url)
+        let server_info: serde_json::Value = self.get(&url)
             .send().await?
             .error_for_status()?
             .json().await?;
@@ -1,6 +1,16 @@
-use std::collections::HashMap;
-use log::{ info, debug };
-use crate::model::utils::get_env_var;
+use std::collections::{ HashMap, HashSet };
+use std::path::{ Path, PathBuf };
+use std::time::SystemTime;
+use log::{ info, debug, warn };
+use serde::{ Deserialize, Serialize };
+use crate::config::{ ConfigResult, PyanoConfig };
+use crate::config::layering::{
+    interpolate_env,
+    is_config_file,
+    merge,
+    parse_file,
+    resolve_extends,
+};
 
 use super::{
     ModelConfig,
@@ -10,9 +20,41 @@ use super::{
     PromptTemplate,
     ServerConfig,
 };
+use super::error::{ ModelError, ModelResult };
+
+/// Top-level key marking a config as a base for `extends` only, not a loadable model.
+const ABSTRACT_KEY: &str = "abstract";
 
 pub struct ModelRegistry {
+    config_dir: PathBuf,
     configs: HashMap<String, ModelConfig>,
+    // Which file each model was read from, so a broken file keeps its last good config
+    sources: HashMap<PathBuf, String>,
+    // Partial per-model configs from the global PyanoConfig
+    overrides: HashMap<String, Value>,
+    // Whether `reload` re-reads the overrides from the pyano config file
+    reload_overrides: bool,
+    // Files that failed to load the last time the directory was read
+    errors: Vec<ConfigFileError>,
+}
+
+/// A config file that could not be read or parsed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigFileError {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Outcome of re-reading the model config directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegistryReloadReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    /// Running models whose config changed or disappeared. They keep serving
+    /// with the old settings until they are unloaded and loaded again.
+    pub restart_required: Vec<String>,
+    pub errors: Vec<ConfigFileError>,
 }
 
 use std::fs;
@@ -20,76 +62,260 @@ use serde_json::Value;
 
 impl ModelRegistry {
     pub fn new() -> Self {
+        let pyano_config = PyanoConfig::global();
+        let mut registry = Self::with_overrides(
+            pyano_config.model_config_dir.clone(),
+            pyano_config.models.clone()
+        );
+        registry.reload_overrides = true;
+        registry
+    }
+
+    pub fn from_dir(config_dir: PathBuf) -> Self {
+        Self::with_overrides(config_dir, HashMap::new())
+    }
+
+    pub fn with_overrides(config_dir: PathBuf, overrides: HashMap<String, Value>) -> Self {
         debug!("Initializing ModelRegistry");
-        let configs = Self::load_configs_from_json();
-        Self { configs }
+        let (configs, sources, errors) = Self::load_configs(&config_dir, &overrides);
+        for err in &errors {
+            warn!("Skipping model config {}: {}", err.path.display(), err.error);
+        }
+        Self { config_dir, configs, sources, overrides, reload_overrides: false, errors }
     }
 
-    fn load_configs_from_json() -> HashMap<String, ModelConfig> {
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    /// Config files that could not be loaded by the last read of the config
+    /// directory. Models they describe may be missing from the registry.
+    pub fn load_errors(&self) -> &[ConfigFileError] {
+        &self.errors
+    }
+
+    /// Reads every JSON, TOML and YAML file in `config_dir`, expands `${ENV}`
+    /// references, resolves `extends` chains and applies per-model overrides.
+    fn load_configs(
+        config_dir: &Path,
+        overrides: &HashMap<String, Value>
+    ) -> (HashMap<String, ModelConfig>, HashMap<PathBuf, String>, Vec<ConfigFileError>) {
         let mut configs = HashMap::new();
-        let config_dir = get_env_var("MODEL_CONFIG_DIR").unwrap_or(
-            "pyano_home/configs".to_string()
-        );
+        let mut sources = HashMap::new();
+        let mut errors = Vec::new();
+
+        debug!("Loading model configurations from {}", config_dir.display());
+
+        let entries = match fs::read_dir(config_dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push(ConfigFileError {
+                    path: config_dir.to_path_buf(),
+                    error: format!("Failed to read config directory: {}", e),
+                });
+                return (configs, sources, errors);
+            }
+        };
 
-        debug!("Loading model configurations from {}", config_dir);
+        // First pass: raw values keyed by model name, so configs can extend each other
+        let mut raw: HashMap<String, Value> = HashMap::new();
+        let mut files: Vec<(PathBuf, String)> = Vec::new();
+        let mut paths: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| is_config_file(path))
+            .collect();
+        paths.sort();
 
-        for entry in fs::read_dir(&config_dir).expect("Failed to read config directory") {
-            let path = entry.expect("Failed to read entry").path();
-            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
-                debug!("Processing config file: {:?}", path);
+        for path in paths {
+            debug!("Processing config file: {:?}", path);
 
-                let config_str = fs::read_to_string(&path).expect("Failed to read config file");
+            let parsed = parse_file(&path)
+                .and_then(|mut value| interpolate_model_env(&mut value).map(|_| value))
+                .map_err(|e| e.to_string())
+                .and_then(|value| {
+                    let name = value
+                        .get("model_config")
+                        .and_then(|c| c.get("name"))
+                        .and_then(|n| n.as_str())
+                        .map(|n| n.to_string())
+                        .ok_or_else(|| "missing `model_config.name`".to_string())?;
+                    Ok((name, value))
+                });
 
-                let json: Value = serde_json::from_str(&config_str).expect("Failed to parse JSON");
+            match parsed {
+                Ok((name, value)) => {
+                    if raw.contains_key(&name) {
+                        errors.push(ConfigFileError {
+                            path,
+                            error: format!("Duplicate model name: {}", name),
+                        });
+                        continue;
+                    }
+                    raw.insert(name.clone(), value);
+                    files.push((path, name));
+                }
+                Err(error) => errors.push(ConfigFileError { path, error }),
+            }
+        }
 
-                debug!("Parsed JSON structure: {:#?}", json);
+        // Second pass: resolve inheritance and overrides into full configs
+        for (path, name) in files {
+            let is_abstract = raw[&name]
+                .get(ABSTRACT_KEY)
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            if is_abstract {
+                debug!("Skipping abstract base config: {}", name);
+                continue;
+            }
 
-                // Extract base configurations
-                let model_config: ModelSpecificConfig = json
-                    .get("model_config")
-                    .and_then(|v| serde_json::from_value::<ModelSpecificConfig>(v.clone()).ok())
-                    .expect("Model config is required");
-                let memory_config = json
-                    .get("memory_config")
-                    .and_then(|v| serde_json::from_value::<ModelMemoryConfig>(v.clone()).ok())
-                    .expect("Memory config is required");
-                let prompt_template = json
-                    .get("prompt_template")
-                    .and_then(|v| serde_json::from_value::<PromptTemplate>(v.clone()).ok())
-                    .expect("Prompt template is required");
+            let resolved = resolve_extends(&name, &raw)
+                .map_err(|e| ModelError::ConfigError(e.to_string()))
+                .and_then(|mut value| {
+                    if let Some(model_override) = overrides.get(&name) {
+                        debug!("Applying config overrides for model: {}", name);
+                        merge(&mut value, model_override);
+                    }
+                    Self::parse_config_value(&value)
+                });
 
-                let defaults = json
-                    .get("defaults")
-                    .and_then(|v| serde_json::from_value::<ModelDefaults>(v.clone()).ok())
-                    .expect("Defaults are required");
+            let resolved = resolved.and_then(|config| {
+                match port_conflict(&config, configs.values()) {
+                    Some(other) =>
+                        Err(
+                            ModelError::InvalidConfig(
+                                format!("replica ports of {} overlap those of {}", name, other)
+                            )
+                        ),
+                    None => Ok(config),
+                }
+            });
 
-                let server_config = json
-                    .get("server_config")
-                    .and_then(|v| serde_json::from_value::<ServerConfig>(v.clone()).ok())
-                    .expect("Server config is required");
+            match resolved {
+                Ok(config) => {
+                    debug!("Adding model to registry: {}", name);
+                    sources.insert(path, name.clone());
+                    configs.insert(name.clone(), config);
+                    debug!("Loaded configuration for model: {}", name);
+                }
+                Err(e) => {
+                    errors.push(ConfigFileError { path, error: e.to_string() });
+                }
+            }
+        }
 
-                // Get model details directly
+        debug!("Loaded {} model configurations", configs.len());
+        (configs, sources, errors)
+    }
 
-                let name = model_config.name.clone();
+    fn parse_config_value(json: &Value) -> ModelResult<ModelConfig> {
+        debug!("Parsed JSON structure: {:#?}", json);
 
-                debug!("Processing model: {}", name);
+        fn section<T: serde::de::DeserializeOwned>(json: &Value, key: &str) -> ModelResult<T> {
+            let value = json
+                .get(key)
+                .ok_or_else(|| ModelError::InvalidConfig(format!("missing `{}` section", key)))?;
+            serde_json
+                ::from_value::<T>(value.clone())
+                .map_err(|e| ModelError::InvalidConfig(format!("invalid `{}`: {}", key, e)))
+        }
 
-                let config = ModelConfig {
-                    model_config: model_config.clone(),
-                    memory_config: memory_config.clone(),
-                    prompt_template: prompt_template.clone(),
-                    defaults: defaults.clone(),
-                    server_config: server_config.clone(),
-                };
+        let config = ModelConfig {
+            model_config: section::<ModelSpecificConfig>(json, "model_config")?,
+            memory_config: section::<ModelMemoryConfig>(json, "memory_config")?,
+            prompt_template: section::<PromptTemplate>(json, "prompt_template")?,
+            defaults: section::<ModelDefaults>(json, "defaults")?,
+            server_config: section::<ServerConfig>(json, "server_config")?,
+        };
+        config.validate().map_err(ModelError::InvalidConfig)?;
+        Ok(config)
+    }
 
-                debug!("Adding model to registry: {}", name);
-                configs.insert(name.to_string(), config);
-                debug!("Loaded configuration for model: {}", name);
+    /// Re-reads every config file. Files that fail to parse keep their previous
+    /// registry entry and are listed in the report's `errors`. A registry made
+    /// with `new` also re-reads the `models` overrides of the pyano config file;
+    /// its other settings only change on restart.
+    pub fn reload(&mut self, running: &HashSet<String>) -> RegistryReloadReport {
+        if self.reload_overrides {
+            match PyanoConfig::load() {
+                Ok(pyano_config) => {
+                    self.overrides = pyano_config.models;
+                }
+                Err(e) => warn!("Keeping previous model overrides: {}", e),
             }
         }
+        let (mut configs, mut sources, errors) = Self::load_configs(
+            &self.config_dir,
+            &self.overrides
+        );
+        let mut report = RegistryReloadReport::default();
 
-        debug!("Loaded {} model configurations", configs.len());
-        configs
+        for err in &errors {
+            if let Some(name) = self.sources.get(&err.path) {
+                if let Some(previous) = self.configs.get(name) {
+                    if !configs.contains_key(name) {
+                        configs.insert(name.clone(), previous.clone());
+                        sources.insert(err.path.clone(), name.clone());
+                    }
+                }
+            }
+        }
+
+        for (name, config) in &configs {
+            match self.configs.get(name) {
+                None => report.added.push(name.clone()),
+                Some(previous) if previous != config => {
+                    report.updated.push(name.clone());
+                    if running.contains(name) {
+                        report.restart_required.push(name.clone());
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        for name in self.configs.keys() {
+            if !configs.contains_key(name) {
+                report.removed.push(name.clone());
+                if running.contains(name) {
+                    report.restart_required.push(name.clone());
+                }
+            }
+        }
+
+        report.added.sort();
+        report.updated.sort();
+        report.removed.sort();
+        report.restart_required.sort();
+        report.errors = errors.clone();
+
+        self.configs = configs;
+        self.errors = errors;
+        self.sources = sources;
+        report
+    }
+
+    /// Modification times of the config files, used to detect changes on disk.
+    pub fn fingerprint(&self) -> Vec<(PathBuf, Option<SystemTime>)> {
+        let mut files: Vec<_> = fs
+            ::read_dir(&self.config_dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| is_config_file(path))
+                    .map(|path| {
+                        let modified = fs
+                            ::metadata(&path)
+                            .and_then(|m| m.modified())
+                            .ok();
+                        (path, modified)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files
     }
 
     pub fn get_config(&self, model_name: &str) -> Option<&ModelConfig> {
@@ -104,3 +330,157 @@ impl ModelRegistry {
         self.configs.iter().collect()
     }
 }
+
+/// The first of `others` whose ports overlap the ports of `config`'s replicas.
+/// Single-replica models may share a port, as only one of them can run at a time.
+fn port_conflict<'a>(
+    config: &ModelConfig,
+    others: impl Iterator<Item = &'a ModelConfig>
+) -> Option<&'a str> {
+    let ports = config.server_config.port_range()?;
+    others
+        .filter(|other| config.server_config.replicas > 1 || other.server_config.replicas > 1)
+        .find(|other| {
+            other.server_config
+                .port_range()
+                .map_or(false, |theirs| {
+                    ports.start() <= theirs.end() && theirs.start() <= ports.end()
+                })
+        })
+        .map(|other| other.model_config.name.as_str())
+}
+
+/// Expands `${ENV}` references everywhere except the prompt template, which
+/// is sent to the model as written.
+fn interpolate_model_env(value: &mut Value) -> ConfigResult<()> {
+    let template = value.as_object_mut().and_then(|config| config.remove("prompt_template"));
+    let result = interpolate_env(value);
+    if let (Some(template), Some(config)) = (template, value.as_object_mut()) {
+        config.insert("prompt_template".to_string(), template);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(dir: &Path, file: &str, name: &str, ctx_size: usize) {
+        let mut config = ModelConfig::default();
+        config.model_config.name = name.to_string();
+        config.server_config.ctx_size = ctx_size;
+        fs::write(dir.join(file), serde_json::to_string(&config).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_reload_reports_changes() {
+        let dir = std::env::temp_dir().join(format!("pyano-registry-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        write_config(&dir, "a.json", "model-a", 2048);
+        write_config(&dir, "b.json", "model-b", 2048);
+        let mut registry = ModelRegistry::from_dir(dir.clone());
+        assert_eq!(registry.get_all_configs().len(), 2);
+
+        write_config(&dir, "a.json", "model-a", 4096);
+        fs::write(dir.join("b.json"), "{ not json").unwrap();
+        write_config(&dir, "c.json", "model-c", 2048);
+
+        let running: HashSet<String> = ["model-a".to_string()].into_iter().collect();
+        let report = registry.reload(&running);
+
+        assert_eq!(report.added, vec!["model-c"]);
+        assert_eq!(report.updated, vec!["model-a"]);
+        assert_eq!(report.restart_required, vec!["model-a"]);
+        assert!(report.removed.is_empty());
+        assert_eq!(report.errors.len(), 1);
+        // The broken file keeps its last good config
+        assert!(registry.get_config("model-b").is_some());
+        assert_eq!(registry.get_config("model-a").unwrap().server_config.ctx_size, 4096);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_replica_port_conflicts() {
+        let dir = std::env::temp_dir().join(format!("pyano-ports-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let write = |file: &str, name: &str, port: u16, replicas: usize| {
+            let mut config = ModelConfig::default();
+            config.model_config.name = name.to_string();
+            config.server_config.port = Some(port);
+            config.server_config.replicas = replicas;
+            fs::write(dir.join(file), serde_json::to_string(&config).unwrap()).unwrap();
+        };
+        write("a.json", "single-a", 5010, 1);
+        write("b.json", "single-b", 5010, 1);
+        write("c.json", "replicated", 5020, 3);
+        write("d.json", "overlapping", 5022, 1);
+
+        let registry = ModelRegistry::from_dir(dir.clone());
+        assert!(registry.get_config("single-a").is_some());
+        assert!(registry.get_config("single-b").is_some());
+        assert!(registry.get_config("replicated").is_some());
+        assert!(registry.get_config("overlapping").is_none());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_prompt_template_is_not_interpolated() {
+        let dir = std::env::temp_dir().join(format!("pyano-template-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut config = serde_json::to_value(ModelConfig::default()).unwrap();
+        config["model_config"]["name"] = "templated".into();
+        config["prompt_template"]["template"] = "${PYANO_TEST_UNSET_VAR} {user_prompt}".into();
+        fs::write(dir.join("templated.json"), config.to_string()).unwrap();
+
+        let registry = ModelRegistry::from_dir(dir.clone());
+        let config = registry.get_config("templated").unwrap();
+        assert_eq!(config.prompt_template.template, "${PYANO_TEST_UNSET_VAR} {user_prompt}");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_extends_and_overrides() {
+        let dir = std::env::temp_dir().join(format!("pyano-extends-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut base = serde_json::to_value(ModelConfig::default()).unwrap();
+        base["model_config"]["name"] = "qwen-base".into();
+        base["abstract"] = true.into();
+        fs::write(dir.join("qwen-base.json"), base.to_string()).unwrap();
+        fs::write(
+            dir.join("qwen-q4.toml"),
+            r#"
+extends = "qwen-base"
+
+[model_config]
+name = "qwen-q4"
+model_path = "Qwen/qwen-q4_0.gguf"
+"#
+        ).unwrap();
+
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "qwen-q4".to_string(),
+            serde_json::json!({ "server_config": { "ctx_size": 4096 } })
+        );
+        let registry = ModelRegistry::with_overrides(dir.clone(), overrides);
+
+        assert!(registry.get_config("qwen-base").is_none());
+        let config = registry.get_config("qwen-q4").unwrap();
+        assert_eq!(config.model_config.model_path, PathBuf::from("Qwen/qwen-q4_0.gguf"));
+        assert_eq!(config.server_config.ctx_size, 4096);
+        assert_eq!(config.server_config.batch_size, 512);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
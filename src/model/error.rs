@@ -1,4 +1,5 @@
 use thiserror::Error;
+use crate::tools::downloader::DownloadError;
 
 #[derive(Error, Debug)]
 pub enum ModelError {
@@ -21,6 +22,8 @@ pub enum ModelError {
     #[error("Memory error: {0}")] MemoryError(String),
 
     #[error("IO error: {0}")] IoError(#[from] std::io::Error),
+
+    #[error("Download error: {0}")] DownloadError(#[from] DownloadError),
 }
 
 pub type ModelResult<T> = std::result::Result<T, ModelError>;
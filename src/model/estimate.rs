@@ -0,0 +1,92 @@
+use std::path::Path;
+use log::debug;
+use serde::{ Deserialize, Serialize };
+
+use super::gguf::GgufMetadata;
+use super::{ KvCacheType, ModelConfig };
+
+const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Memory one server of a model needs, in GB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEstimate {
+    /// `memory_config.min_ram_gb`, which assumes an f16 KV cache
+    pub base_gb: f32,
+    /// Change from a non-f16 KV cache type; negative when it saves memory
+    pub kv_cache_delta_gb: f32,
+    /// `min_ram_gb` of the draft model
+    pub draft_gb: f32,
+}
+
+impl MemoryEstimate {
+    pub fn total_gb(&self) -> f32 {
+        (self.base_gb + self.kv_cache_delta_gb + self.draft_gb).max(0.0)
+    }
+}
+
+/// Estimates what a server started with `config` needs, including a draft
+/// model and the KV cache types. The cache size comes from the GGUF header
+/// under `model_home`; when it can't be read the configured size is used.
+pub fn estimate_memory(
+    config: &ModelConfig,
+    draft: Option<&ModelConfig>,
+    model_home: &Path
+) -> MemoryEstimate {
+    let server = &config.server_config;
+    let cache_k = server.cache_type_k.unwrap_or(KvCacheType::F16);
+    let cache_v = server.cache_type_v.unwrap_or(KvCacheType::F16);
+
+    let mut kv_cache_delta_gb = 0.0;
+    if (cache_k, cache_v) != (KvCacheType::F16, KvCacheType::F16) {
+        let path = model_home.join(&config.model_config.model_path);
+        match GgufMetadata::read(&path) {
+            Ok(metadata) => {
+                let default = metadata.kv_cache_bytes(server.ctx_size, 2.0, 2.0);
+                let actual = metadata.kv_cache_bytes(
+                    server.ctx_size,
+                    cache_k.bytes_per_element(),
+                    cache_v.bytes_per_element()
+                );
+                if let (Some(default), Some(actual)) = (default, actual) {
+                    kv_cache_delta_gb = (((actual as f64) - (default as f64)) / GB) as f32;
+                }
+            }
+            Err(e) => debug!("Cannot size the KV cache of {}: {}", path.display(), e),
+        }
+    }
+
+    MemoryEstimate {
+        base_gb: config.memory_config.min_ram_gb,
+        kv_cache_delta_gb,
+        draft_gb: draft.map_or(0.0, |draft| draft.memory_config.min_ram_gb),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::DraftConfig;
+
+    #[test]
+    fn test_estimate_with_draft() {
+        let mut config = ModelConfig::default();
+        config.memory_config.min_ram_gb = 6.0;
+        config.server_config.draft = Some(DraftConfig {
+            model: "draft".to_string(),
+            max: Some(16),
+            min: None,
+            gpu_layers: None,
+        });
+        let mut draft = ModelConfig::default();
+        draft.memory_config.min_ram_gb = 0.8;
+
+        let estimate = estimate_memory(&config, Some(&draft), Path::new("/nonexistent"));
+        assert!((estimate.total_gb() - 6.8).abs() < 1e-6);
+
+        // Without a readable GGUF header the cache type can't be accounted
+        config.server_config.cache_type_k = Some(KvCacheType::Q8_0);
+        let estimate = estimate_memory(&config, None, Path::new("/nonexistent"));
+        assert_eq!(estimate.kv_cache_delta_gb, 0.0);
+        assert_eq!(estimate.total_gb(), 6.0);
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::{ DateTime, Utc };
+use log::debug;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::broadcast;
+
+/// Events kept for subscribers that fall behind before they start missing some.
+pub const DEFAULT_EVENT_CAPACITY: usize = 256;
+
+/// Something that happened to a model managed by [`ModelManager`](super::ModelManager).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ModelEvent {
+    /// Memory was reserved and the model's servers are starting
+    Loading {
+        model: String,
+        replicas: usize,
+    },
+    Ready {
+        model: String,
+        load_secs: f64,
+    },
+    Failed {
+        model: String,
+        error: String,
+    },
+    /// Unloaded to make room for another model
+    Evicted {
+        model: String,
+        freed_gb: f32,
+        for_gb: f32,
+    },
+    Unloaded {
+        model: String,
+    },
+    DownloadProgress {
+        model: String,
+        file_name: String,
+        downloaded: u64,
+        total: Option<u64>,
+    },
+    /// A replica stopped answering, or answered again after failing
+    HealthChanged {
+        model: String,
+        replica: usize,
+        healthy: bool,
+    },
+}
+
+impl ModelEvent {
+    pub fn model(&self) -> &str {
+        match self {
+            | ModelEvent::Loading { model, .. }
+            | ModelEvent::Ready { model, .. }
+            | ModelEvent::Failed { model, .. }
+            | ModelEvent::Evicted { model, .. }
+            | ModelEvent::Unloaded { model }
+            | ModelEvent::DownloadProgress { model, .. }
+            | ModelEvent::HealthChanged { model, .. } => model,
+        }
+    }
+}
+
+/// An event with the time it was emitted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: ModelEvent,
+}
+
+/// Fans lifecycle events out to every subscriber. Cloning gives another
+/// handle to the same bus.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<TimedEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_CAPACITY)
+    }
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Receives every event emitted from now on. A subscriber that lags more
+    /// than the bus capacity behind gets `RecvError::Lagged` and skips ahead.
+    pub fn subscribe(&self) -> broadcast::Receiver<TimedEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn emit(&self, event: ModelEvent) {
+        debug!("Model event: {:?}", event);
+        // Having no subscribers is fine
+        let _ = self.sender.send(TimedEvent { at: Utc::now(), event });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscribers_receive_events() {
+        let bus = EventBus::new(4);
+        bus.emit(ModelEvent::Unloaded { model: "before".to_string() });
+
+        let mut rx = bus.subscribe();
+        bus.emit(ModelEvent::Loading { model: "qwen".to_string(), replicas: 1 });
+        bus.emit(ModelEvent::Ready { model: "qwen".to_string(), load_secs: 1.5 });
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.event.model(), "qwen");
+        assert!(matches!(first.event, ModelEvent::Loading { .. }));
+        assert!(matches!(rx.recv().await.unwrap().event, ModelEvent::Ready { .. }));
+
+        let json = serde_json::to_value(&first).unwrap();
+        assert_eq!(json["type"], "loading");
+        assert_eq!(json["model"], "qwen");
+        assert!(json["at"].is_string());
+    }
+}
@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+use super::system_memory::EvictionCandidate;
+
+/// Decides in which order loaded models are unloaded to make room for another.
+/// Pinned models and models serving requests are filtered out before the
+/// policy is consulted, so a policy only ever sees models that are safe to stop.
+pub trait EvictionPolicy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Returns `candidates` in eviction order. Models are unloaded from the
+    /// front until `required_gb` fits, so the order decides what gets evicted.
+    fn order(&self, candidates: Vec<EvictionCandidate>, required_gb: f32) -> Vec<EvictionCandidate>;
+}
+
+/// Least recently used first. This is the default.
+pub struct LruPolicy;
+
+impl EvictionPolicy for LruPolicy {
+    fn name(&self) -> &'static str {
+        "lru"
+    }
+
+    fn order(&self, mut candidates: Vec<EvictionCandidate>, _: f32) -> Vec<EvictionCandidate> {
+        candidates.sort_by_key(|c| c.last_used);
+        candidates
+    }
+}
+
+/// Least frequently used first, ties broken by recency.
+pub struct LfuPolicy;
+
+impl EvictionPolicy for LfuPolicy {
+    fn name(&self) -> &'static str {
+        "lfu"
+    }
+
+    fn order(&self, mut candidates: Vec<EvictionCandidate>, _: f32) -> Vec<EvictionCandidate> {
+        candidates.sort_by_key(|c| (c.use_count, c.last_used));
+        candidates
+    }
+}
+
+/// Lowest `memory_config.priority` first, ties broken by recency.
+pub struct PriorityPolicy;
+
+impl EvictionPolicy for PriorityPolicy {
+    fn name(&self) -> &'static str {
+        "priority"
+    }
+
+    fn order(&self, mut candidates: Vec<EvictionCandidate>, _: f32) -> Vec<EvictionCandidate> {
+        candidates.sort_by_key(|c| (c.priority, c.last_used));
+        candidates
+    }
+}
+
+/// Evicts as little as possible: the smallest single model that frees enough
+/// memory, or otherwise the largest models first so fewer have to go.
+pub struct SmallestSufficientPolicy;
+
+impl EvictionPolicy for SmallestSufficientPolicy {
+    fn name(&self) -> &'static str {
+        "smallest_sufficient"
+    }
+
+    fn order(&self, mut candidates: Vec<EvictionCandidate>, required_gb: f32) -> Vec<EvictionCandidate> {
+        let by_size = |a: &EvictionCandidate, b: &EvictionCandidate| {
+            a.memory_gb.partial_cmp(&b.memory_gb).unwrap_or(Ordering::Equal)
+        };
+
+        let sufficient = candidates
+            .iter()
+            .filter(|c| c.memory_gb >= required_gb)
+            .min_by(|a, b| by_size(a, b))
+            .map(|c| c.name.clone());
+
+        candidates.sort_by(|a, b| by_size(b, a).then(a.last_used.cmp(&b.last_used)));
+        if let Some(name) = sufficient {
+            if let Some(index) = candidates.iter().position(|c| c.name == name) {
+                let best = candidates.remove(index);
+                candidates.insert(0, best);
+            }
+        }
+        candidates
+    }
+}
+
+/// Looks a policy up by the name used in config files.
+pub fn policy_from_name(name: &str) -> Option<Box<dyn EvictionPolicy>> {
+    match name.to_lowercase().as_str() {
+        "lru" => Some(Box::new(LruPolicy)),
+        "lfu" => Some(Box::new(LfuPolicy)),
+        "priority" => Some(Box::new(PriorityPolicy)),
+        "smallest" | "smallest_sufficient" => Some(Box::new(SmallestSufficientPolicy)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{ Duration, Utc };
+
+    fn candidate(name: &str, memory_gb: f32, age_secs: i64, use_count: u64, priority: i32) -> EvictionCandidate {
+        EvictionCandidate {
+            name: name.to_string(),
+            memory_gb,
+            measured: true,
+            last_used: Utc::now() - Duration::seconds(age_secs),
+            use_count,
+            priority,
+            in_flight: 0,
+            pinned: false,
+        }
+    }
+
+    fn names(candidates: &[EvictionCandidate]) -> Vec<&str> {
+        candidates
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect()
+    }
+
+    fn candidates() -> Vec<EvictionCandidate> {
+        vec![
+            candidate("big", 8.0, 10, 50, 1),
+            candidate("old", 2.0, 300, 20, 5),
+            candidate("mid", 4.0, 100, 1, 0)
+        ]
+    }
+
+    #[test]
+    fn test_policies_order() {
+        assert_eq!(names(&LruPolicy.order(candidates(), 1.0)), vec!["old", "mid", "big"]);
+        assert_eq!(names(&LfuPolicy.order(candidates(), 1.0)), vec!["mid", "old", "big"]);
+        assert_eq!(names(&PriorityPolicy.order(candidates(), 1.0)), vec!["mid", "big", "old"]);
+    }
+
+    #[test]
+    fn test_smallest_sufficient() {
+        let policy = SmallestSufficientPolicy;
+        assert_eq!(names(&policy.order(candidates(), 3.0)), vec!["mid", "big", "old"]);
+        // Nothing is big enough alone, so the largest go first
+        assert_eq!(names(&policy.order(candidates(), 10.0)), vec!["big", "mid", "old"]);
+    }
+}
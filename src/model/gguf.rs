@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ BufReader, Read };
+use std::path::Path;
+
+use super::error::{ ModelError, ModelResult };
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+// Guards against allocating huge buffers when reading a corrupt header
+const MAX_STRING_LEN: u64 = 64 * 1024 * 1024;
+
+/// A metadata value from a GGUF header. Arrays are skipped rather than kept,
+/// since none of the keys we need (template, context length, ...) are arrays.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GgufValue {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Array(u64),
+}
+
+/// Key/value metadata from the header of a GGUF model file.
+#[derive(Debug, Clone, Default)]
+pub struct GgufMetadata {
+    pub version: u32,
+    pub tensor_count: u64,
+    pub values: HashMap<String, GgufValue>,
+}
+
+impl GgufMetadata {
+    /// Reads only the header; tensor data is never touched.
+    pub fn read(path: &Path) -> ModelResult<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Self::from_reader(&mut reader)
+    }
+
+    pub fn from_reader<R: Read>(reader: &mut R) -> ModelResult<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != GGUF_MAGIC {
+            return Err(ModelError::InvalidConfig("Not a GGUF file".to_string()));
+        }
+
+        let version = read_u32(reader)?;
+        if version < 2 {
+            return Err(ModelError::InvalidConfig(format!("Unsupported GGUF version {}", version)));
+        }
+        let tensor_count = read_u64(reader)?;
+        let kv_count = read_u64(reader)?;
+
+        let mut values = HashMap::new();
+        for _ in 0..kv_count {
+            let key = read_string(reader)?;
+            let value_type = read_u32(reader)?;
+            let value = read_value(reader, value_type)?;
+            values.insert(key, value);
+        }
+
+        Ok(Self { version, tensor_count, values })
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.values.get(key) {
+            Some(GgufValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        match self.values.get(key) {
+            Some(GgufValue::UInt(v)) => Some(*v),
+            Some(GgufValue::Int(v)) if *v >= 0 => Some(*v as u64),
+            _ => None,
+        }
+    }
+
+    pub fn architecture(&self) -> Option<&str> {
+        self.get_str("general.architecture")
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.get_str("general.name")
+    }
+
+    pub fn chat_template(&self) -> Option<&str> {
+        self.get_str("tokenizer.chat_template")
+    }
+
+    /// Training context length, stored under `<architecture>.context_length`.
+    pub fn context_length(&self) -> Option<u64> {
+        self.arch_u64("context_length")
+    }
+
+    /// Size of the KV cache for `ctx_size` tokens with the given bytes per K
+    /// and V element, from the layer count and attention head sizes.
+    pub fn kv_cache_bytes(&self, ctx_size: usize, k_bytes: f64, v_bytes: f64) -> Option<u64> {
+        let layers = self.arch_u64("block_count")?;
+        let heads = self.arch_u64("attention.head_count")?;
+        let kv_heads = self.arch_u64("attention.head_count_kv").unwrap_or(heads);
+        let head_dim = self.arch_u64("embedding_length")? / heads.max(1);
+        let key_length = self.arch_u64("attention.key_length").unwrap_or(head_dim);
+        let value_length = self.arch_u64("attention.value_length").unwrap_or(head_dim);
+
+        let per_token = (kv_heads as f64) * ((key_length as f64) * k_bytes + (value_length as f64) * v_bytes);
+        Some(((layers as f64) * (ctx_size as f64) * per_token) as u64)
+    }
+
+    fn arch_u64(&self, key: &str) -> Option<u64> {
+        self.architecture().and_then(|arch| self.get_u64(&format!("{}.{}", arch, key)))
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> ModelResult<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> ModelResult<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> ModelResult<String> {
+    let len = read_u64(reader)?;
+    if len > MAX_STRING_LEN {
+        return Err(ModelError::InvalidConfig(format!("GGUF string too long: {} bytes", len)));
+    }
+    let mut buf = vec![0u8; len as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).to_string())
+}
+
+fn read_fixed<R: Read>(reader: &mut R, size: usize) -> ModelResult<[u8; 8]> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf[..size])?;
+    Ok(buf)
+}
+
+fn read_value<R: Read>(reader: &mut R, value_type: u32) -> ModelResult<GgufValue> {
+    Ok(match value_type {
+        0 => GgufValue::UInt(read_fixed(reader, 1)?[0] as u64),
+        1 => GgufValue::Int(read_fixed(reader, 1)?[0] as i8 as i64),
+        2 => GgufValue::UInt(u16::from_le_bytes(read_fixed(reader, 2)?[..2].try_into().unwrap()) as u64),
+        3 => GgufValue::Int(i16::from_le_bytes(read_fixed(reader, 2)?[..2].try_into().unwrap()) as i64),
+        4 => GgufValue::UInt(u32::from_le_bytes(read_fixed(reader, 4)?[..4].try_into().unwrap()) as u64),
+        5 => GgufValue::Int(i32::from_le_bytes(read_fixed(reader, 4)?[..4].try_into().unwrap()) as i64),
+        6 => GgufValue::Float(f32::from_le_bytes(read_fixed(reader, 4)?[..4].try_into().unwrap()) as f64),
+        7 => GgufValue::Bool(read_fixed(reader, 1)?[0] != 0),
+        8 => GgufValue::String(read_string(reader)?),
+        9 => {
+            let item_type = read_u32(reader)?;
+            let count = read_u64(reader)?;
+            for _ in 0..count {
+                read_value(reader, item_type)?;
+            }
+            GgufValue::Array(count)
+        }
+        10 => GgufValue::UInt(read_u64(reader)?),
+        11 => GgufValue::Int(read_u64(reader)? as i64),
+        12 => GgufValue::Float(f64::from_le_bytes(read_u64(reader)?.to_le_bytes())),
+        other => {
+            return Err(ModelError::InvalidConfig(format!("Unknown GGUF value type {}", other)));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_string(buf: &mut Vec<u8>, s: &str) {
+        buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn test_read_metadata() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(b"GGUF");
+        buf.extend_from_slice(&3u32.to_le_bytes());
+        buf.extend_from_slice(&0u64.to_le_bytes());
+        buf.extend_from_slice(&4u64.to_le_bytes());
+
+        push_string(&mut buf, "general.architecture");
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        push_string(&mut buf, "qwen2");
+
+        push_string(&mut buf, "qwen2.context_length");
+        buf.extend_from_slice(&4u32.to_le_bytes());
+        buf.extend_from_slice(&32768u32.to_le_bytes());
+
+        push_string(&mut buf, "tokenizer.ggml.tokens");
+        buf.extend_from_slice(&9u32.to_le_bytes());
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        buf.extend_from_slice(&2u64.to_le_bytes());
+        push_string(&mut buf, "a");
+        push_string(&mut buf, "b");
+
+        push_string(&mut buf, "tokenizer.chat_template");
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        push_string(&mut buf, "<|im_start|>");
+
+        let metadata = GgufMetadata::from_reader(&mut buf.as_slice()).unwrap();
+        assert_eq!(metadata.architecture(), Some("qwen2"));
+        assert_eq!(metadata.context_length(), Some(32768));
+        assert_eq!(metadata.chat_template(), Some("<|im_start|>"));
+        assert_eq!(metadata.values.get("tokenizer.ggml.tokens"), Some(&GgufValue::Array(2)));
+    }
+
+    #[test]
+    fn test_kv_cache_bytes() {
+        let mut metadata = GgufMetadata::default();
+        let values = [
+            ("qwen2.block_count", 28),
+            ("qwen2.attention.head_count", 28),
+            ("qwen2.attention.head_count_kv", 4),
+            ("qwen2.embedding_length", 3584),
+        ];
+        metadata.values.insert("general.architecture".into(), GgufValue::String("qwen2".into()));
+        for (key, value) in values {
+            metadata.values.insert(key.into(), GgufValue::UInt(value));
+        }
+
+        // 28 layers x 4 KV heads x 128 dims x (K + V) x 2 bytes per token
+        assert_eq!(metadata.kv_cache_bytes(8192, 2.0, 2.0), Some(28 * 4 * 128 * 2 * 2 * 8192));
+        assert_eq!(metadata.kv_cache_bytes(8192, 34.0 / 32.0, 34.0 / 32.0), Some(249_561_088));
+        assert_eq!(GgufMetadata::default().kv_cache_bytes(8192, 2.0, 2.0), None);
+    }
+
+    #[test]
+    fn test_rejects_non_gguf() {
+        let mut data: &[u8] = b"NOPE\x03\x00\x00\x00";
+        assert!(GgufMetadata::from_reader(&mut data).is_err());
+    }
+}
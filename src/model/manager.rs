@@ -3,35 +3,62 @@ use log::{ debug, error, info, warn };
 use tokio::sync::RwLock;
 use super::state::ModelState;
 
-use std::collections::HashMap;
+use std::collections::{ BTreeMap, HashMap, HashSet };
 use std::sync::Arc;
 
-use super::utils::get_env_var;
+use crate::config::PyanoConfig;
 
 use std::sync::atomic::{ AtomicBool, Ordering };
 use std::time::{ Duration, SystemTime, UNIX_EPOCH };
-use parking_lot::Mutex;
-use super::process::ModelProcess;
-use super::config_loader::ModelRegistry;
+use parking_lot::{ Mutex, RwLock as SyncRwLock };
+use super::metrics::{ Metrics, MetricsWriter };
+use super::events::{ EventBus, ModelEvent, TimedEvent };
+use super::persistence::{
+    config_hash,
+    is_server_healthy,
+    kill_process,
+    shutdown_signal,
+    PersistedServer,
+    RestoreReport,
+    StateFile,
+};
+use super::replica::{ replica_urls, ReplicaPool, ReplicaSet, ReplicaStats };
+use super::estimate::estimate_memory;
+use super::config_loader::{ ModelRegistry, RegistryReloadReport };
+use super::store::{ GcOptions, GcReport, ModelStore };
+use super::system_memory::{ EvictionCandidate, MemoryPlan };
+use super::eviction::{ policy_from_name, EvictionPolicy, LruPolicy };
+use super::requests::{ InFlightGuard, RequestStats, RequestTracker };
+use super::queue::{ QueueStats, RequestQueue };
 use super::error::{ ModelError, ModelResult };
 use super::{ ModelConfig, ModelInfo, ModelStatus, ModelType, SystemMemory };
 use crate::llm::llm_builder::LLM;
 use crate::llm::options::LLMHTTPCallOptions;
 use crate::llm::stream_processing::llamacpp_process_stream;
 use crate::llm::types::AccumulatedStream;
-use crate::tools::downloader::download::download_model_files;
+use crate::tools::downloader::Downloader;
 
 use std::pin::Pin;
 use bytes::Bytes;
 use futures::Stream;
 use super::manager_trait::ModelManagerInterface;
 
+// How often a queued load re-checks memory when no request finishes
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 type StreamProcessor = Arc<dyn (Fn(AccumulatedStream) -> AccumulatedStream) + Send + Sync>;
 
 pub struct ModelManager {
-    models: Arc<RwLock<HashMap<String, ModelProcess>>>,
-    registry: ModelRegistry,
+    models: Arc<RwLock<HashMap<String, ReplicaSet>>>,
+    registry: SyncRwLock<ModelRegistry>,
     system_memory: SystemMemory,
+    eviction_policy: Box<dyn EvictionPolicy>,
+    requests: Arc<RequestTracker>,
+    load_queue_timeout: Duration,
+    queues: Mutex<HashMap<String, Arc<RequestQueue>>>,
+    replica_pools: Mutex<HashMap<String, Arc<ReplicaPool>>>,
+    state_file: Option<StateFile>,
+    events: EventBus,
 
     lock_in_progress: Arc<AtomicBool>,
     last_lock_holder: Arc<Mutex<Option<String>>>, // For debugging
@@ -39,10 +66,23 @@ pub struct ModelManager {
 
 impl ModelManager {
     pub fn new() -> Self {
+        let pyano_config = PyanoConfig::global();
+        let eviction_policy = policy_from_name(&pyano_config.eviction_policy).unwrap_or_else(|| {
+            warn!("Unknown eviction policy {}, using lru", pyano_config.eviction_policy);
+            Box::new(LruPolicy)
+        });
+
         Self {
             models: Arc::new(RwLock::new(HashMap::new())),
-            registry: ModelRegistry::new(),
-            system_memory: SystemMemory::new(),
+            registry: SyncRwLock::new(ModelRegistry::new()),
+            system_memory: SystemMemory::new().with_headroom(pyano_config.memory_headroom_gb),
+            eviction_policy,
+            requests: Arc::new(RequestTracker::new()),
+            load_queue_timeout: Duration::from_secs(pyano_config.load_queue_timeout_secs),
+            queues: Mutex::new(HashMap::new()),
+            replica_pools: Mutex::new(HashMap::new()),
+            state_file: None,
+            events: EventBus::default(),
 
             lock_in_progress: Arc::new(AtomicBool::new(false)),
             last_lock_holder: Arc::new(Mutex::new(None)),
@@ -51,11 +91,92 @@ impl ModelManager {
         // add a check here tro check if the directories provided as enviroment variables are present or not.
     }
 
+    pub fn with_eviction_policy<P: EvictionPolicy + 'static>(mut self, policy: P) -> Self {
+        self.eviction_policy = Box::new(policy);
+        self
+    }
+
+    /// How long a load waits for busy or pinned models to free memory before failing.
+    pub fn with_load_queue_timeout(mut self, timeout: Duration) -> Self {
+        self.load_queue_timeout = timeout;
+        self
+    }
+
+    /// Where running servers are recorded, or `None` (the default) to not
+    /// record them. Only one manager should use a given state file, since
+    /// `restore` kills the servers it lists that it cannot adopt.
+    pub fn with_state_file(mut self, state_file: Option<StateFile>) -> Self {
+        self.state_file = state_file;
+        self
+    }
+
+    /// Lifecycle events (loads, evictions, downloads, replica health) from now on.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<TimedEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn request_stats(&self, name: &str) -> RequestStats {
+        self.requests.stats(name)
+    }
+
+    /// The shared request queue for a model, sized to the `parallel` slots of
+    /// all its replicas.
+    pub fn request_queue(&self, config: &ModelConfig) -> Arc<RequestQueue> {
+        let server_config = &config.server_config;
+        let slots = server_config.parallel.max(1) * server_config.replicas.max(1);
+        let timeout = server_config.queue_timeout_secs.map(Duration::from_secs);
+        let mut queues = self.queues.lock();
+        let queue = queues
+            .entry(config.model_config.name.clone())
+            .or_insert_with(|| Arc::new(RequestQueue::new(slots, timeout)));
+        queue.resize(slots, timeout);
+        Arc::clone(queue)
+    }
+
+    /// The balancer over a model's replicas, starting at `base_port`.
+    pub fn replica_pool(&self, config: &ModelConfig, base_port: Option<u16>) -> Arc<ReplicaPool> {
+        let mut config = config.clone();
+        config.server_config.port = base_port.or(config.server_config.port);
+        let urls = replica_urls(&config);
+
+        let mut pools = self.replica_pools.lock();
+        let pool = pools
+            .entry(config.model_config.name.clone())
+            .or_insert_with(|| {
+                Arc::new(
+                    ReplicaPool::new(urls.clone()).with_events(
+                        &config.model_config.name,
+                        self.events.clone()
+                    )
+                )
+            });
+        pool.set_urls(urls);
+        Arc::clone(pool)
+    }
+
+    /// Outstanding requests and health per replica, for models with replica pools.
+    pub fn replica_stats(&self) -> HashMap<String, Vec<ReplicaStats>> {
+        self.replica_pools
+            .lock()
+            .iter()
+            .map(|(name, pool)| (name.clone(), pool.stats()))
+            .collect()
+    }
+
+    /// Queue depth and wait times per model.
+    pub fn queue_stats(&self) -> HashMap<String, QueueStats> {
+        self.queues
+            .lock()
+            .iter()
+            .map(|(name, queue)| (name.clone(), queue.stats()))
+            .collect()
+    }
+
     async fn acquire_models_lock<'a>(
         &'a self,
         operation: &str,
         timeout: Duration
-    ) -> ModelResult<tokio::sync::RwLockWriteGuard<'a, HashMap<String, ModelProcess>>> {
+    ) -> ModelResult<tokio::sync::RwLockWriteGuard<'a, HashMap<String, ReplicaSet>>> {
         info!("Starting lock acquisition for operation: {}", operation);
 
         // First, try to get read lock to check current state
@@ -135,8 +256,8 @@ impl ModelManager {
         // First check if model is already loaded without holding write lock
         {
             let read_guard = self.models.read().await;
-            if let Some(process) = read_guard.get(&state.config.model_config.name) {
-                if *process.state.status.lock().unwrap() == ModelStatus::Running {
+            if let Some(set) = read_guard.get(&state.config.model_config.name) {
+                if *set.state().status.lock().unwrap() == ModelStatus::Running {
                     self.record_lock_event(
                         &format!("Model {} already loaded", state.config.model_config.name)
                     );
@@ -145,11 +266,35 @@ impl ModelManager {
             }
         }
 
+        let prepared = match self.prepare_draft(&state).await {
+            Ok(draft) => self.ensure_lora_files(&state.config).await.map(|_| draft),
+            Err(e) => Err(e),
+        };
+        let draft = match prepared {
+            Ok(draft) => draft,
+            Err(e) => {
+                self.events.emit(ModelEvent::Failed {
+                    model: state.config.model_config.name.clone(),
+                    error: e.to_string(),
+                });
+                return Err(e);
+            }
+        };
+
         self.record_lock_event("Checking memory requirements");
         self.system_memory.debug_memory_info().await;
 
-        // Memory management with proper lock release
-        match self.manage_memory(state.config.memory_config.min_ram_gb).await {
+        // Memory management with proper lock release. Every replica is counted
+        // in full, although mmapped weights are shared between them.
+        let replicas = state.config.server_config.replicas.max(1);
+        let estimate = estimate_memory(
+            &state.config,
+            draft.as_ref(),
+            &PyanoConfig::global().model_home
+        );
+        debug!("Memory estimate for {}: {:?}", state.config.model_config.name, estimate);
+        let required_gb = estimate.total_gb() * (replicas as f32);
+        match self.manage_memory(required_gb).await {
             Ok(_) => {
                 info!("Memory requirements satisfied for model {}", state.config.model_config.name);
             }
@@ -159,6 +304,10 @@ impl ModelManager {
                     state.config.model_config.name,
                     e
                 );
+                self.events.emit(ModelEvent::Failed {
+                    model: state.config.model_config.name.clone(),
+                    error: e.to_string(),
+                });
                 return Err(e);
             }
         }
@@ -174,11 +323,22 @@ impl ModelManager {
             }
         };
         let lm_state = state.clone();
-        let mut process = ModelProcess::new(state);
-        match process.start().await {
+        // Match the queue to the slots this server is started with
+        self.request_queue(&lm_state.config);
+        let name = lm_state.config.model_config.name.clone();
+        self.events.emit(ModelEvent::Loading { model: name.clone(), replicas });
+        let started = std::time::Instant::now();
+        let mut set = ReplicaSet::new(state);
+        match set.start().await {
             Ok(_) => {
                 debug!("Successfully started model process: {}", lm_state.config.model_config.name);
-                models.insert(lm_state.config.model_config.name.clone(), process);
+                models.insert(lm_state.config.model_config.name.clone(), set);
+                self.persist_state(&models);
+                Metrics::global().record_load(&name, started.elapsed().as_secs_f64(), true);
+                self.events.emit(ModelEvent::Ready {
+                    model: name,
+                    load_secs: started.elapsed().as_secs_f64(),
+                });
                 self.record_lock_event(
                     &format!("Successfully loaded model {}", lm_state.config.model_config.name)
                 );
@@ -187,6 +347,8 @@ impl ModelManager {
             Err(e) => {
                 error!("Failed to start model process: {}", e);
                 self.record_lock_event(&format!("Failed to start model process: {}", e));
+                Metrics::global().record_load(&name, started.elapsed().as_secs_f64(), false);
+                self.events.emit(ModelEvent::Failed { model: name, error: e.to_string() });
                 Err(e)
             }
         }
@@ -194,29 +356,56 @@ impl ModelManager {
 
     pub async fn show_model_details(&self) {
         let models = self.models.read().await;
-        for (name, process) in models.iter() {
+        for (name, set) in models.iter() {
             info!("Model Name: {} \n", name);
-            info!("Model Process: \n");
-            process.state.show_state();
+            for (index, process) in set.replicas.iter().enumerate() {
+                info!("Model Process (replica {}): \n", index);
+                process.state.show_state();
+            }
         }
     }
 
     async fn load_model_by_name(&self, name: &str) -> ModelResult<()> {
-        let config = self.registry
-            .get_config(name)
+        let config = self
+            .get_registry_config(name)
             .ok_or_else(|| {
                 ModelError::ModelNotFound(format!("Configuration not found for model: {}", name))
             })?;
-        let state = ModelState::new(config.clone());
+        let state = ModelState::new(config);
         self.load_model(state.clone()).await
     }
 
+    /// Stops a model once its in-flight requests have finished, waiting up to
+    /// the load queue timeout for them.
     pub async fn unload_model(&self, name: &str) -> ModelResult<()> {
-        let mut models = self.models.write().await;
+        let deadline = tokio::time::Instant::now() + self.load_queue_timeout;
+        let mut models = loop {
+            // Checked under the write lock: a request starting after this waits
+            // for the lock to look the model up and then finds it unloaded
+            let models = self.models.write().await;
+            let in_flight = self.requests.in_flight(name);
+            if in_flight == 0 {
+                break models;
+            }
+            drop(models);
 
-        if let Some(process) = models.get_mut(name) {
-            process.stop().await?;
+            if tokio::time::Instant::now() >= deadline {
+                return Err(
+                    ModelError::ProcessError(
+                        format!("Model {} is still serving {} request(s)", name, in_flight)
+                    )
+                );
+            }
+            info!("Waiting for in-flight requests to {} before unloading", name);
+            self.requests.wait_for_change(QUEUE_POLL_INTERVAL).await;
+        };
+
+        if let Some(set) = models.get_mut(name) {
+            set.stop().await?;
             models.remove(name);
+            self.persist_state(&models);
+            self.requests.reset(name);
+            self.events.emit(ModelEvent::Unloaded { model: name.to_string() });
             Ok(())
         } else {
             Err(ModelError::ModelNotFound(name.to_string()))
@@ -227,27 +416,38 @@ impl ModelManager {
         let models = self.models.read().await;
 
         match models.get(name) {
-            Some(process) => Ok(process.state.status.lock().unwrap().clone()),
+            Some(set) => Ok(set.state().status.lock().unwrap().clone()),
             None => Err(ModelError::ModelNotFound(name.to_string())),
         }
     }
 
-    // pub async fn list_models(&self) -> ModelResult<Vec<ModelInfo>> {
-    //     let models = self.models.read().await;
+    pub async fn list_models(&self) -> ModelResult<Vec<ModelInfo>> {
+        let models = self.models.read().await;
+        let mut infos: Vec<ModelInfo> = models
+            .values()
+            .map(|set| {
+                let state = set.state();
+                ModelInfo {
+                    name: state.config.model_config.name.clone(),
+                    model_type: state.config.model_config.model_type.clone(),
+                    status: state.status.lock().unwrap().clone(),
+                    last_used: *state.last_used.lock().unwrap(),
+                    server_port: *state.port.lock().unwrap(),
+                }
+            })
+            .collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(infos)
+    }
 
-    //     Ok(
-    //         models
-    //             .values()
-    //             .map(|process| ModelInfo {
-    //                 name: process.state.config.model_config.name.clone(),
-    //                 model_type: process.state.config.model_config.model_type.clone(),
-    //                 status: process.status.clone(),
-    //                 last_used: process.last_used,
-    //                 server_port: process.config.server_config.port,
-    //             })
-    //             .collect()
-    //     )
-    // }
+    /// The last `lines` lines of output from a loaded model's servers.
+    pub async fn model_logs(&self, name: &str, lines: usize) -> ModelResult<Vec<String>> {
+        let models = self.models.read().await;
+        models
+            .get(name)
+            .map(|set| set.logs(lines))
+            .ok_or_else(|| ModelError::ModelNotFound(name.to_string()))
+    }
 
     fn get_processor_for_model(config: &ModelConfig) -> StreamProcessor {
         match config.model_config.model_type {
@@ -274,46 +474,118 @@ impl ModelManager {
     }
     //cretae a get_llm() & load_llm() methods
 
+    /// Validates the config and, for speculative decoding, resolves the draft
+    /// model through the registry, downloads it if needed and records its path.
+    async fn prepare_draft(&self, state: &ModelState) -> ModelResult<Option<ModelConfig>> {
+        let name = &state.config.model_config.name;
+        state.config
+            .validate()
+            .map_err(|e| ModelError::InvalidConfig(format!("{}: {}", name, e)))?;
+
+        let Some(draft) = &state.config.server_config.draft else {
+            return Ok(None);
+        };
+        let draft_config = self
+            .get_registry_config(&draft.model)
+            .ok_or_else(|| {
+                ModelError::ModelNotFound(
+                    format!("Draft model {} of {} is not in the registry", draft.model, name)
+                )
+            })?;
+        if draft_config.server_config.draft.is_some() {
+            return Err(
+                ModelError::InvalidConfig(format!("Draft model {} has a draft itself", draft.model))
+            );
+        }
+        self.ensure_model_files(&draft_config).await?;
+        *state.draft_model_path.lock().unwrap() = Some(draft_config.model_config.model_path.clone());
+        Ok(Some(draft_config))
+    }
+
+    /// Downloads the model's LoRA adapters that are missing from the model home.
+    async fn ensure_lora_files(&self, config: &ModelConfig) -> ModelResult<()> {
+        let model_home = &PyanoConfig::global().model_home;
+        for adapter in &config.server_config.lora_adapters {
+            let path = model_home.join(&adapter.path);
+            if path.exists() {
+                continue;
+            }
+            let url = adapter.url.as_deref().ok_or_else(|| {
+                ModelError::ConfigError(
+                    format!(
+                        "LoRA adapter {} of {} is missing at {} and has no url",
+                        adapter.name,
+                        config.model_config.name,
+                        path.display()
+                    )
+                )
+            })?;
+            info!("Downloading LoRA adapter {} of {}", adapter.name, config.model_config.name);
+            Downloader::new()
+                .with_progress_bar()
+                .download(url, &path, adapter.sha256.as_deref()).await
+                .map_err(|e| ModelError::ProcessError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Downloads a model's files from its `model_url` when they are missing and
+    /// `download_if_not_exist` is set.
+    async fn ensure_model_files(&self, config: &ModelConfig) -> ModelResult<()> {
+        let name = config.model_config.name.as_str();
+        let model_full_path = PyanoConfig::global().model_home.join(&config.model_config.model_path);
+        if model_full_path.exists() {
+            debug!("Model {} is already present at {}", name, model_full_path.display());
+            return Ok(());
+        }
+        warn!("Model {} is not present at {}", name, model_full_path.display());
+        if !config.model_config.download_if_not_exist {
+            warn!("Model {} is not present at the location and download_if_not_present is set to false", name);
+            return Ok(());
+        }
+
+        info!("Downloading model {}", name);
+        let model_url = config.model_config.model_url.as_deref().ok_or_else(|| {
+            ModelError::ConfigError(format!("No model_url configured for {}", name))
+        })?;
+        // Report progress roughly every percent, not on every chunk
+        let events = self.events.clone();
+        let model = name.to_string();
+        let last_reported = Arc::new(std::sync::atomic::AtomicU64::new(0));
+        let downloader = Downloader::new()
+            .with_progress_bar()
+            .with_progress(move |progress| {
+                let step = progress.total.map_or(8 << 20, |total| (total / 100).max(1));
+                let last = last_reported.load(Ordering::SeqCst);
+                let done = progress.total == Some(progress.downloaded);
+                if progress.downloaded >= last + step || done {
+                    last_reported.store(progress.downloaded, Ordering::SeqCst);
+                    events.emit(ModelEvent::DownloadProgress {
+                        model: model.clone(),
+                        file_name: progress.file_name.clone(),
+                        downloaded: progress.downloaded,
+                        total: progress.total,
+                    });
+                }
+            });
+        // Saved exactly where the config points, whatever the URL's file is called
+        downloader
+            .download(model_url, &model_full_path, config.model_config.sha256.as_deref()).await
+            .map_err(|e| ModelError::ProcessError(e.to_string()))?;
+        info!("Model {} downloaded successfully", name);
+        Ok(())
+    }
+
     pub async fn get_llm(
         self: Arc<Self>,
         model_name: &str,
         options: Option<LLMHTTPCallOptions>
     ) -> ModelResult<LLM> {
-        let config = self.registry.get_config(model_name).ok_or_else(|| {
+        let config = self.get_registry_config(model_name).ok_or_else(|| {
             error!("Model configuration not found for: {}", model_name);
             ModelError::ModelNotFound(format!("Configuration not found for model: {}", model_name))
         })?;
-        let model_path = config.model_config.model_path.clone();
-        let model_path_str = model_path
-            .to_str()
-            .ok_or_else(|| {
-                ModelError::ProcessError("Failed to convert model path to string".to_string())
-            })?;
-        let model_home = get_env_var("MODEL_HOME").unwrap_or("pyano_home/models".to_string());
-        let model_full_path = std::path::Path
-            ::new(&format!("{}/{}", model_home, model_path_str))
-            .to_path_buf();
-        if model_full_path.exists() {
-            debug!("Model {} is already present at {}", model_name, model_full_path.display());
-        } else {
-            let model_path_parts: Vec<&str> = model_path_str.split('/').collect();
-            let download_path = model_path_parts.get(0).unwrap_or(&"");
-            let model_save_path = std::path::Path
-                ::new(&format!("{}/{}", model_home, download_path))
-                .to_path_buf();
-            warn!("Model {} is not present at {}", model_name, model_full_path.display());
-            let download_if_true: bool = config.model_config.download_if_not_exist;
-            if download_if_true {
-                info!("Downloading model {}", model_name);
-                download_model_files(
-                    config.model_config.model_url.as_deref().unwrap(),
-                    model_save_path.to_str().unwrap()
-                ).await.map_err(|e| ModelError::ProcessError(e.to_string()))?;
-                info!("Model {} downloaded successfully", model_name);
-            } else {
-                warn!("Model {} is not present at the location and download_if_not_present is set to false", model_name);
-            }
-        }
+        self.ensure_model_files(&config).await?;
         let state = ModelState::new(config.clone());
 
         if options.is_none() {
@@ -365,124 +637,196 @@ impl ModelManager {
         }
 
         let processor = ModelManager::get_processor_for_model(&config);
+        let base_port = *state.port.lock().unwrap();
         // let manager: Arc<dyn ModelManagerInterface> = Arc::new(self.clone());
         Ok(
             LLM::builder()
                 .with_state(state)
                 .with_model_manager(self.clone(), config.model_config.name.to_string(), true)
                 .with_options(llm_options)
+                .with_request_queue(self.request_queue(&config))
+                .with_replica_pool(self.replica_pool(&config, base_port))
                 .with_process_response(move |stream| processor(stream))
                 .build()
         )
     }
 
-    async fn manage_memory(&self, required_gb: f32) -> ModelResult<()> {
-        // Get initial memory status
-        let initial_status = self.system_memory.get_memory_status().await;
+    /// Loaded models sized by the measured RSS of their server processes, with
+    /// the request counters the eviction policy works from.
+    async fn eviction_candidates(
+        &self,
+        models: &HashMap<String, ReplicaSet>
+    ) -> Vec<EvictionCandidate> {
+        let mut candidates = Vec::new();
+        for (name, set) in models.iter() {
+            let memory_config = &set.state().config.memory_config;
+
+            // Replicas that cannot be measured count with their configured size
+            let mut memory_gb = 0.0;
+            let mut measured = true;
+            for pid in set.process_ids() {
+                let rss_gb = match pid {
+                    Some(pid) => self.system_memory.process_rss_gb(pid).await,
+                    None => None,
+                };
+                measured &= rss_gb.is_some();
+                memory_gb += rss_gb.unwrap_or(memory_config.min_ram_gb);
+            }
 
-        if self.system_memory.has_available_memory(required_gb).await {
-            info!("Sufficient memory available ({:.1} GB required)", required_gb);
-            return Ok(());
+            let stats = self.requests.stats(name);
+            let loaded_at = *set.state().last_used.lock().unwrap();
+            candidates.push(EvictionCandidate {
+                name: name.clone(),
+                memory_gb,
+                measured,
+                last_used: stats.last_request.map_or(loaded_at, |last| last.max(loaded_at)),
+                use_count: stats.total,
+                priority: memory_config.priority,
+                in_flight: stats.in_flight,
+                pinned: memory_config.pinned,
+            });
         }
-        info!("Starting memory management for {:.1} GB", required_gb);
-        info!(
-            "Initial memory status:\n\
-             Available: {:.1} GB\n\
-             Total: {:.1} GB\n\
-             Usage: {:.1}%",
-            initial_status.available_gb,
-            initial_status.total_gb,
-            initial_status.usage_percentage
-        );
-        // Try to get models lock with detailed diagnostics
-        info!("Attempting to acquire models lock for memory management...");
-        let models_result = self.acquire_models_lock(
-            "manage_memory",
-            Duration::from_secs(10) // Increased timeout
-        ).await;
-
-        let mut models = match models_result {
-            Ok(guard) => {
-                info!("Successfully acquired models lock for memory management");
-                guard
-            }
-            Err(e) => {
-                error!("Failed to acquire models lock: {}", e);
-                return Err(e);
+        candidates
+    }
+
+    /// Explains, without unloading anything, whether a model needing
+    /// `required_gb` fits now and which models would be evicted otherwise.
+    pub async fn plan_memory(&self, required_gb: f32) -> MemoryPlan {
+        let status = self.system_memory.get_memory_status().await;
+        let models = self.models.read().await;
+        let candidates = self.eviction_candidates(&models).await;
+        MemoryPlan::build(required_gb, status, candidates, self.eviction_policy.as_ref())
+    }
+
+    /// Makes room for `required_gb`, unloading models chosen by the eviction
+    /// policy. When only busy or pinned models could free enough memory, the
+    /// load waits for them (up to the load queue timeout) instead of failing.
+    async fn manage_memory(&self, required_gb: f32) -> ModelResult<()> {
+        let deadline = tokio::time::Instant::now() + self.load_queue_timeout;
+
+        let (plan, mut models) = loop {
+            let status = self.system_memory.get_memory_status().await;
+
+            if status.usable_gb >= required_gb {
+                info!("Sufficient memory available ({:.1} GB required)", required_gb);
+                return Ok(());
             }
-        };
-        if models.is_empty() {
-            info!("No models currently loaded to unload");
-            return Err(
-                ModelError::MemoryError(
-                    "No models available to unload for freeing memory".to_string()
-                )
+            info!("Starting memory management for {:.1} GB", required_gb);
+            info!(
+                "Memory status:\n\
+                 Available: {:.1} GB (headroom {:.1} GB)\n\
+                 Total: {:.1} GB\n\
+                 Usage: {:.1}%",
+                status.available_gb,
+                status.headroom_gb,
+                status.total_gb,
+                status.usage_percentage
             );
-        }
-
-        // Convert to vec for sorting
-        let mut model_times: Vec<_> = models
-            .iter()
-            .map(|(k, v)| (k.clone(), v.state.last_used.clone()))
-            .collect();
+            // Try to get models lock with detailed diagnostics
+            info!("Attempting to acquire models lock for memory management...");
+            let models = match
+                self.acquire_models_lock("manage_memory", Duration::from_secs(10)).await
+            {
+                Ok(guard) => {
+                    info!("Successfully acquired models lock for memory management");
+                    guard
+                }
+                Err(e) => {
+                    error!("Failed to acquire models lock: {}", e);
+                    return Err(e);
+                }
+            };
+
+            let candidates = self.eviction_candidates(&models).await;
+            let plan = MemoryPlan::build(
+                required_gb,
+                status,
+                candidates,
+                self.eviction_policy.as_ref()
+            );
+            info!("Memory plan ({}): {}", plan.policy, plan.explanation);
+            if plan.satisfiable {
+                break (plan, models);
+            }
+            drop(models);
 
-        // Sort by last used time (oldest first)
-        model_times.sort_by_key(|(_k, v)| *v.lock().unwrap());
+            // Waiting only helps if a busy or pinned model may free memory
+            if plan.blocked.is_empty() || tokio::time::Instant::now() >= deadline {
+                return Err(ModelError::MemoryError(plan.explanation));
+            }
+            info!("Queueing load until {} become idle", plan.blocked.join(", "));
+            self.requests.wait_for_change(QUEUE_POLL_INTERVAL).await;
+        };
 
         // Track unloading results
         let mut freed_memory = 0.0;
         let mut unloaded_models = Vec::new();
         let mut failed_unloads = Vec::new();
 
-        // Unload models until we have enough memory
-        for (model_name, _) in model_times {
-            if let Some(process) = models.get_mut(&model_name) {
-                let model_memory = process.state.config.memory_config.min_ram_gb;
-
-                info!("Attempting to unload model: {}", model_name);
+        for candidate in &plan.evict {
+            // A request may have started since the plan was made
+            if self.requests.in_flight(&candidate.name) > 0 {
+                failed_unloads.push((candidate.name.clone(), "model became busy".to_string()));
+                continue;
+            }
+            if let Some(set) = models.get_mut(&candidate.name) {
+                info!(
+                    "Attempting to unload model: {} ({:.1} GB{})",
+                    candidate.name,
+                    candidate.memory_gb,
+                    if candidate.measured { "" } else { ", estimated" }
+                );
 
-                match process.stop().await {
+                match set.stop().await {
                     Ok(()) => {
-                        freed_memory += model_memory;
-                        unloaded_models.push(model_name.clone());
-
-                        // Remove from models map
-                        models.remove(&model_name);
+                        freed_memory += candidate.memory_gb;
+                        unloaded_models.push(candidate.name.clone());
+                        models.remove(&candidate.name);
+                        self.persist_state(&models);
+                        self.requests.reset(&candidate.name);
+                        Metrics::global().record_eviction(&candidate.name);
+                        self.events.emit(ModelEvent::Evicted {
+                            model: candidate.name.clone(),
+                            freed_gb: candidate.memory_gb,
+                            for_gb: required_gb,
+                        });
 
                         info!(
                             "Unloaded model: {} - Total freed memory: {:.1} GB",
-                            model_name,
+                            candidate.name,
                             freed_memory
                         );
-
-                        if self.system_memory.has_available_memory(required_gb).await {
-                            info!("Successfully freed enough memory");
-                            return Ok(());
-                        }
                     }
                     Err(e) => {
-                        error!("Failed to unload model {}: {}", model_name, e);
-                        failed_unloads.push((model_name.clone(), e.to_string()));
+                        error!("Failed to unload model {}: {}", candidate.name, e);
+                        failed_unloads.push((candidate.name.clone(), e.to_string()));
                     }
                 }
             }
         }
 
-        // If we get here, we couldn't free enough memory
+        // Check against what the OS reports now, not the estimate
+        if self.system_memory.has_available_memory(required_gb).await {
+            info!("Successfully freed enough memory");
+            return Ok(());
+        }
+
         let mem_status = self.system_memory.get_memory_status().await;
         Err(
             ModelError::MemoryError(
                 format!(
                     "Could not allocate enough memory ({:.1} GB required) after unloading attempt.\n\
              Memory Status:\n\
-             - Available: {:.1} GB\n\
+             - Usable: {:.1} GB (available {:.1} GB, headroom {:.1} GB)\n\
              - Total: {:.1} GB\n\
              - Usage: {:.1}%\n\
              Unloading Results:\n\
              - Successfully unloaded: {:?} (freed {:.1} GB)\n\
              - Failed to unload: {:?}",
                     required_gb,
+                    mem_status.usable_gb,
                     mem_status.available_gb,
+                    mem_status.headroom_gb,
                     mem_status.total_gb,
                     mem_status.usage_percentage,
                     unloaded_models,
@@ -493,6 +837,171 @@ impl ModelManager {
         )
     }
 
+    /// Metrics in the Prometheus text format: the counters recorded by loads
+    /// and LLM calls, plus per-model status, memory and queue gauges.
+    pub async fn render_metrics(&self) -> String {
+        let mut out = MetricsWriter::new();
+        Metrics::global().render(&mut out);
+
+        let memory = self.system_memory.get_memory_status().await;
+        let gb = 1024.0 * 1024.0 * 1024.0;
+        out.family("pyano_memory_total_bytes", "Memory visible to the manager", "gauge");
+        out.sample("pyano_memory_total_bytes", &[], (memory.total_gb as f64) * gb);
+        out.family("pyano_memory_available_bytes", "Memory available for new models", "gauge");
+        out.sample("pyano_memory_available_bytes", &[], (memory.available_gb as f64) * gb);
+
+        let models = self.models.read().await;
+        let candidates = self.eviction_candidates(&models).await;
+        out.family("pyano_model_status", "1 for the current status of each loaded model", "gauge");
+        for (name, set) in models.iter() {
+            let status = match &*set.state().status.lock().unwrap() {
+                ModelStatus::Error(_) => "error".to_string(),
+                status => format!("{:?}", status).to_lowercase(),
+            };
+            out.sample("pyano_model_status", &[("model", name), ("status", &status)], 1.0);
+        }
+        out.family("pyano_model_rss_bytes", "Resident memory of a model's servers", "gauge");
+        for candidate in candidates.iter().filter(|c| c.measured) {
+            out.sample("pyano_model_rss_bytes", &[("model", &candidate.name)], (candidate.memory_gb as f64) * gb);
+        }
+        out.family("pyano_model_requests_in_flight", "Requests being served", "gauge");
+        for candidate in &candidates {
+            out.sample("pyano_model_requests_in_flight", &[("model", &candidate.name)], candidate.in_flight as f64);
+        }
+        drop(models);
+
+        let queues = self.queue_stats();
+        out.family("pyano_queue_depth", "Requests waiting for a slot", "gauge");
+        for (name, stats) in &queues {
+            out.sample("pyano_queue_depth", &[("model", name)], stats.queued as f64);
+        }
+        out.family("pyano_queue_active", "Requests holding a slot", "gauge");
+        for (name, stats) in &queues {
+            out.sample("pyano_queue_active", &[("model", name)], stats.active as f64);
+        }
+        out.family("pyano_queue_timeouts_total", "Requests that gave up waiting for a slot", "counter");
+        for (name, stats) in &queues {
+            out.sample("pyano_queue_timeouts_total", &[("model", name)], stats.timed_out as f64);
+        }
+
+        out.finish()
+    }
+
+    /// Records the servers of every loaded model in the state file.
+    fn persist_state(&self, models: &HashMap<String, ReplicaSet>) {
+        let Some(state_file) = &self.state_file else {
+            return;
+        };
+        let servers: Vec<PersistedServer> = models
+            .values()
+            .flat_map(|set| set.persisted())
+            .collect();
+        if let Err(e) = state_file.save(&servers) {
+            warn!("Failed to save manager state to {}: {}", state_file.path().display(), e);
+        }
+    }
+
+    /// Deals with servers left running by a previous manager. Models whose
+    /// servers are all alive, healthy and started with the current registry
+    /// config are adopted as loaded; any other leftover server is killed.
+    pub async fn restore(&self) -> RestoreReport {
+        let mut report = RestoreReport::default();
+        let Some(state_file) = &self.state_file else {
+            return report;
+        };
+
+        let mut by_model: BTreeMap<String, Vec<PersistedServer>> = BTreeMap::new();
+        for server in state_file.load() {
+            by_model.entry(server.model.clone()).or_default().push(server);
+        }
+
+        let mut models = self.models.write().await;
+        for (name, mut servers) in by_model {
+            servers.sort_by_key(|server| server.replica);
+            let alive: Vec<bool> = servers
+                .iter()
+                .map(|server| server.is_alive())
+                .collect();
+            if !alive.contains(&true) {
+                info!("Servers of {} exited since the last run", name);
+                report.gone.push(name);
+                continue;
+            }
+
+            let config = self.get_registry_config(&name);
+            let mut adoptable =
+                !models.contains_key(&name) &&
+                alive.iter().all(|alive| *alive) &&
+                config.as_ref().map_or(false, |config| {
+                    let hash = config_hash(config);
+                    let base_port = config.server_config.port;
+                    servers.len() == config.server_config.replicas.max(1) &&
+                        servers.iter().enumerate().all(|(index, server)| {
+                            server.replica == index &&
+                                server.port == base_port.map(|port| port + (index as u16)) &&
+                                server.config_hash == hash
+                        })
+                });
+            for server in &servers {
+                if !adoptable {
+                    break;
+                }
+                adoptable = match server.port {
+                    Some(port) => is_server_healthy(port).await,
+                    None => false,
+                };
+            }
+
+            match config.filter(|_| adoptable) {
+                Some(config) => {
+                    info!("Adopting {} running server(s) of {}", servers.len(), name);
+                    self.request_queue(&config);
+                    let mut set = ReplicaSet::new(ModelState::new(config));
+                    set.adopt(&servers);
+                    models.insert(name.clone(), set);
+                    self.events.emit(ModelEvent::Ready { model: name.clone(), load_secs: 0.0 });
+                    report.adopted.push(name);
+                }
+                None => {
+                    for server in servers.iter().filter(|server| server.is_alive()) {
+                        warn!("Killing stale server of {} (pid {})", name, server.pid);
+                        if kill_process(server.pid).await {
+                            report.killed.push(server.pid);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.persist_state(&models);
+        report
+    }
+
+    /// Stops every loaded model, e.g. before the process exits.
+    pub async fn shutdown(&self) {
+        let mut models = self.models.write().await;
+        for (name, set) in models.iter_mut() {
+            info!("Stopping model {}", name);
+            match set.stop().await {
+                Ok(()) => self.events.emit(ModelEvent::Unloaded { model: name.clone() }),
+                Err(e) => error!("Failed to stop model {}: {}", name, e),
+            }
+        }
+        models.clear();
+        self.persist_state(&models);
+    }
+
+    /// Unloads every model on SIGINT or SIGTERM and then exits the process, so
+    /// no llama-server outlives the manager.
+    pub fn install_shutdown_hook(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            info!("Shutdown signal received, unloading models");
+            self.shutdown().await;
+            std::process::exit(0);
+        })
+    }
+
     // Add this method to help diagnose lock issues
     pub async fn debug_lock_status(&self) -> String {
         format!(
@@ -520,13 +1029,76 @@ impl ModelManager {
         debug!("Lock Event [{}]: {}", timestamp, event);
     }
 
+    pub(crate) fn get_registry_config(&self, name: &str) -> Option<ModelConfig> {
+        self.registry.read().get_config(name).cloned()
+    }
+
+    /// Re-reads `MODEL_CONFIG_DIR`. New settings apply the next time a model is
+    /// loaded; running models whose config changed are listed in `restart_required`.
+    pub async fn reload_registry(&self) -> RegistryReloadReport {
+        let running = self.running_model_names().await;
+        let report = self.registry.write().reload(&running);
+
+        info!(
+            "Registry reloaded: added {:?}, updated {:?}, removed {:?}",
+            report.added,
+            report.updated,
+            report.removed
+        );
+        for name in &report.restart_required {
+            warn!("Model {} is running with an outdated config, reload it to apply changes", name);
+        }
+        for err in &report.errors {
+            error!("Failed to load model config {}: {}", err.path.display(), err.error);
+        }
+        report
+    }
+
+    /// Runs garbage collection on the model store, never evicting models that
+    /// are loaded or loading.
+    pub async fn gc_store(&self, mut options: GcOptions) -> ModelResult<GcReport> {
+        options.keep.extend(self.running_model_names().await);
+        ModelStore::new().gc(&self.registry.read(), &options)
+    }
+
+    async fn running_model_names(&self) -> HashSet<String> {
+        let models = self.models.read().await;
+        models
+            .iter()
+            .filter(|(_, set)| {
+                matches!(
+                    *set.state().status.lock().unwrap(),
+                    ModelStatus::Running | ModelStatus::Loading
+                )
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Polls `MODEL_CONFIG_DIR` and reloads the registry whenever a config file
+    /// is added, removed or modified.
+    pub fn watch_registry(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut last_seen = self.registry.read().fingerprint();
+            loop {
+                tokio::time::sleep(interval).await;
+                let current = self.registry.read().fingerprint();
+                if current != last_seen {
+                    debug!("Change detected in model config directory");
+                    last_seen = current;
+                    self.reload_registry().await;
+                }
+            }
+        })
+    }
+
     // Add show registry function
 
     pub fn show_models(&self) {
         // get_all_models
         println!("\n\n");
         println!("Available models: ");
-        for (name, config) in self.registry.get_all_configs() {
+        for (name, config) in self.registry.read().get_all_configs() {
             println!("Model Name: {}, Type: {:?}", name, config.model_config.model_type);
         }
         println!("\n\n");
@@ -566,6 +1138,10 @@ impl ModelManagerInterface for ModelManager {
     async fn load_model_by_name(&self, name: &str) -> ModelResult<()> {
         self.load_model_by_name(name).await
     }
+
+    fn begin_request(&self, name: &str) -> Option<InFlightGuard> {
+        Some(self.requests.begin(name))
+    }
 }
 
 pub fn qwen_process_stream(
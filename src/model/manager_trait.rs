@@ -4,6 +4,7 @@ use super::state::ModelState;
 use crate::llm::options::LLMHTTPCallOptions;
 use super::types::{ ModelInfo, ModelStatus };
 use super::error::ModelResult;
+use super::requests::InFlightGuard;
 
 #[async_trait]
 pub trait ModelManagerInterface: Send + Sync {
@@ -17,4 +18,10 @@ pub trait ModelManagerInterface: Send + Sync {
         options: Option<LLMHTTPCallOptions>
     ) -> ModelResult<LLM>;
     async fn load_model_by_name(&self, name: &str) -> ModelResult<()>;
+
+    /// Marks a request to `name` as in flight until the guard is dropped, so the
+    /// model is not evicted mid-response. Remote managers do not track requests.
+    fn begin_request(&self, _name: &str) -> Option<InFlightGuard> {
+        None
+    }
 }
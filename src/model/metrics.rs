@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+use std::time::Instant;
+use parking_lot::Mutex;
+
+/// Upper bounds, in seconds, of the request latency histogram buckets.
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0,
+];
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    counts: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self { counts: [0; LATENCY_BUCKETS.len()], sum: 0.0, count: 0 }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct MetricsState {
+    loads: BTreeMap<(String, &'static str), u64>,
+    load_seconds: BTreeMap<String, f64>,
+    evictions: BTreeMap<String, u64>,
+    requests: BTreeMap<(String, &'static str), u64>,
+    tokens_predicted: BTreeMap<String, u64>,
+    tokens_prompt: BTreeMap<String, u64>,
+    tokens_per_second: BTreeMap<String, f64>,
+    latency: BTreeMap<String, Histogram>,
+}
+
+/// Counters and histograms for model loads and LLM calls, rendered in the
+/// Prometheus text format. Gauges that can be read at scrape time (status,
+/// RSS, queue depth) are added by [`ModelManager::render_metrics`](super::ModelManager::render_metrics).
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The process-wide registry used by `ModelManager` and `LLM`.
+    pub fn global() -> &'static Metrics {
+        METRICS.get_or_init(Metrics::new)
+    }
+
+    pub fn record_load(&self, model: &str, seconds: f64, ok: bool) {
+        let mut state = self.state.lock();
+        *state.loads.entry((model.to_string(), outcome(ok))).or_default() += 1;
+        if ok {
+            state.load_seconds.insert(model.to_string(), seconds);
+        }
+    }
+
+    pub fn record_eviction(&self, model: &str) {
+        *self.state.lock().evictions.entry(model.to_string()).or_default() += 1;
+    }
+
+    pub fn record_request(&self, model: &str, seconds: f64, ok: bool) {
+        let mut state = self.state.lock();
+        *state.requests.entry((model.to_string(), outcome(ok))).or_default() += 1;
+        state.latency.entry(model.to_string()).or_default().observe(seconds);
+    }
+
+    /// Token counts and generation speed from llama-server's `timings`.
+    pub fn record_timings(&self, model: &str, prompt_n: u64, predicted_n: u64, predicted_per_second: f64) {
+        let mut state = self.state.lock();
+        *state.tokens_prompt.entry(model.to_string()).or_default() += prompt_n;
+        *state.tokens_predicted.entry(model.to_string()).or_default() += predicted_n;
+        if predicted_per_second.is_finite() {
+            state.tokens_per_second.insert(model.to_string(), predicted_per_second);
+        }
+    }
+
+    /// Token totals and the last generation speed recorded for `model`.
+    pub fn token_stats(&self, model: &str) -> TokenStats {
+        let state = self.state.lock();
+        TokenStats {
+            prompt_tokens: state.tokens_prompt.get(model).copied().unwrap_or(0),
+            predicted_tokens: state.tokens_predicted.get(model).copied().unwrap_or(0),
+            tokens_per_second: state.tokens_per_second.get(model).copied(),
+        }
+    }
+
+    /// Times a request until the returned timer is dropped. It counts as
+    /// failed unless [`RequestTimer::succeed`] was called.
+    pub fn start_request(&'static self, model: &str) -> RequestTimer {
+        RequestTimer {
+            metrics: self,
+            model: model.to_string(),
+            started: Instant::now(),
+            ok: false,
+        }
+    }
+
+    /// Appends the recorded metrics to `out`.
+    pub fn render(&self, out: &mut MetricsWriter) {
+        let state = self.state.lock();
+
+        out.family("pyano_model_loads_total", "Model loads by outcome", "counter");
+        for ((model, outcome), count) in &state.loads {
+            out.sample("pyano_model_loads_total", &[("model", model), ("outcome", outcome)], *count as f64);
+        }
+        out.family("pyano_model_load_seconds", "Duration of the last successful load", "gauge");
+        for (model, seconds) in &state.load_seconds {
+            out.sample("pyano_model_load_seconds", &[("model", model)], *seconds);
+        }
+        out.family("pyano_model_evictions_total", "Models unloaded to make room for another", "counter");
+        for (model, count) in &state.evictions {
+            out.sample("pyano_model_evictions_total", &[("model", model)], *count as f64);
+        }
+
+        out.family("pyano_llm_requests_total", "LLM requests by outcome", "counter");
+        for ((model, outcome), count) in &state.requests {
+            out.sample("pyano_llm_requests_total", &[("model", model), ("outcome", outcome)], *count as f64);
+        }
+        out.family("pyano_llm_prompt_tokens_total", "Prompt tokens processed", "counter");
+        for (model, count) in &state.tokens_prompt {
+            out.sample("pyano_llm_prompt_tokens_total", &[("model", model)], *count as f64);
+        }
+        out.family("pyano_llm_predicted_tokens_total", "Tokens generated", "counter");
+        for (model, count) in &state.tokens_predicted {
+            out.sample("pyano_llm_predicted_tokens_total", &[("model", model)], *count as f64);
+        }
+        out.family("pyano_llm_tokens_per_second", "Generation speed of the last request", "gauge");
+        for (model, rate) in &state.tokens_per_second {
+            out.sample("pyano_llm_tokens_per_second", &[("model", model)], *rate);
+        }
+
+        let name = "pyano_llm_request_duration_seconds";
+        out.family(name, "LLM request latency", "histogram");
+        for (model, histogram) in &state.latency {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.counts.iter()) {
+                let le = bound.to_string();
+                out.sample(&format!("{}_bucket", name), &[("model", model), ("le", &le)], *count as f64);
+            }
+            out.sample(
+                &format!("{}_bucket", name),
+                &[("model", model), ("le", "+Inf")],
+                histogram.count as f64
+            );
+            out.sample(&format!("{}_sum", name), &[("model", model)], histogram.sum);
+            out.sample(&format!("{}_count", name), &[("model", model)], histogram.count as f64);
+        }
+    }
+}
+
+/// Token counters of one model, see [`Metrics::token_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenStats {
+    pub prompt_tokens: u64,
+    pub predicted_tokens: u64,
+    pub tokens_per_second: Option<f64>,
+}
+
+fn outcome(ok: bool) -> &'static str {
+    if ok { "ok" } else { "error" }
+}
+
+/// Records a request's latency and outcome when dropped.
+pub struct RequestTimer {
+    metrics: &'static Metrics,
+    model: String,
+    started: Instant,
+    ok: bool,
+}
+
+impl RequestTimer {
+    pub fn succeed(&mut self) {
+        self.ok = true;
+    }
+}
+
+impl Drop for RequestTimer {
+    fn drop(&mut self) {
+        self.metrics.record_request(&self.model, self.started.elapsed().as_secs_f64(), self.ok);
+    }
+}
+
+/// Builds a Prometheus text exposition.
+#[derive(Default)]
+pub struct MetricsWriter {
+    out: String,
+}
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn family(&mut self, name: &str, help: &str, kind: &str) {
+        let _ = writeln!(self.out, "# HELP {} {}", name, help);
+        let _ = writeln!(self.out, "# TYPE {} {}", name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {}", value);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.record_load("qwen", 3.5, true);
+        metrics.record_eviction("qwen");
+        metrics.record_request("qwen", 0.3, true);
+        metrics.record_request("qwen", 7.0, false);
+        metrics.record_timings("qwen", 12, 40, 25.0);
+
+        let mut out = MetricsWriter::new();
+        metrics.render(&mut out);
+        let text = out.finish();
+
+        assert!(text.contains("# TYPE pyano_model_loads_total counter"));
+        assert!(text.contains("pyano_model_loads_total{model=\"qwen\",outcome=\"ok\"} 1"));
+        assert!(text.contains("pyano_model_load_seconds{model=\"qwen\"} 3.5"));
+        assert!(text.contains("pyano_llm_requests_total{model=\"qwen\",outcome=\"error\"} 1"));
+        assert!(text.contains("pyano_llm_predicted_tokens_total{model=\"qwen\"} 40"));
+        assert!(
+            text.contains("pyano_llm_request_duration_seconds_bucket{model=\"qwen\",le=\"0.5\"} 1")
+        );
+        assert!(
+            text.contains("pyano_llm_request_duration_seconds_bucket{model=\"qwen\",le=\"+Inf\"} 2")
+        );
+        assert!(text.contains("pyano_llm_request_duration_seconds_count{model=\"qwen\"} 2"));
+
+        let tokens = metrics.token_stats("qwen");
+        assert_eq!((tokens.prompt_tokens, tokens.predicted_tokens), (12, 40));
+        assert_eq!(tokens.tokens_per_second, Some(25.0));
+    }
+
+    #[test]
+    fn test_label_escaping() {
+        let mut out = MetricsWriter::new();
+        out.sample("m", &[("model", "a\"b")], 1.0);
+        assert_eq!(out.finish(), "m{model=\"a\\\"b\"} 1\n");
+    }
+}
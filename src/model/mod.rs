@@ -10,6 +10,18 @@ pub mod manager_trait;
 pub mod adapters;
 pub mod utils;
 pub mod state;
+pub mod gguf;
+pub mod pull;
+pub mod store;
+pub mod eviction;
+pub mod requests;
+pub mod queue;
+pub mod replica;
+pub mod persistence;
+pub mod events;
+pub mod metrics;
+pub mod auth;
+pub mod estimate;
 
 mod client;
 mod server;
@@ -18,5 +30,6 @@ pub use types::*;
 pub use manager::ModelManager;
 pub use client::ModelManagerClient;
 pub use server::ModelManagerServer;
-pub use system_memory::SystemMemory;
+pub use auth::{ Role, ServerSecurity };
+pub use system_memory::{ MemoryPlan, MemoryStatus, SystemMemory };
 pub use manager_trait::ModelManagerInterface;
@@ -0,0 +1,236 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+use chrono::{ DateTime, Utc };
+use log::{ debug, warn };
+use serde::{ Deserialize, Serialize };
+use sha2::{ Digest, Sha256 };
+use sysinfo::{ Pid, ProcessesToUpdate, Signal, System };
+
+use crate::config::PyanoConfig;
+use super::error::ModelResult;
+use super::ModelConfig;
+
+/// File under the pyano home that lists the servers a manager has started.
+pub const STATE_FILE_NAME: &str = "manager_state.json";
+
+/// A llama-server started by a manager, as recorded in the state file.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PersistedServer {
+    pub model: String,
+    pub replica: usize,
+    pub pid: u32,
+    pub port: Option<u16>,
+    /// Hash of the registry config the server was started with
+    pub config_hash: String,
+    /// Process start time in seconds since the epoch, so a reused pid is not
+    /// mistaken for the server
+    pub process_start: u64,
+    pub started_at: DateTime<Utc>,
+}
+
+impl PersistedServer {
+    /// Whether the recorded process is still running.
+    pub fn is_alive(&self) -> bool {
+        process_start_time(self.pid) == Some(self.process_start)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StateFileContents {
+    servers: Vec<PersistedServer>,
+}
+
+/// What [`ModelManager::restore`](super::ModelManager::restore) did with the
+/// servers left behind by a previous manager.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RestoreReport {
+    /// Models whose servers were healthy and matched their config
+    pub adopted: Vec<String>,
+    /// Pids of stale servers that were killed
+    pub killed: Vec<u32>,
+    /// Models whose servers had already exited
+    pub gone: Vec<String>,
+}
+
+pub struct StateFile {
+    path: PathBuf,
+}
+
+impl StateFile {
+    pub fn new() -> Self {
+        Self::at(PyanoConfig::global().pyano_home.join(STATE_FILE_NAME))
+    }
+
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Servers recorded by the last manager. A missing or unreadable file
+    /// counts as empty.
+    pub fn load(&self) -> Vec<PersistedServer> {
+        let Ok(contents) = fs::read_to_string(&self.path) else {
+            return Vec::new();
+        };
+        match serde_json::from_str::<StateFileContents>(&contents) {
+            Ok(state) => state.servers,
+            Err(e) => {
+                warn!("Ignoring unreadable state file {}: {}", self.path.display(), e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Replaces the recorded servers, writing through a temporary file so a
+    /// crash never leaves a truncated state file.
+    pub fn save(&self, servers: &[PersistedServer]) -> ModelResult<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(
+            &(StateFileContents { servers: servers.to_vec() })
+        )?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)?;
+        debug!("Saved {} server(s) to {}", servers.len(), self.path.display());
+        Ok(())
+    }
+}
+
+/// Stable hash of a model config, used to tell whether a running server was
+/// started with the config currently in the registry.
+pub fn config_hash(config: &ModelConfig) -> String {
+    // Going through Value sorts map keys, so extra_args order doesn't matter
+    let value = serde_json::to_value(config).unwrap_or_default();
+    format!("{:x}", Sha256::digest(value.to_string().as_bytes()))
+}
+
+/// Start time of a running process in seconds since the epoch.
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let mut sys = System::new();
+    let pid = Pid::from_u32(pid);
+    sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    sys.process(pid).map(|process| process.start_time())
+}
+
+/// How long a process gets to exit after SIGTERM before it is killed.
+const KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// Asks a process to terminate and sends SIGKILL if it is still running after
+/// a grace period. Returns whether the process is gone.
+pub async fn kill_process(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let running = |sys: &mut System| {
+        sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+        sys.process(pid).is_some()
+    };
+    let mut sys = System::new();
+    if !running(&mut sys) {
+        return false;
+    }
+    let terminated = sys
+        .process(pid)
+        .and_then(|process| process.kill_with(Signal::Term))
+        .unwrap_or(false);
+    if terminated {
+        let deadline = tokio::time::Instant::now() + KILL_GRACE;
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if !running(&mut sys) {
+                return true;
+            }
+        }
+    }
+    match sys.process(pid) {
+        Some(process) => {
+            process.kill();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            !running(&mut sys)
+        }
+        None => true,
+    }
+}
+
+/// Whether a llama-server answers its health endpoint on `port`.
+pub async fn is_server_healthy(port: u16) -> bool {
+    let client = reqwest::Client::new();
+    match
+        client
+            .get(format!("http://localhost:{}/health", port))
+            .timeout(Duration::from_secs(2))
+            .send().await
+    {
+        Ok(response) => response.status().is_success(),
+        Err(_) => false,
+    }
+}
+
+/// Resolves on SIGINT, or SIGTERM on Unix.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{ signal, SignalKind };
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!("Cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_file_round_trip() {
+        let path = std::env::temp_dir().join(
+            format!("pyano-state-{}/{}", std::process::id(), STATE_FILE_NAME)
+        );
+        let file = StateFile::at(path.clone());
+        assert!(file.load().is_empty());
+
+        let server = PersistedServer {
+            model: "qwen".to_string(),
+            replica: 0,
+            pid: std::process::id(),
+            port: Some(52555),
+            config_hash: config_hash(&ModelConfig::default()),
+            process_start: process_start_time(std::process::id()).unwrap(),
+            started_at: Utc::now(),
+        };
+        file.save(&[server.clone()]).unwrap();
+        assert_eq!(file.load(), vec![server.clone()]);
+        // The test process itself is alive; a different start time is not
+        assert!(server.is_alive());
+        assert!(!(PersistedServer { process_start: 1, ..server }).is_alive());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_config_hash_tracks_changes() {
+        let config = ModelConfig::default();
+        let mut changed = config.clone();
+        assert_eq!(config_hash(&config), config_hash(&changed));
+
+        changed.server_config.ctx_size *= 2;
+        assert_ne!(config_hash(&config), config_hash(&changed));
+    }
+}
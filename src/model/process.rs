@@ -5,21 +5,30 @@ use tokio::sync::oneshot;
 use log::{ debug, error, info };
 use super::adapters::llama::LlamaProcess;
 use super::ModelStatus;
+use super::persistence::{ kill_process, process_start_time };
 use super::state::ModelState;
 use reqwest::Client;
 use super::error::{ ModelError, ModelResult };
+use std::collections::VecDeque;
+use std::io::{ BufRead, BufReader, Read };
 use std::process::{ Child, Stdio };
-// use std::io::{ BufReader, BufRead };
-// use std::thread;
+use std::sync::{ Arc, Mutex };
+use std::thread;
 
 const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(2);
 const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+// Server output lines kept per process for `logs`
+const LOG_CAPACITY: usize = 1000;
 
 pub(crate) struct ModelProcess {
     pub state: ModelState,
     pub child: Option<Child>,
     pub shutdown_signal: Option<oneshot::Sender<()>>,
     pub model_process: Option<Box<LlamaProcess>>,
+    pub logs: Arc<Mutex<VecDeque<String>>>,
+    /// Start time of a server adopted from a previous manager, so `stop`
+    /// doesn't kill another process that got the same pid
+    pub adopted_start: Option<u64>,
 }
 
 impl ModelProcess {
@@ -29,8 +38,33 @@ impl ModelProcess {
             child: None,
             shutdown_signal: None,
             model_process: None,
+            logs: Arc::new(Mutex::new(VecDeque::with_capacity(LOG_CAPACITY))),
+            adopted_start: None,
         }
     }
+
+    /// The last `lines` lines the server printed, oldest first. Empty for
+    /// servers adopted from a previous manager.
+    pub fn recent_logs(&self, lines: usize) -> Vec<String> {
+        let logs = self.logs.lock().unwrap();
+        logs.iter().skip(logs.len().saturating_sub(lines)).cloned().collect()
+    }
+
+    // Drains a pipe into the log buffer so the server never blocks on a full pipe
+    fn capture_output(&self, output: impl Read + Send + 'static) {
+        let logs = Arc::clone(&self.logs);
+        let name = self.state.config.model_config.name.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines().map_while(Result::ok) {
+                debug!("[{}] {}", name, line);
+                let mut logs = logs.lock().unwrap();
+                if logs.len() == LOG_CAPACITY {
+                    logs.pop_front();
+                }
+                logs.push_back(line);
+            }
+        });
+    }
     async fn check_health(&mut self, port: u16) -> bool {
         let client = Client::new();
         let url = format!("http://localhost:{}/health", port);
@@ -85,7 +119,10 @@ impl ModelProcess {
         info!("Starting model {}", self.state.config.model_config.name);
         *self.state.status.lock().unwrap() = ModelStatus::Loading;
         self.model_process = Some(Box::new(LlamaProcess::new(self.state.clone())));
-        self.model_process.as_mut().unwrap().getcmd().await;
+        if let Err(e) = self.model_process.as_mut().unwrap().getcmd().await {
+            *self.state.status.lock().unwrap() = ModelStatus::Error(e.to_string());
+            return Err(e);
+        }
 
         let cmd = self.model_process.as_mut().unwrap().cmd.as_mut().unwrap();
 
@@ -95,33 +132,15 @@ impl ModelProcess {
 
         debug!("Starting model with command: {:?}", cmd);
         match self.model_process.as_mut().unwrap().cmd.as_mut().unwrap().spawn() {
-            Ok(child) => {
-                // Capture stdout in a separate thread
-                // if let Some(stdout) = child.stdout.take() {
-                //     thread::spawn(move || {
-                //         let reader = BufReader::new(stdout);
-                //         for line in reader.lines() {
-                //             if let Ok(line) = line {
-                //                 // Log the output instead of printing to stdout
-                //                 // You can also store it in a buffer if needed
-                //                 info!("Model output: {}", line);
-                //             }
-                //         }
-                //     });
-                // }
-
-                // Capture stderr in a separate thread
-                // if let Some(stderr) = child.stderr.take() {
-                //     thread::spawn(move || {
-                //         let reader = BufReader::new(stderr);
-                //         for line in reader.lines() {
-                //             if let Ok(line) = line {
-                //                 // Log the error output
-                //                 error!("Model error: {}", line);
-                //             }
-                //         }
-                //     });
-                // }
+            Ok(mut child) => {
+                // llama-server logs to stderr; keep both streams
+                if let Some(stdout) = child.stdout.take() {
+                    self.capture_output(stdout);
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    self.capture_output(stderr);
+                }
+                *self.state.process_id.lock().unwrap() = Some(child.id());
                 self.child = Some(child);
 
                 // Get port from state or configuration
@@ -157,6 +176,7 @@ impl ModelProcess {
         }
     }
     pub async fn stop(&mut self) -> ModelResult<()> {
+        let pid = *self.state.process_id.lock().unwrap();
         if let Some(mut child) = self.child.take() {
             let pid = child.id();
 
@@ -179,9 +199,21 @@ impl ModelProcess {
             unsafe {
                 libc::kill(pid as i32, libc::SIGKILL);
             }
+        } else if let Some(pid) = pid {
+            // Adopted from a previous manager, so there is no child handle
+            let name = &self.state.config.model_config.name;
+            match self.adopted_start.take() {
+                Some(start) if process_start_time(pid) == Some(start) => {
+                    if !kill_process(pid).await {
+                        error!("Failed to stop server of {} (pid {})", name, pid);
+                    }
+                }
+                _ => debug!("Server of {} (pid {}) has already exited", name, pid),
+            }
         }
 
         *self.state.status.lock().unwrap() = ModelStatus::Stopped;
+        *self.state.process_id.lock().unwrap() = None;
         self.child = None;
 
         Ok(())
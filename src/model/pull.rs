@@ -0,0 +1,262 @@
+use std::path::{ Path, PathBuf };
+use log::{ info, warn };
+
+use crate::config::PyanoConfig;
+use crate::tools::downloader::{ select_quant, Downloader, HuggingFaceHub };
+use super::error::{ ModelError, ModelResult };
+use super::gguf::GgufMetadata;
+use super::{
+    ModelConfig,
+    ModelDefaults,
+    ModelMemoryConfig,
+    ModelSpecificConfig,
+    ModelType,
+    PromptTemplate,
+    ServerConfig,
+};
+
+// Context size written into generated configs when the model supports more
+const DEFAULT_MAX_CTX: usize = 8192;
+
+#[derive(Debug, Clone)]
+pub struct PullOptions {
+    pub quant: String,
+    pub revision: String,
+    /// Hub or mirror base URL; `HF_ENDPOINT` or huggingface.co when unset
+    pub endpoint: Option<String>,
+    /// Registry name for the model; defaults to `<repo>-<quant>`
+    pub name: Option<String>,
+    /// Overwrite an existing config file with the same name
+    pub force: bool,
+}
+
+impl PullOptions {
+    pub fn new(quant: &str) -> Self {
+        Self {
+            quant: quant.to_string(),
+            revision: "main".to_string(),
+            endpoint: None,
+            name: None,
+            force: false,
+        }
+    }
+}
+
+/// Result of [`pull_model`].
+#[derive(Debug, Clone)]
+pub struct PulledModel {
+    pub name: String,
+    pub files: Vec<PathBuf>,
+    pub config_path: PathBuf,
+    pub config: ModelConfig,
+}
+
+/// Downloads the GGUF file(s) for `options.quant` from a Hugging Face repo and
+/// writes a registry config for it into the model config directory.
+pub async fn pull_model(
+    repo: &str,
+    options: &PullOptions,
+    downloader: &Downloader
+) -> ModelResult<PulledModel> {
+    let mut hub = HuggingFaceHub::new();
+    if let Some(endpoint) = &options.endpoint {
+        hub = hub.with_endpoint(endpoint);
+    }
+
+    // Gated repos need the token for the files as well as for the listing
+    let downloader = match hub.token() {
+        Some(token) => downloader.clone().with_bearer_token(token),
+        None => downloader.clone(),
+    };
+
+    let files = hub.list_files(repo, &options.revision).await?;
+    let selected = select_quant(&files, &options.quant)?;
+
+    let repo_name = repo
+        .rsplit('/')
+        .next()
+        .unwrap_or(repo)
+        .trim_end_matches("-GGUF")
+        .trim_end_matches("-gguf");
+    let quant = options.quant.to_uppercase();
+    let relative_dir = Path::new(repo_name).join(&quant);
+    let save_dir = PyanoConfig::global().model_home.join(&relative_dir);
+
+    info!("Pulling {} ({} file(s)) into {}", repo, selected.len(), save_dir.display());
+
+    let mut downloaded = Vec::new();
+    for file in &selected {
+        let file_name = Path::new(&file.path)
+            .file_name()
+            .ok_or_else(|| ModelError::InvalidConfig(format!("Bad file path: {}", file.path)))?;
+        let url = hub.file_url(repo, &options.revision, &file.path);
+        let path = downloader.download(&url, &save_dir.join(file_name), file.sha256.as_deref()).await?;
+        downloaded.push(path);
+    }
+
+    // llama.cpp opens split models through their first shard
+    let first = &selected[0];
+    let metadata = GgufMetadata::read(&downloaded[0])?;
+    let name = options.name.clone().unwrap_or_else(|| format!("{}-{}", repo_name, quant));
+    let total_bytes: u64 = selected
+        .iter()
+        .map(|file| file.size)
+        .sum();
+
+    // Auto-download fetches a single URL, so split models only record their path
+    let model_url = (selected.len() == 1).then(|| {
+        hub.file_url(repo, &options.revision, &first.path)
+    });
+    let config = build_config(
+        &name,
+        relative_dir.join(downloaded[0].file_name().unwrap_or_default()),
+        model_url,
+        first.sha256.clone(),
+        total_bytes,
+        &metadata
+    );
+
+    let config_dir = &PyanoConfig::global().model_config_dir;
+    std::fs::create_dir_all(config_dir)?;
+    let config_path = config_dir.join(format!("{}.json", name));
+    if config_path.exists() && !options.force {
+        warn!("Config {} already exists, leaving it untouched", config_path.display());
+    } else {
+        std::fs::write(&config_path, serde_json::to_string_pretty(&config)?)?;
+        info!("Wrote model config {}", config_path.display());
+    }
+
+    Ok(PulledModel {
+        name,
+        files: downloaded,
+        config_path,
+        config,
+    })
+}
+
+/// Registry config for a downloaded GGUF, with the prompt template, kind and
+/// context size taken from the file's metadata. Without a `model_url` the
+/// config doesn't download missing files.
+pub fn build_config(
+    name: &str,
+    model_path: PathBuf,
+    model_url: Option<String>,
+    sha256: Option<String>,
+    size_bytes: u64,
+    metadata: &GgufMetadata
+) -> ModelConfig {
+    let size_gb = (size_bytes as f32) / (1024.0 * 1024.0 * 1024.0);
+    let round = |gb: f32| (gb * 10.0).round() / 10.0;
+
+    let ctx_size = metadata
+        .context_length()
+        .map_or(DEFAULT_MAX_CTX, |ctx| (ctx as usize).min(DEFAULT_MAX_CTX));
+
+    let model_kind = match metadata.architecture() {
+        Some(arch) if arch.starts_with("qwen") => "Qwen".to_string(),
+        Some(arch) if arch.starts_with("llama") => "LLaMA".to_string(),
+        Some(arch) if arch.starts_with("mistral") => "Mistral".to_string(),
+        Some(arch) => arch.to_string(),
+        None => "LLaMA".to_string(),
+    };
+
+    ModelConfig {
+        model_config: ModelSpecificConfig {
+            name: name.to_string(),
+            model_path,
+            model_type: ModelType::Text,
+            model_kind,
+            download_if_not_exist: model_url.is_some(),
+            model_url,
+            sha256,
+        },
+        memory_config: ModelMemoryConfig {
+            // Weights plus room for the KV cache and compute buffers
+            min_ram_gb: round(size_gb * 1.2),
+            recommended_ram_gb: round(size_gb * 1.5),
+            gpu_memory_gb: None,
+            priority: 0,
+            pinned: false,
+        },
+        prompt_template: PromptTemplate {
+            template: prompt_template_for(metadata.chat_template()),
+            required_keys: vec!["system_prompt".to_string(), "user_prompt".to_string()],
+        },
+        defaults: ModelDefaults {
+            temperature: 0.7,
+            top_p: 0.9,
+            top_k: 40,
+            max_tokens: 2048,
+            repetition_penalty: 1.1,
+        },
+        server_config: ServerConfig {
+            ctx_size,
+            ..ServerConfig::default()
+        },
+    }
+}
+
+/// Maps a Jinja chat template onto the `{system_prompt}`/`{user_prompt}`
+/// template format used by `LLM`, by recognising its special tokens.
+pub fn prompt_template_for(chat_template: Option<&str>) -> String {
+    let chat_template = chat_template.unwrap_or_default();
+
+    if chat_template.contains("<|im_start|>") {
+        "<|im_start|>system\n{system_prompt}<|im_end|>\n<|im_start|>user\n{user_prompt}<|im_end|>\n<|im_start|>assistant\n".to_string()
+    } else if chat_template.contains("<|start_header_id|>") {
+        "<|begin_of_text|><|start_header_id|>system<|end_header_id|>\n\n{system_prompt}<|eot_id|><|start_header_id|>user<|end_header_id|>\n\n{user_prompt}<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n".to_string()
+    } else if chat_template.contains("<｜User｜>") {
+        "<｜begin▁of▁sentence｜>{system_prompt}<｜User｜>{user_prompt}<｜Assistant｜>".to_string()
+    } else if chat_template.contains("<|start_of_role|>") {
+        "<|start_of_role|>system<|end_of_role|>{system_prompt}<|end_of_text|>\n<|start_of_role|>user<|end_of_role|>{user_prompt}<|end_of_text|>\n<|start_of_role|>assistant<|end_of_role|>".to_string()
+    } else if chat_template.contains("[INST]") {
+        "[INST] {system_prompt}\n\n{user_prompt} [/INST]".to_string()
+    } else {
+        "{system_prompt}\n\n{user_prompt}\n".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::gguf::GgufValue;
+
+    #[test]
+    fn test_build_config_from_metadata() {
+        let mut metadata = GgufMetadata::default();
+        metadata.values.insert("general.architecture".into(), GgufValue::String("qwen2".into()));
+        metadata.values.insert("qwen2.context_length".into(), GgufValue::UInt(32768));
+        metadata.values.insert(
+            "tokenizer.chat_template".into(),
+            GgufValue::String("{% for m in messages %}<|im_start|>{{ m.role }}".into())
+        );
+
+        let config = build_config(
+            "Qwen2.5-7B-Instruct-Q4_K_M",
+            PathBuf::from("Qwen2.5-7B-Instruct/Q4_K_M/Qwen2.5-7B-Instruct-Q4_K_M.gguf"),
+            Some("https://huggingface.co/x/resolve/main/x.gguf".to_string()),
+            None,
+            4 * 1024 * 1024 * 1024,
+            &metadata
+        );
+
+        assert_eq!(config.model_config.model_kind, "Qwen");
+        assert_eq!(config.server_config.ctx_size, DEFAULT_MAX_CTX);
+        assert!(config.prompt_template.template.starts_with("<|im_start|>system"));
+        assert!(config.prompt_template.template.contains("{user_prompt}"));
+        assert_eq!(config.memory_config.min_ram_gb, 4.8);
+        assert!(config.model_config.download_if_not_exist);
+
+        // Split models are opened through their first shard but not auto-downloaded
+        let split = build_config(
+            "Qwen2.5-72B-Instruct-Q4_K_M",
+            PathBuf::from("Qwen2.5-72B-Instruct/Q4_K_M/x-00001-of-00002.gguf"),
+            None,
+            None,
+            40 * 1024 * 1024 * 1024,
+            &metadata
+        );
+        assert_eq!(split.model_config.model_url, None);
+        assert!(!split.model_config.download_if_not_exist);
+    }
+}
@@ -0,0 +1,266 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+use parking_lot::Mutex;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::oneshot;
+
+/// Queue depth and wait metrics for one model.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueueStats {
+    pub slots: usize,
+    pub active: usize,
+    pub queued: usize,
+    /// Highest queue depth seen
+    pub peak_queued: usize,
+    pub served: u64,
+    pub timed_out: u64,
+    pub avg_wait_ms: f64,
+}
+
+struct Waiter {
+    priority: i32,
+    seq: u64,
+    tx: oneshot::Sender<()>,
+}
+
+// Highest priority first, then first come first served
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl Eq for Waiter {}
+
+struct QueueState {
+    slots: usize,
+    active: usize,
+    waiters: BinaryHeap<Waiter>,
+    next_seq: u64,
+    default_timeout: Option<Duration>,
+    stats: QueueStats,
+    total_wait: Duration,
+}
+
+/// Fair async queue in front of a model server with a fixed number of
+/// concurrent request slots, matching llama-server's `--parallel`.
+pub struct RequestQueue {
+    state: Mutex<QueueState>,
+}
+
+/// Error returned when a request waited longer than its queue timeout.
+#[derive(Debug, Clone)]
+pub struct QueueTimeout {
+    pub waited: Duration,
+    pub queued_ahead: usize,
+}
+
+impl RequestQueue {
+    pub fn new(slots: usize, default_timeout: Option<Duration>) -> Self {
+        let slots = slots.max(1);
+        Self {
+            state: Mutex::new(QueueState {
+                slots,
+                active: 0,
+                waiters: BinaryHeap::new(),
+                next_seq: 0,
+                default_timeout,
+                stats: QueueStats { slots, ..QueueStats::default() },
+                total_wait: Duration::ZERO,
+            }),
+        }
+    }
+
+    /// Changes the number of slots and the default timeout, e.g. after the
+    /// model is restarted with a different config. Requests already running
+    /// keep their slot; waiting ones keep the timeout they started with.
+    pub fn resize(&self, slots: usize, default_timeout: Option<Duration>) {
+        let mut state = self.state.lock();
+        state.default_timeout = default_timeout;
+        state.slots = slots.max(1);
+        state.stats.slots = state.slots;
+        Self::dispatch(&mut state);
+    }
+
+    /// Waits for a free slot. Higher `priority` requests are served first and
+    /// equal priorities in arrival order. `timeout` falls back to the queue's
+    /// default; `None` for both waits indefinitely.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        priority: i32,
+        timeout: Option<Duration>
+    ) -> Result<SlotPermit, QueueTimeout> {
+        let start = Instant::now();
+        let (seq, mut rx, timeout) = {
+            let mut state = self.state.lock();
+            let timeout = timeout.or(state.default_timeout);
+            if state.active < state.slots && state.waiters.is_empty() {
+                state.active += 1;
+                Self::record_start(&mut state, Duration::ZERO);
+                return Ok(SlotPermit { queue: Arc::clone(self) });
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiters.push(Waiter { priority, seq, tx });
+            state.stats.queued = state.waiters.len();
+            state.stats.peak_queued = state.stats.peak_queued.max(state.stats.queued);
+            (seq, rx, timeout)
+        };
+
+        let received = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, &mut rx).await.ok(),
+            None => Some((&mut rx).await),
+        };
+
+        if let Some(Ok(())) = received {
+            let mut state = self.state.lock();
+            Self::record_start(&mut state, start.elapsed());
+            return Ok(SlotPermit { queue: Arc::clone(self) });
+        }
+
+        // Timed out: leave the queue, or hand back a slot granted in the meantime
+        let queued_ahead = {
+            let mut state = self.state.lock();
+            let before = state.waiters.len();
+            state.waiters.retain(|waiter| waiter.seq != seq);
+            let removed = state.waiters.len() < before;
+            state.stats.queued = state.waiters.len();
+            state.stats.timed_out += 1;
+
+            if !removed && rx.try_recv().is_ok() {
+                state.active -= 1;
+                Self::dispatch(&mut state);
+            }
+            state.waiters
+                .iter()
+                .filter(|waiter| waiter.seq < seq)
+                .count()
+        };
+
+        Err(QueueTimeout { waited: start.elapsed(), queued_ahead })
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let state = self.state.lock();
+        QueueStats {
+            active: state.active,
+            queued: state.waiters.len(),
+            ..state.stats.clone()
+        }
+    }
+
+    fn record_start(state: &mut QueueState, waited: Duration) {
+        state.stats.served += 1;
+        state.total_wait += waited;
+        state.stats.avg_wait_ms =
+            (state.total_wait.as_secs_f64() * 1000.0) / (state.stats.served as f64);
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock();
+        state.active -= 1;
+        Self::dispatch(&mut state);
+    }
+
+    // Hands free slots to waiters; waiters that gave up are skipped
+    fn dispatch(state: &mut QueueState) {
+        while state.active < state.slots {
+            let Some(waiter) = state.waiters.pop() else {
+                break;
+            };
+            if waiter.tx.send(()).is_ok() {
+                state.active += 1;
+            }
+        }
+        state.stats.queued = state.waiters.len();
+    }
+}
+
+/// A request slot, returned to the queue when dropped.
+pub struct SlotPermit {
+    queue: Arc<RequestQueue>,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_priority_then_fifo() {
+        let queue = Arc::new(RequestQueue::new(1, None));
+        let first = queue.acquire(0, None).await.unwrap();
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (name, priority) in [("low-1", 0), ("low-2", 0), ("high", 5)] {
+            let queue = Arc::clone(&queue);
+            let order = Arc::clone(&order);
+            handles.push(
+                tokio::spawn(async move {
+                    let _permit = queue.acquire(priority, None).await.unwrap();
+                    order.lock().push(name);
+                })
+            );
+            // Let each task enqueue before the next one
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(queue.stats().queued, 3);
+        drop(first);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(*order.lock(), vec!["high", "low-1", "low-2"]);
+
+        let stats = queue.stats();
+        assert_eq!(stats.served, 4);
+        assert_eq!(stats.peak_queued, 3);
+        assert_eq!(stats.active, 0);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_leaves_queue() {
+        let queue = Arc::new(RequestQueue::new(1, Some(Duration::from_millis(50))));
+        let held = queue.acquire(0, None).await.unwrap();
+
+        let err = queue.acquire(0, None).await.err().unwrap();
+        assert!(err.waited >= Duration::from_millis(50));
+        assert_eq!(queue.stats().queued, 0);
+        assert_eq!(queue.stats().timed_out, 1);
+
+        drop(held);
+        let again = queue.acquire(0, Some(Duration::from_millis(10))).await.unwrap();
+
+        // A changed default applies to later requests
+        queue.resize(1, None);
+        let waiting = {
+            let queue = Arc::clone(&queue);
+            tokio::spawn(async move { queue.acquire(0, None).await.is_ok() })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(again);
+        assert!(waiting.await.unwrap());
+    }
+}
@@ -0,0 +1,425 @@
+use std::sync::atomic::{ AtomicU64, AtomicUsize, Ordering };
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+use chrono::Utc;
+use log::{ error, info, warn };
+use parking_lot::{ Mutex, RwLock };
+use serde::{ Deserialize, Serialize };
+
+use super::error::ModelResult;
+use super::events::{ EventBus, ModelEvent };
+use super::persistence::{ config_hash, process_start_time, PersistedServer };
+use super::process::ModelProcess;
+use super::state::ModelState;
+use super::{ ModelConfig, ModelStatus };
+
+/// How long a replica that failed a request is skipped before being retried.
+pub const DEFAULT_UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(10);
+
+/// One llama-server replica that requests can be sent to.
+pub struct ReplicaEndpoint {
+    pub index: usize,
+    pub url: String,
+    outstanding: AtomicUsize,
+    served: AtomicU64,
+    failures: AtomicU64,
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl ReplicaEndpoint {
+    fn new(index: usize, url: String) -> Self {
+        Self {
+            index,
+            url,
+            outstanding: AtomicUsize::new(0),
+            served: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            unhealthy_until: Mutex::new(None),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        let until = *self.unhealthy_until.lock();
+        until.map_or(true, |until| Instant::now() >= until)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicaStats {
+    pub index: usize,
+    pub url: String,
+    pub outstanding: usize,
+    pub served: u64,
+    pub failures: u64,
+    pub healthy: bool,
+}
+
+/// The endpoints of a model's replicas, balanced by least outstanding requests.
+/// Replicas that fail are skipped for a cooldown period, so requests fail over
+/// to the remaining ones.
+pub struct ReplicaPool {
+    endpoints: RwLock<Vec<Arc<ReplicaEndpoint>>>,
+    cooldown: Duration,
+    events: Option<HealthEvents>,
+}
+
+// Where health transitions of a pool's replicas are reported
+#[derive(Clone)]
+struct HealthEvents {
+    model: String,
+    bus: EventBus,
+}
+
+impl ReplicaPool {
+    pub fn new(urls: Vec<String>) -> Self {
+        let pool = Self {
+            endpoints: RwLock::new(Vec::new()),
+            cooldown: DEFAULT_UNHEALTHY_COOLDOWN,
+            events: None,
+        };
+        pool.set_urls(urls);
+        pool
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Emits `HealthChanged` events for `model` when a replica fails or recovers.
+    pub fn with_events(mut self, model: &str, bus: EventBus) -> Self {
+        self.events = Some(HealthEvents { model: model.to_string(), bus });
+        self
+    }
+
+    /// Replaces the endpoints if the URLs changed, e.g. after the replica count
+    /// or base port in the model's config changed.
+    pub fn set_urls(&self, urls: Vec<String>) {
+        let mut endpoints = self.endpoints.write();
+        let current: Vec<&str> = endpoints
+            .iter()
+            .map(|e| e.url.as_str())
+            .collect();
+        if current == urls.iter().map(String::as_str).collect::<Vec<_>>() {
+            return;
+        }
+        *endpoints = urls
+            .into_iter()
+            .enumerate()
+            .map(|(index, url)| Arc::new(ReplicaEndpoint::new(index, url)))
+            .collect();
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Picks the healthy replica with the fewest outstanding requests, skipping
+    /// indexes in `exclude`. When every replica is unhealthy the least loaded
+    /// one is returned anyway, since a stale mark is better than no attempt.
+    pub fn pick(&self, exclude: &[usize]) -> Option<ReplicaLease> {
+        let endpoints = self.endpoints.read();
+        let candidates: Vec<&Arc<ReplicaEndpoint>> = endpoints
+            .iter()
+            .filter(|e| !exclude.contains(&e.index))
+            .collect();
+        let load = |e: &&&Arc<ReplicaEndpoint>| e.outstanding.load(Ordering::SeqCst);
+
+        let chosen = candidates
+            .iter()
+            .filter(|e| e.is_healthy())
+            .min_by_key(load)
+            .or_else(|| candidates.iter().min_by_key(load))?;
+
+        chosen.outstanding.fetch_add(1, Ordering::SeqCst);
+        chosen.served.fetch_add(1, Ordering::SeqCst);
+        Some(ReplicaLease {
+            endpoint: Arc::clone(*chosen),
+            cooldown: self.cooldown,
+            events: self.events.clone(),
+        })
+    }
+
+    pub fn stats(&self) -> Vec<ReplicaStats> {
+        self.endpoints
+            .read()
+            .iter()
+            .map(|e| ReplicaStats {
+                index: e.index,
+                url: e.url.clone(),
+                outstanding: e.outstanding.load(Ordering::SeqCst),
+                served: e.served.load(Ordering::SeqCst),
+                failures: e.failures.load(Ordering::SeqCst),
+                healthy: e.is_healthy(),
+            })
+            .collect()
+    }
+}
+
+/// A request routed to one replica; counts as outstanding until dropped.
+pub struct ReplicaLease {
+    endpoint: Arc<ReplicaEndpoint>,
+    cooldown: Duration,
+    events: Option<HealthEvents>,
+}
+
+impl ReplicaLease {
+    pub fn url(&self) -> &str {
+        &self.endpoint.url
+    }
+
+    pub fn index(&self) -> usize {
+        self.endpoint.index
+    }
+
+    /// Takes the replica out of rotation for the pool's cooldown.
+    pub fn mark_failed(&self) {
+        self.endpoint.failures.fetch_add(1, Ordering::SeqCst);
+        let was_healthy = self.endpoint.is_healthy();
+        *self.endpoint.unhealthy_until.lock() = Some(Instant::now() + self.cooldown);
+        warn!("Replica {} marked unhealthy for {:?}", self.endpoint.url, self.cooldown);
+        if was_healthy {
+            self.emit_health(false);
+        }
+    }
+
+    /// Puts the replica back into rotation after it served a request.
+    pub fn mark_healthy(&self) {
+        if self.endpoint.unhealthy_until.lock().take().is_some() {
+            self.emit_health(true);
+        }
+    }
+
+    fn emit_health(&self, healthy: bool) {
+        if let Some(events) = &self.events {
+            events.bus.emit(ModelEvent::HealthChanged {
+                model: events.model.clone(),
+                replica: self.endpoint.index,
+                healthy,
+            });
+        }
+    }
+}
+
+impl Drop for ReplicaLease {
+    fn drop(&mut self) {
+        self.endpoint.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Server URLs of a model's replicas. Replica `i` listens on the configured
+/// port plus `i`. Empty without a port, so requests use the LLM's own port.
+pub fn replica_urls(config: &ModelConfig) -> Vec<String> {
+    config.server_config
+        .port_range()
+        .map(|ports| ports.map(|port| format!("http://localhost:{}", port)).collect())
+        .unwrap_or_default()
+}
+
+/// The llama-server processes serving one registry model.
+pub(crate) struct ReplicaSet {
+    /// Config the set was created from, before per-replica ports and CPU ranges
+    pub config: ModelConfig,
+    pub replicas: Vec<ModelProcess>,
+}
+
+impl ReplicaSet {
+    /// Replica 0 runs with `state` itself; the others get a copy of its config
+    /// on the following ports. Each replica can get its own CPU range.
+    pub fn new(state: ModelState) -> Self {
+        let config = state.config.clone();
+        let count = config.server_config.replicas.max(1);
+        let mut replicas = Vec::with_capacity(count);
+
+        for index in 0..count {
+            let server_config = &state.config.server_config;
+            let cpu_range = server_config.replica_cpu_ranges.get(index).cloned();
+
+            let replica_state = if index == 0 {
+                // Shares its runtime state with the caller, so status changes are visible
+                let mut replica_state = state.clone();
+                replica_state.config.server_config.cpu_range = cpu_range.or(
+                    replica_state.config.server_config.cpu_range
+                );
+                replica_state
+            } else {
+                let mut config = state.config.clone();
+                let base_port = *state.port.lock().unwrap();
+                let port = base_port.and_then(|port| port.checked_add(u16::try_from(index).ok()?));
+                config.server_config.port = port;
+                config.server_config.cpu_range = cpu_range.or(config.server_config.cpu_range);
+
+                let replica_state = ModelState::new(config);
+                *replica_state.model_path.lock().unwrap() = state.model_path.lock().unwrap().clone();
+                *replica_state.draft_model_path.lock().unwrap() = state.draft_model_path
+                    .lock()
+                    .unwrap()
+                    .clone();
+                replica_state
+            };
+            replicas.push(ModelProcess::new(replica_state));
+        }
+
+        Self { config, replicas }
+    }
+
+    /// Takes over servers started by a previous manager, one per replica in
+    /// replica order.
+    pub fn adopt(&mut self, servers: &[PersistedServer]) {
+        for (replica, server) in self.replicas.iter_mut().zip(servers) {
+            *replica.state.process_id.lock().unwrap() = Some(server.pid);
+            replica.adopted_start = Some(server.process_start);
+            *replica.state.status.lock().unwrap() = ModelStatus::Running;
+            *replica.state.last_used.lock().unwrap() = Utc::now();
+        }
+    }
+
+    /// State file entries for the running replicas.
+    pub fn persisted(&self) -> Vec<PersistedServer> {
+        let hash = config_hash(&self.config);
+        self.replicas
+            .iter()
+            .enumerate()
+            .filter_map(|(index, replica)| {
+                let pid = (*replica.state.process_id.lock().unwrap())?;
+                Some(PersistedServer {
+                    model: self.config.model_config.name.clone(),
+                    replica: index,
+                    pid,
+                    port: *replica.state.port.lock().unwrap(),
+                    config_hash: hash.clone(),
+                    process_start: process_start_time(pid)?,
+                    started_at: *replica.state.last_used.lock().unwrap(),
+                })
+            })
+            .collect()
+    }
+
+    /// State of the first replica, which stands for the whole set.
+    pub fn state(&self) -> &ModelState {
+        &self.replicas[0].state
+    }
+
+    /// Starts every replica. If any of them fails, the ones already started
+    /// are stopped again so the set is either fully up or not at all.
+    pub async fn start(&mut self) -> ModelResult<()> {
+        for index in 0..self.replicas.len() {
+            if let Err(e) = self.replicas[index].start().await {
+                error!("Replica {} of {} failed to start: {}", index, self.name(), e);
+                for started in self.replicas[..index].iter_mut() {
+                    let _ = started.stop().await;
+                }
+                // The set reports the failure through its first replica's state
+                *self.replicas[0].state.status.lock().unwrap() = ModelStatus::Error(e.to_string());
+                return Err(e);
+            }
+        }
+        if self.replicas.len() > 1 {
+            info!("Started {} replicas of {}", self.replicas.len(), self.name());
+        }
+        Ok(())
+    }
+
+    pub async fn stop(&mut self) -> ModelResult<()> {
+        let mut result = Ok(());
+        for replica in self.replicas.iter_mut() {
+            if let Err(e) = replica.stop().await {
+                error!("Failed to stop a replica of {}: {}", replica.state.config.model_config.name, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    pub fn process_ids(&self) -> Vec<Option<u32>> {
+        self.replicas
+            .iter()
+            .map(|replica| *replica.state.process_id.lock().unwrap())
+            .collect()
+    }
+
+    /// Recent server output of every replica, prefixed with the replica index
+    /// when there is more than one.
+    pub fn logs(&self, lines: usize) -> Vec<String> {
+        if self.replicas.len() == 1 {
+            return self.replicas[0].recent_logs(lines);
+        }
+        self.replicas
+            .iter()
+            .enumerate()
+            .flat_map(|(index, replica)| {
+                replica
+                    .recent_logs(lines)
+                    .into_iter()
+                    .map(move |line| format!("[replica {}] {}", index, line))
+            })
+            .collect()
+    }
+
+    fn name(&self) -> &str {
+        &self.state().config.model_config.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> ReplicaPool {
+        ReplicaPool::new(
+            vec!["http://localhost:9000".to_string(), "http://localhost:9001".to_string()]
+        )
+    }
+
+    #[test]
+    fn test_least_outstanding() {
+        let pool = pool();
+        let first = pool.pick(&[]).unwrap();
+        let second = pool.pick(&[]).unwrap();
+        assert_ne!(first.index(), second.index());
+
+        drop(first);
+        let third = pool.pick(&[]).unwrap();
+        assert_ne!(third.index(), second.index());
+    }
+
+    #[test]
+    fn test_failover_skips_unhealthy() {
+        let pool = pool();
+        let lease = pool.pick(&[]).unwrap();
+        let failed = lease.index();
+        lease.mark_failed();
+        drop(lease);
+
+        for _ in 0..3 {
+            assert_ne!(pool.pick(&[]).unwrap().index(), failed);
+        }
+
+        // With every other replica excluded the unhealthy one is still tried
+        let other = 1 - failed;
+        assert_eq!(pool.pick(&[other]).unwrap().index(), failed);
+        assert!(pool.pick(&[0, 1]).is_none());
+
+        let stats = pool.stats();
+        assert_eq!(stats[failed].failures, 1);
+        assert!(!stats[failed].healthy);
+    }
+
+    #[test]
+    fn test_replica_urls() {
+        let mut config = ModelConfig::default();
+        config.server_config.port = Some(52555);
+        config.server_config.replicas = 3;
+        assert_eq!(
+            replica_urls(&config),
+            vec!["http://localhost:52555", "http://localhost:52556", "http://localhost:52557"]
+        );
+
+        config.server_config.port = None;
+        assert!(replica_urls(&config).is_empty());
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{ DateTime, Utc };
+use parking_lot::Mutex;
+use serde::{ Deserialize, Serialize };
+use tokio::sync::Notify;
+
+/// Request counters for one model.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestStats {
+    pub in_flight: usize,
+    /// Requests started since the model was last loaded
+    pub total: u64,
+    pub last_request: Option<DateTime<Utc>>,
+}
+
+/// Tracks requests per model so eviction can skip models that are serving,
+/// and so queued loads can wake up when a request finishes.
+#[derive(Default)]
+pub struct RequestTracker {
+    stats: Mutex<HashMap<String, RequestStats>>,
+    changed: Notify,
+}
+
+impl RequestTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks a request as started. It counts as in flight until the returned
+    /// guard is dropped.
+    pub fn begin(self: &Arc<Self>, model: &str) -> InFlightGuard {
+        {
+            let mut stats = self.stats.lock();
+            let entry = stats.entry(model.to_string()).or_default();
+            entry.in_flight += 1;
+            entry.total += 1;
+            entry.last_request = Some(Utc::now());
+        }
+        InFlightGuard {
+            tracker: Arc::clone(self),
+            model: model.to_string(),
+        }
+    }
+
+    fn end(&self, model: &str) {
+        if let Some(entry) = self.stats.lock().get_mut(model) {
+            entry.in_flight = entry.in_flight.saturating_sub(1);
+        }
+        self.notify();
+    }
+
+    pub fn stats(&self, model: &str) -> RequestStats {
+        self.stats.lock().get(model).cloned().unwrap_or_default()
+    }
+
+    pub fn in_flight(&self, model: &str) -> usize {
+        self.stats(model).in_flight
+    }
+
+    /// Clears the request count of an unloaded model, keeping requests that
+    /// are still in flight.
+    pub fn reset(&self, model: &str) {
+        if let Some(entry) = self.stats.lock().get_mut(model) {
+            entry.total = 0;
+        }
+        self.notify();
+    }
+
+    /// Wakes tasks blocked in [`RequestTracker::wait_for_change`].
+    pub fn notify(&self) {
+        self.changed.notify_waiters();
+    }
+
+    /// Waits until a request finishes or a model is unloaded, or `timeout` passes.
+    pub async fn wait_for_change(&self, timeout: Duration) {
+        let _ = tokio::time::timeout(timeout, self.changed.notified()).await;
+    }
+}
+
+/// Keeps a request counted as in flight while it is alive.
+pub struct InFlightGuard {
+    tracker: Arc<RequestTracker>,
+    model: String,
+}
+
+impl InFlightGuard {
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.tracker.end(&self.model);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guard_tracks_in_flight() {
+        let tracker = Arc::new(RequestTracker::new());
+        let first = tracker.begin("qwen");
+        let second = tracker.begin("qwen");
+        assert_eq!(tracker.in_flight("qwen"), 2);
+
+        drop(first);
+        assert_eq!(tracker.in_flight("qwen"), 1);
+        drop(second);
+
+        let stats = tracker.stats("qwen");
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.total, 2);
+        assert!(stats.last_request.is_some());
+
+        tracker.reset("qwen");
+        assert_eq!(tracker.stats("qwen").total, 0);
+    }
+}
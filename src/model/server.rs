@@ -4,55 +4,162 @@ use axum::{
     routing::{ get, post },
     Router,
     Json,
-    extract::State,
-    response::IntoResponse,
-    http::StatusCode,
+    extract::{ DefaultBodyLimit, Extension, Query, State },
+    middleware,
+    response::{ sse::{ Event, KeepAlive, Sse }, IntoResponse },
+    http::{ header, StatusCode },
 };
+use std::convert::Infallible;
 use std::net::SocketAddr;
+use futures::{ stream, Stream };
+use tokio::sync::broadcast::error::RecvError;
 use tokio::net::TcpListener;
+use log::warn;
+use serde::Deserialize;
 use serde_json::json;
 use crate::model::state::ModelState;
 
 use crate::model::error::{ ModelError, ModelResult };
+use super::auth::{ require_role, Role, ServerSecurity };
+use super::manager_trait::ModelManagerInterface;
 use super::{ ModelConfig, ModelManager };
 
+#[derive(Deserialize)]
+struct MemoryPlanQuery {
+    required_gb: f32,
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+    #[serde(default = "default_log_lines")]
+    lines: usize,
+}
+
+fn default_log_lines() -> usize {
+    100
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    /// Only stream events for this model
+    model: Option<String>,
+}
+
 pub struct ModelManagerServer {
     manager: Arc<ModelManager>,
+    security: ServerSecurity,
+    preload: Vec<String>,
 }
 
 impl ModelManagerServer {
     pub fn new(manager: Arc<ModelManager>) -> Self {
-        Self { manager }
+        Self { manager, security: ServerSecurity::default(), preload: Vec::new() }
+    }
+
+    /// Registry models to load in the background once the server starts.
+    pub fn with_preload(mut self, models: Vec<String>) -> Self {
+        self.preload = models;
+        self
+    }
+
+    /// API keys, load allowlist, body size limit and TLS.
+    pub fn with_security(mut self, security: ServerSecurity) -> Self {
+        self.security = security;
+        self
     }
 
     pub async fn run(self, addr: &str) -> ModelResult<()> {
-        let app = Router::new()
+        // Pick up servers left by a previous run and unload everything on exit
+        let report = self.manager.restore().await;
+        if !report.adopted.is_empty() || !report.killed.is_empty() {
+            println!("Adopted models {:?}, killed stale servers {:?}", report.adopted, report.killed);
+        }
+        Arc::clone(&self.manager).install_shutdown_hook();
+        if !self.preload.is_empty() {
+            let manager = Arc::clone(&self.manager);
+            let preload = self.preload;
+            tokio::spawn(async move {
+                for name in preload {
+                    if let Err(e) = ModelManagerInterface::load_model_by_name(manager.as_ref(), &name).await {
+                        warn!("Failed to preload {}: {}", name, e);
+                    }
+                }
+            });
+        }
+
+        let security = Arc::new(self.security);
+        let read = Router::new()
+            .route("/models/list", get(Self::handle_list_models))
+            .route("/models/status/:name", get(Self::handle_get_status))
+            .route("/models/logs/:name", get(Self::handle_get_logs))
+            .route("/memory/plan", get(Self::handle_plan_memory))
+            .route("/models/queues", get(Self::handle_queue_stats))
+            .route("/models/replicas", get(Self::handle_replica_stats))
+            .route("/events", get(Self::handle_events))
+            .route("/metrics", get(Self::handle_metrics))
+            .route_layer(
+                middleware::from_fn_with_state((Arc::clone(&security), Role::Read), require_role)
+            );
+        let admin = Router::new()
             .route("/models/load", post(Self::handle_load_model))
+            .route("/models/load/:name", post(Self::handle_load_model_by_name))
             .route("/models/unload", post(Self::handle_unload_model))
-            .route("/models/status/:name", get(Self::handle_get_status))
-            // .route("/models/list", get(Self::handle_list_models))
+            .route("/models/registry/reload", post(Self::handle_reload_registry))
+            .route_layer(
+                middleware::from_fn_with_state((Arc::clone(&security), Role::Admin), require_role)
+            );
+        let app = read
+            .merge(admin)
+            .layer(DefaultBodyLimit::max(security.max_body_bytes))
+            .layer(Extension(Arc::clone(&security)))
             .with_state(self.manager);
 
-        println!("Model Manager server starting on {}", addr);
-
         // Parse the address
         let addr: SocketAddr = addr
             .parse()
             .map_err(|e| ModelError::ConfigError(format!("Invalid address: {}", e)))?;
+        if !security.auth_enabled() && !addr.ip().is_loopback() {
+            warn!("Serving on {} without API keys; anyone who can reach it can start processes", addr);
+        }
 
-        // Create the listener
-        let listener = TcpListener::bind(addr).await.map_err(|e| ModelError::IoError(e))?;
-
-        // Start the server
-        axum::serve(listener, app).await.map_err(|e| ModelError::IoError(e))?;
+        match &security.tls {
+            None => {
+                println!("Model Manager server starting on http://{}", addr);
+                let listener = TcpListener::bind(addr).await.map_err(|e| ModelError::IoError(e))?;
+                axum::serve(listener, app).await.map_err(|e| ModelError::IoError(e))?;
+            }
+            #[cfg(feature = "tls")]
+            Some(tls) => {
+                let config = axum_server::tls_rustls::RustlsConfig
+                    ::from_pem_file(&tls.cert_path, &tls.key_path).await
+                    .map_err(|e| ModelError::ConfigError(format!("Invalid TLS files: {}", e)))?;
+                println!("Model Manager server starting on https://{}", addr);
+                axum_server
+                    ::bind_rustls(addr, config)
+                    .serve(app.into_make_service()).await
+                    .map_err(|e| ModelError::IoError(e))?;
+            }
+            #[cfg(not(feature = "tls"))]
+            Some(_) => {
+                return Err(
+                    ModelError::ConfigError("pyano was built without the `tls` feature".to_string())
+                );
+            }
+        }
 
         Ok(())
     }
 
+    /// Loads a config sent in the body if the load policy allows it.
     async fn handle_load_model(
         State(manager): State<Arc<ModelManager>>,
+        Extension(security): Extension<Arc<ServerSecurity>>,
         Json(config): Json<ModelConfig>
     ) -> impl IntoResponse {
+        let registered = manager.get_registry_config(&config.model_config.name);
+        if let Err(e) = security.load_policy.check(&config, registered.as_ref()) {
+            return e.into_response();
+        }
         match manager.load_model(ModelState::new(config)).await {
             Ok(()) => (StatusCode::OK, Json(())).into_response(),
             Err(e) =>
@@ -63,6 +170,23 @@ impl ModelManagerServer {
         }
     }
 
+    /// Loads a model from the registry.
+    async fn handle_load_model_by_name(
+        State(manager): State<Arc<ModelManager>>,
+        axum::extract::Path(name): axum::extract::Path<String>
+    ) -> impl IntoResponse {
+        match ModelManagerInterface::load_model_by_name(manager.as_ref(), &name).await {
+            Ok(()) => (StatusCode::OK, Json(())).into_response(),
+            Err(ModelError::ModelNotFound(e)) =>
+                (StatusCode::NOT_FOUND, Json(json!({ "error": e }))).into_response(),
+            Err(e) =>
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": e.to_string() })),
+                ).into_response(),
+        }
+    }
+
     async fn handle_unload_model(
         State(manager): State<Arc<ModelManager>>,
         Json(name): Json<String>
@@ -91,14 +215,91 @@ impl ModelManagerServer {
         }
     }
 
-    // async fn handle_list_models(State(manager): State<Arc<ModelManager>>) -> impl IntoResponse {
-    //     match manager.list_models().await {
-    //         Ok(models) => (StatusCode::OK, Json(models)).into_response(),
-    //         Err(e) =>
-    //             (
-    //                 StatusCode::INTERNAL_SERVER_ERROR,
-    //                 Json(json!({ "error": e.to_string() })),
-    //             ).into_response(),
-    //     }
-    // }
+    async fn handle_reload_registry(
+        State(manager): State<Arc<ModelManager>>
+    ) -> impl IntoResponse {
+        let report = manager.reload_registry().await;
+        (StatusCode::OK, Json(report)).into_response()
+    }
+
+    async fn handle_plan_memory(
+        State(manager): State<Arc<ModelManager>>,
+        Query(query): Query<MemoryPlanQuery>
+    ) -> impl IntoResponse {
+        let plan = manager.plan_memory(query.required_gb).await;
+        (StatusCode::OK, Json(plan)).into_response()
+    }
+
+    async fn handle_queue_stats(State(manager): State<Arc<ModelManager>>) -> impl IntoResponse {
+        (StatusCode::OK, Json(manager.queue_stats())).into_response()
+    }
+
+    async fn handle_replica_stats(State(manager): State<Arc<ModelManager>>) -> impl IntoResponse {
+        (StatusCode::OK, Json(manager.replica_stats())).into_response()
+    }
+
+    async fn handle_metrics(State(manager): State<Arc<ModelManager>>) -> impl IntoResponse {
+        (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            manager.render_metrics().await,
+        ).into_response()
+    }
+
+    /// Streams lifecycle events as server-sent events, named after their type.
+    async fn handle_events(
+        State(manager): State<Arc<ModelManager>>,
+        Query(query): Query<EventsQuery>
+    ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let receiver = manager.subscribe();
+        let events = stream::unfold(receiver, move |mut receiver| {
+            let model = query.model.clone();
+            async move {
+                loop {
+                    let event = match receiver.recv().await {
+                        Ok(event) => event,
+                        // A slow client misses events rather than stalling the bus
+                        Err(RecvError::Lagged(_)) => {
+                            continue;
+                        }
+                        Err(RecvError::Closed) => {
+                            return None;
+                        }
+                    };
+                    if model.as_deref().map_or(false, |model| model != event.event.model()) {
+                        continue;
+                    }
+                    let data = serde_json::to_value(&event).unwrap_or_default();
+                    let sse = Event::default()
+                        .event(data["type"].as_str().unwrap_or("event"))
+                        .data(data.to_string());
+                    return Some((Ok(sse), receiver));
+                }
+            }
+        });
+        Sse::new(events).keep_alive(KeepAlive::default())
+    }
+
+    async fn handle_list_models(State(manager): State<Arc<ModelManager>>) -> impl IntoResponse {
+        match manager.list_models().await {
+            Ok(models) => (StatusCode::OK, Json(models)).into_response(),
+            Err(e) =>
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({ "error": e.to_string() })),
+                ).into_response(),
+        }
+    }
+
+    async fn handle_get_logs(
+        State(manager): State<Arc<ModelManager>>,
+        axum::extract::Path(name): axum::extract::Path<String>,
+        Query(query): Query<LogsQuery>
+    ) -> impl IntoResponse {
+        match manager.model_logs(&name, query.lines).await {
+            Ok(lines) => (StatusCode::OK, Json(lines)).into_response(),
+            Err(e) =>
+                (StatusCode::NOT_FOUND, Json(json!({ "error": e.to_string() }))).into_response(),
+        }
+    }
 }
@@ -11,6 +11,8 @@ pub struct ModelState {
     // Model Confguratons
     pub model_path: Arc<Mutex<PathBuf>>,
     pub model_url: Arc<Mutex<std::option::Option<std::string::String>>>,
+    // Draft model file for speculative decoding, relative to model_home
+    pub draft_model_path: Arc<Mutex<Option<PathBuf>>>,
 
     //Dyanmic Memory Configurations (Only to be changed when n advance user settng)
     pub min_ram_usage: Arc<Mutex<f32>>,
@@ -32,6 +34,7 @@ pub struct ModelState {
 
     // Process management
     pub process_id: Arc<Mutex<Option<u32>>>,
+    pub llama_build: Arc<Mutex<Option<u32>>>,
 }
 
 impl Default for ModelState {
@@ -41,6 +44,7 @@ impl Default for ModelState {
             config: ModelConfig::default(),
             model_path: Arc::new(Mutex::new(PathBuf::new())),
             model_url: Arc::new(Mutex::new(None)),
+            draft_model_path: Arc::new(Mutex::new(None)),
             min_ram_usage: Arc::new(Mutex::new(0.0)),
             recommended_ram_gb: Arc::new(Mutex::new(0.0)),
             gpu_memory_gb: Arc::new(Mutex::new(None)),
@@ -54,6 +58,7 @@ impl Default for ModelState {
             port: Arc::new(Mutex::new(None)),
             server_url: Arc::new(Mutex::new(None)),
             process_id: Arc::new(Mutex::new(None)),
+            llama_build: Arc::new(Mutex::new(None)),
         }
     }
 }
@@ -64,6 +69,7 @@ impl ModelState {
             config: config.clone(),
             model_path: Arc::new(Mutex::new(config.model_config.model_path.clone())),
             model_url: Arc::new(Mutex::new(config.model_config.model_url.clone())),
+            draft_model_path: Arc::new(Mutex::new(None)),
             min_ram_usage: Arc::new(Mutex::new(config.memory_config.min_ram_gb)),
             recommended_ram_gb: Arc::new(Mutex::new(config.memory_config.recommended_ram_gb)),
             gpu_memory_gb: Arc::new(Mutex::new(config.memory_config.gpu_memory_gb)),
@@ -81,6 +87,7 @@ impl ModelState {
             status: Arc::new(Mutex::new(ModelStatus::Stopped)),
             last_used: Arc::new(Mutex::new(Utc::now())),
             process_id: Arc::new(Mutex::new(None)),
+            llama_build: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -114,5 +121,6 @@ impl ModelState {
         println!("Status: {:?}", status);
         println!("Last Used: {:?}", self.last_used.lock().unwrap());
         println!("Process ID: {:?}", self.process_id.lock().unwrap());
+        println!("llama.cpp Build: {:?}", self.llama_build.lock().unwrap());
     }
 }
@@ -0,0 +1,788 @@
+use std::collections::{ BTreeMap, HashMap, HashSet };
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::time::{ Duration, SystemTime };
+use chrono::{ DateTime, Utc };
+use log::{ debug, info, warn };
+use regex::Regex;
+use serde::{ Deserialize, Serialize };
+
+use crate::config::PyanoConfig;
+use crate::embedding::embedding_models::EmbeddingModels;
+use crate::tools::downloader::sha256_file;
+use super::config_loader::ModelRegistry;
+use super::error::{ ModelError, ModelResult };
+use super::ModelConfig;
+
+// Partial downloads younger than this may still be in progress
+const DEFAULT_PARTIAL_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreEntryKind {
+    Gguf,
+    /// A directory holding one embedding model's files
+    Embedding,
+    /// A `.part`/`.partN` file left by an interrupted download, or the
+    /// `.parts` chunk plan next to them
+    Partial,
+    Other,
+}
+
+/// A file (or embedding model directory) found in the model store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreEntry {
+    pub path: PathBuf,
+    pub kind: StoreEntryKind,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Registry or embedding models that use this entry
+    pub models: Vec<String>,
+}
+
+impl StoreEntry {
+    pub fn is_orphan(&self) -> bool {
+        self.models.is_empty() && self.kind != StoreEntryKind::Partial
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub total: u64,
+    pub gguf: u64,
+    pub embeddings: u64,
+    pub partial: u64,
+    pub other: u64,
+    pub orphaned: u64,
+    pub per_model: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum VerifyStatus {
+    Ok,
+    /// The file is present but the config has no `sha256` to check against
+    NoChecksum,
+    Missing,
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+    /// Embedding model directory without some of its required files
+    Incomplete {
+        missing: Vec<String>,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub name: String,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub status: VerifyStatus,
+}
+
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// Evict least recently modified models until the store fits in this many bytes
+    pub budget_bytes: Option<u64>,
+    /// Models that must never be evicted, e.g. the ones currently running
+    pub keep: HashSet<String>,
+    pub dry_run: bool,
+    /// Partial downloads are only removed once they are older than this
+    pub partial_max_age: Duration,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            budget_bytes: None,
+            keep: HashSet::new(),
+            dry_run: false,
+            partial_max_age: DEFAULT_PARTIAL_MAX_AGE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GcReport {
+    pub removed: Vec<PathBuf>,
+    pub evicted_models: Vec<String>,
+    pub freed_bytes: u64,
+    pub remaining_bytes: u64,
+    pub dry_run: bool,
+}
+
+/// Index of the files under `MODEL_HOME` and the embedding model directory,
+/// matched against registry entries so orphans and stale downloads can be found.
+pub struct ModelStore {
+    model_home: PathBuf,
+    embed_home: PathBuf,
+}
+
+impl Default for ModelStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModelStore {
+    pub fn new() -> Self {
+        let embed_home = EmbeddingModels::all()
+            .first()
+            .and_then(|model| model.model_dir().parent()?.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        Self::with_dirs(PyanoConfig::global().model_home.clone(), embed_home)
+    }
+
+    pub fn with_dirs(model_home: PathBuf, embed_home: PathBuf) -> Self {
+        Self { model_home, embed_home }
+    }
+
+    pub fn model_home(&self) -> &Path {
+        &self.model_home
+    }
+
+    /// Every file the store knows about, largest first.
+    pub fn list(&self, registry: &ModelRegistry) -> ModelResult<Vec<StoreEntry>> {
+        let references = self.references(registry);
+        let part_re = Regex::new(r"\.part(s|\d*)$").unwrap();
+
+        let mut files = Vec::new();
+        if self.model_home.is_dir() {
+            walk(&self.model_home, &self.embed_home, &mut files)?;
+        }
+
+        let mut entries = Vec::new();
+        for path in files {
+            let metadata = fs::metadata(&path)?;
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+            let (kind, owner) = if part_re.is_match(&file_name) {
+                let dest = path.with_file_name(part_re.replace(&file_name, "").to_string());
+                (StoreEntryKind::Partial, dest)
+            } else if file_name.to_lowercase().ends_with(".gguf") {
+                (StoreEntryKind::Gguf, path.clone())
+            } else {
+                (StoreEntryKind::Other, path.clone())
+            };
+
+            entries.push(StoreEntry {
+                models: references.get(&owner).cloned().unwrap_or_default(),
+                kind,
+                size: metadata.len(),
+                modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+                path,
+            });
+        }
+
+        entries.extend(self.embedding_entries()?);
+        entries.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        Ok(entries)
+    }
+
+    pub fn du(&self, registry: &ModelRegistry) -> ModelResult<DiskUsage> {
+        let mut usage = DiskUsage::default();
+        for entry in self.list(registry)? {
+            usage.total += entry.size;
+            match entry.kind {
+                StoreEntryKind::Gguf => {
+                    usage.gguf += entry.size;
+                }
+                StoreEntryKind::Embedding => {
+                    usage.embeddings += entry.size;
+                }
+                StoreEntryKind::Partial => {
+                    usage.partial += entry.size;
+                }
+                StoreEntryKind::Other => {
+                    usage.other += entry.size;
+                }
+            }
+            if entry.is_orphan() {
+                usage.orphaned += entry.size;
+            }
+            for model in &entry.models {
+                *usage.per_model.entry(model.clone()).or_default() += entry.size;
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Deletes every file belonging to a registry or embedding model, including
+    /// its partial downloads. The registry config itself is left in place.
+    pub fn remove(&self, registry: &ModelRegistry, name: &str) -> ModelResult<Vec<PathBuf>> {
+        let entries: Vec<StoreEntry> = self
+            .list(registry)?
+            .into_iter()
+            .filter(|entry| entry.models.iter().any(|model| model == name))
+            .collect();
+        if entries.is_empty() {
+            return Err(ModelError::ModelNotFound(format!("No downloaded files for model {}", name)));
+        }
+
+        let mut removed = Vec::new();
+        for entry in entries {
+            self.remove_entry(&entry)?;
+            removed.push(entry.path);
+        }
+        info!("Removed {} file(s) of model {}", removed.len(), name);
+        Ok(removed)
+    }
+
+    /// Checks registry models against their configured `sha256` and embedding
+    /// models for missing files. `name` limits the check to one model.
+    pub async fn verify(&self, registry: &ModelRegistry, name: Option<&str>) -> Vec<VerifyReport> {
+        let mut reports = Vec::new();
+
+        let mut configs: Vec<(&String, &ModelConfig)> = registry
+            .get_all_configs()
+            .into_iter()
+            .filter(|(model, _)| name.map_or(true, |n| n == model.as_str()))
+            .collect();
+        configs.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (model, config) in configs {
+            let path = self.model_home.join(&config.model_config.model_path);
+            let status = if !path.is_file() {
+                VerifyStatus::Missing
+            } else {
+                match &config.model_config.sha256 {
+                    None => VerifyStatus::NoChecksum,
+                    Some(expected) => {
+                        debug!("Hashing {}", path.display());
+                        match sha256_file(&path).await {
+                            Ok(actual) if actual.eq_ignore_ascii_case(expected) =>
+                                VerifyStatus::Ok,
+                            Ok(actual) =>
+                                VerifyStatus::Mismatch {
+                                    expected: expected.clone(),
+                                    actual,
+                                },
+                            Err(e) => VerifyStatus::Error { error: e.to_string() },
+                        }
+                    }
+                }
+            };
+            reports.push(VerifyReport { name: model.clone(), path, status });
+        }
+
+        for model in EmbeddingModels::all() {
+            if name.map_or(false, |n| n != model.model_name()) {
+                continue;
+            }
+            let dir = self.embedding_dir(&model);
+            if !dir.is_dir() {
+                // Embedding models are only downloaded on first use
+                if name.is_some() {
+                    reports.push(VerifyReport {
+                        name: model.model_name().to_string(),
+                        path: dir,
+                        status: VerifyStatus::Missing,
+                    });
+                }
+                continue;
+            }
+            let missing: Vec<String> = model
+                .required_files()
+                .iter()
+                .filter(|file| !dir.join(file).is_file())
+                .map(|file| file.to_string())
+                .collect();
+            let status = if missing.is_empty() {
+                VerifyStatus::Ok
+            } else {
+                VerifyStatus::Incomplete { missing }
+            };
+            reports.push(VerifyReport { name: model.model_name().to_string(), path: dir, status });
+        }
+
+        reports
+    }
+
+    /// Removes orphaned files and stale partial downloads, then, if a budget is
+    /// set, evicts whole models oldest first until the store fits in it.
+    /// Refuses to run while any model config failed to load, since the files
+    /// of those models would look orphaned. Flat embedding installs are moved
+    /// into their model's directory first.
+    pub fn gc(&self, registry: &ModelRegistry, options: &GcOptions) -> ModelResult<GcReport> {
+        if let Some(error) = registry.load_errors().first() {
+            return Err(
+                ModelError::ConfigError(
+                    format!(
+                        "{} model config(s) failed to load, not collecting garbage ({}: {})",
+                        registry.load_errors().len(),
+                        error.path.display(),
+                        error.error
+                    )
+                )
+            );
+        }
+        if !options.dry_run {
+            self.migrate_embeddings();
+        }
+        let entries = self.list(registry)?;
+        let now = SystemTime::now();
+        let total: u64 = entries
+            .iter()
+            .map(|entry| entry.size)
+            .sum();
+
+        let mut to_remove: Vec<&StoreEntry> = Vec::new();
+        let mut evicted_models = Vec::new();
+        for entry in &entries {
+            let removable = match entry.kind {
+                StoreEntryKind::Gguf | StoreEntryKind::Embedding => entry.is_orphan(),
+                StoreEntryKind::Partial => {
+                    let age = entry.modified
+                        .and_then(|modified| now.duration_since(modified.into()).ok())
+                        .unwrap_or_default();
+                    // The config of a pull is written after its download, so a
+                    // fresh partial without a model may still be in progress
+                    age >= options.partial_max_age
+                }
+                StoreEntryKind::Other => false,
+            };
+            if removable {
+                to_remove.push(entry);
+            }
+        }
+
+        let mut remaining =
+            total -
+            to_remove
+                .iter()
+                .map(|entry| entry.size)
+                .sum::<u64>();
+
+        if let Some(budget) = options.budget_bytes {
+            // Group what is left by model, so shards are evicted together
+            let mut groups: HashMap<&str, Vec<&StoreEntry>> = HashMap::new();
+            for entry in &entries {
+                if to_remove.iter().any(|removed| removed.path == entry.path) {
+                    continue;
+                }
+                if entry.models.iter().any(|model| options.keep.contains(model)) {
+                    continue;
+                }
+                if let Some(model) = entry.models.first() {
+                    groups.entry(model.as_str()).or_default().push(entry);
+                }
+            }
+            let mut groups: Vec<(&str, Vec<&StoreEntry>)> = groups.into_iter().collect();
+            groups.sort_by_key(|(model, group)| {
+                (group.iter().filter_map(|entry| entry.modified).max(), model.to_string())
+            });
+
+            for (model, group) in groups {
+                if remaining <= budget {
+                    break;
+                }
+                remaining -= group
+                    .iter()
+                    .map(|entry| entry.size)
+                    .sum::<u64>();
+                evicted_models.push(model.to_string());
+                to_remove.extend(group);
+            }
+
+            if remaining > budget {
+                warn!(
+                    "Store still uses {} bytes after gc, above the {} byte budget",
+                    remaining,
+                    budget
+                );
+            }
+        }
+
+        let mut report = GcReport {
+            evicted_models,
+            remaining_bytes: remaining,
+            dry_run: options.dry_run,
+            ..GcReport::default()
+        };
+        for entry in to_remove {
+            if !options.dry_run {
+                self.remove_entry(entry)?;
+            }
+            report.freed_bytes += entry.size;
+            report.removed.push(entry.path.clone());
+        }
+
+        info!(
+            "Store gc {}removed {} entries, freeing {} bytes",
+            if options.dry_run { "would have " } else { "" },
+            report.removed.len(),
+            report.freed_bytes
+        );
+        Ok(report)
+    }
+
+    /// Files each registry model points at. Split models only name their first
+    /// shard in the config, so the remaining shards are added here.
+    fn references(&self, registry: &ModelRegistry) -> HashMap<PathBuf, Vec<String>> {
+        let shard_re = Regex::new(r"^(.*)-00001-of-(\d{5})\.gguf$").unwrap();
+        let mut references: HashMap<PathBuf, Vec<String>> = HashMap::new();
+
+        for (name, config) in registry.get_all_configs() {
+            let path = self.model_home.join(&config.model_config.model_path);
+            let mut paths = vec![path.clone()];
+
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            if let Some(caps) = shard_re.captures(&file_name) {
+                let count: usize = caps[2].parse().unwrap_or(1);
+                for index in 2..=count {
+                    paths.push(
+                        path.with_file_name(format!("{}-{:05}-of-{}.gguf", &caps[1], index, &caps[2]))
+                    );
+                }
+            }
+
+            for path in paths {
+                references.entry(path).or_default().push(name.clone());
+            }
+        }
+
+        for names in references.values_mut() {
+            names.sort();
+        }
+        references
+    }
+
+    fn embedding_dir(&self, model: &EmbeddingModels) -> PathBuf {
+        self.embed_home.join(model.category()).join(model.model_name())
+    }
+
+    /// Moves embedding models installed straight into their category directory
+    /// into a directory of their own, as `EmbeddingBuilder` does on startup.
+    fn migrate_embeddings(&self) {
+        for model in EmbeddingModels::all() {
+            let category_dir = self.embed_home.join(model.category());
+            if !category_dir.is_dir() {
+                continue;
+            }
+            if let Err(e) = model.migrate_flat_install(&category_dir) {
+                warn!("Cannot move {} into its own directory: {}", model.model_name(), e);
+            }
+        }
+    }
+
+    /// One entry per embedding model directory. Files at the top level of a
+    /// category, e.g. a flat install `gc` hasn't migrated yet, are listed on
+    /// their own and never removed by `gc`.
+    fn embedding_entries(&self) -> ModelResult<Vec<StoreEntry>> {
+        let models = EmbeddingModels::all();
+        let known: HashMap<PathBuf, String> = models
+            .iter()
+            .map(|model| (self.embedding_dir(model), model.model_name().to_string()))
+            .collect();
+
+        let mut entries = Vec::new();
+        for category in ["text", "image"] {
+            let category_dir = self.embed_home.join(category);
+            if !category_dir.is_dir() {
+                continue;
+            }
+            let mut paths = Vec::new();
+            for dir_entry in fs::read_dir(&category_dir)? {
+                paths.push(dir_entry?.path());
+            }
+            // Directories next to leftover top-level files may belong to them
+            let flat_leftovers = paths.iter().any(|path| path.is_file());
+
+            for path in paths {
+                if path.is_file() {
+                    let metadata = fs::metadata(&path)?;
+                    entries.push(StoreEntry {
+                        models: Vec::new(),
+                        kind: StoreEntryKind::Other,
+                        size: metadata.len(),
+                        modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+                        path,
+                    });
+                    continue;
+                }
+                let mut files = Vec::new();
+                walk(&path, Path::new(""), &mut files)?;
+
+                let mut size = 0;
+                let mut modified = None;
+                for file in &files {
+                    let metadata = fs::metadata(file)?;
+                    size += metadata.len();
+                    modified = modified.max(metadata.modified().ok().map(DateTime::<Utc>::from));
+                }
+
+                let model = known.get(&path).cloned();
+                let kind = if model.is_none() && flat_leftovers {
+                    StoreEntryKind::Other
+                } else {
+                    StoreEntryKind::Embedding
+                };
+                entries.push(StoreEntry {
+                    models: model.into_iter().collect(),
+                    kind,
+                    size,
+                    modified,
+                    path,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    fn remove_entry(&self, entry: &StoreEntry) -> ModelResult<()> {
+        debug!("Removing {}", entry.path.display());
+        if entry.path.is_dir() {
+            fs::remove_dir_all(&entry.path)?;
+        } else {
+            fs::remove_file(&entry.path)?;
+        }
+        self.prune_empty_dirs(entry.path.parent());
+        Ok(())
+    }
+
+    // Removes directories left empty by a removal, up to the store roots
+    fn prune_empty_dirs(&self, mut dir: Option<&Path>) {
+        while let Some(current) = dir {
+            if
+                current == self.model_home ||
+                current == self.embed_home ||
+                !(current.starts_with(&self.model_home) || current.starts_with(&self.embed_home))
+            {
+                break;
+            }
+            if fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+}
+
+fn walk(dir: &Path, skip: &Path, files: &mut Vec<PathBuf>) -> ModelResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path == skip {
+            continue;
+        }
+        if path.is_dir() {
+            walk(&path, skip, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Parses sizes such as `512M`, `20G` or `1.5T` (binary units) into bytes.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => {
+            return None;
+        }
+    };
+    Some((number * (multiplier as f64)) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::embedding_models::{ ImageEmbeddingModels, TextEmbeddingModels };
+
+    fn write_file(path: &Path, size: usize) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0u8; size]).unwrap();
+    }
+
+    fn set_age(path: &Path, age: Duration) {
+        let file = fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn setup(name: &str) -> (PathBuf, ModelStore, ModelRegistry) {
+        let root = std::env::temp_dir().join(format!("pyano-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let model_home = root.join("models");
+        let config_dir = root.join("configs");
+        fs::create_dir_all(&config_dir).unwrap();
+
+        let mut config = ModelConfig::default();
+        config.model_config.name = "split".to_string();
+        config.model_config.model_path = PathBuf::from("split/Q8_0/split-Q8_0-00001-of-00002.gguf");
+        fs::write(config_dir.join("split.json"), serde_json::to_string(&config).unwrap()).unwrap();
+
+        config.model_config.name = "single".to_string();
+        config.model_config.model_path = PathBuf::from("single/Q4_K_M/single-Q4_K_M.gguf");
+        fs::write(config_dir.join("single.json"), serde_json::to_string(&config).unwrap()).unwrap();
+
+        write_file(&model_home.join("split/Q8_0/split-Q8_0-00001-of-00002.gguf"), 100);
+        write_file(&model_home.join("split/Q8_0/split-Q8_0-00002-of-00002.gguf"), 100);
+        write_file(&model_home.join("single/Q4_K_M/single-Q4_K_M.gguf"), 50);
+        write_file(&model_home.join("old/old-Q2_K.gguf"), 30);
+        write_file(&model_home.join("single/Q4_K_M/single-Q4_K_M.gguf.part1"), 10);
+        write_file(&root.join("embed/text/MiniLMV6/config.json"), 5);
+        write_file(&root.join("embed/text/Unknown/config.json"), 5);
+        for shard in ["00001", "00002"] {
+            let path = format!("split/Q8_0/split-Q8_0-{}-of-00002.gguf", shard);
+            set_age(&model_home.join(path), Duration::from_secs(3600));
+        }
+
+        let store = ModelStore::with_dirs(model_home, root.join("embed"));
+        (root.clone(), store, ModelRegistry::from_dir(config_dir))
+    }
+
+    #[test]
+    fn test_list_and_du() {
+        let (root, store, registry) = setup("du");
+        let entries = store.list(&registry).unwrap();
+        assert_eq!(entries.len(), 7);
+
+        let partial = entries
+            .iter()
+            .find(|e| e.kind == StoreEntryKind::Partial)
+            .unwrap();
+        assert_eq!(partial.models, vec!["single"]);
+
+        let usage = store.du(&registry).unwrap();
+        assert_eq!(usage.total, 300);
+        assert_eq!(usage.per_model["split"], 200);
+        assert_eq!(usage.per_model["single"], 60);
+        assert_eq!(usage.per_model["MiniLMV6"], 5);
+        assert_eq!(usage.orphaned, 35);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_gc_with_budget() {
+        let (root, store, registry) = setup("gc");
+        let options = GcOptions {
+            budget_bytes: Some(120),
+            keep: HashSet::from(["single".to_string()]),
+            partial_max_age: Duration::ZERO,
+            ..GcOptions::default()
+        };
+
+        let report = store.gc(&registry, &options).unwrap();
+        assert_eq!(report.evicted_models, vec!["split"]);
+        assert_eq!(report.freed_bytes, 245);
+        assert_eq!(report.remaining_bytes, 55);
+        assert!(!store.model_home().join("old").exists());
+        assert!(!store.model_home().join("split").exists());
+        assert!(store.model_home().join("single/Q4_K_M/single-Q4_K_M.gguf").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_gc_keeps_fresh_partials() {
+        let (root, store, registry) = setup("gc-partial");
+        // A pull in progress has no registry config yet
+        let pulling = store.model_home().join("new/Q4_K_M/new-Q4_K_M.gguf.part");
+        write_file(&pulling, 10);
+
+        let report = store.gc(&registry, &GcOptions::default()).unwrap();
+        assert!(pulling.exists());
+        assert!(!report.removed.contains(&pulling));
+
+        let options = GcOptions { partial_max_age: Duration::ZERO, ..GcOptions::default() };
+        store.gc(&registry, &options).unwrap();
+        assert!(!pulling.exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_gc_refuses_with_broken_configs() {
+        let (root, store, _) = setup("gc-broken");
+        fs::write(root.join("configs/single.json"), "{ not json").unwrap();
+        let registry = ModelRegistry::from_dir(root.join("configs"));
+        assert!(store.gc(&registry, &GcOptions::default()).is_err());
+        assert!(store.model_home().join("single/Q4_K_M/single-Q4_K_M.gguf").exists());
+        assert!(store.model_home().join("old/old-Q2_K.gguf").exists());
+
+        // An unreadable config directory leaves every model looking orphaned
+        let registry = ModelRegistry::from_dir(root.join("missing"));
+        assert!(store.gc(&registry, &GcOptions::default()).is_err());
+        assert!(store.model_home().join("split").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_embedding_layout() {
+        let (root, store, registry) = setup("embed");
+        let embed = root.join("embed");
+        // As EmbeddingBuilder installs a model: every file under <category>/<model>
+        let minilm = EmbeddingModels::Text(TextEmbeddingModels::MiniLMV6);
+        for file in minilm.required_files() {
+            write_file(&embed.join("text/MiniLMV6").join(file), 1);
+        }
+        // A flat install, with its files straight in the category directory
+        let minilm12 = EmbeddingModels::Text(TextEmbeddingModels::MiniLMV12);
+        for file in minilm12.required_files() {
+            write_file(&embed.join("text").join(file), 1);
+        }
+        fs::write(embed.join("text/config.json"), r#"{"num_hidden_layers":12}"#).unwrap();
+        let clip = EmbeddingModels::Image(ImageEmbeddingModels::CLIP);
+        for file in clip.required_files() {
+            write_file(&embed.join("image").join(file), 1);
+        }
+        write_file(&embed.join("image/notes.txt"), 3);
+
+        // Listing leaves the flat install where it is
+        let entries = store.list(&registry).unwrap();
+        assert!(embed.join("text/1_Pooling").exists());
+        assert!(!embed.join("text/MiniLMV12").exists());
+        let unknown = entries
+            .iter()
+            .find(|entry| entry.path == embed.join("text/Unknown"))
+            .unwrap();
+        assert_eq!(unknown.kind, StoreEntryKind::Other);
+
+        // gc moves it into its own directory first
+        store.gc(&registry, &GcOptions::default()).unwrap();
+        let entries = store.list(&registry).unwrap();
+        let model = |name: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.models == vec![name.to_string()])
+                .unwrap()
+        };
+        assert_eq!(model("MiniLMV6").size, 11);
+        assert_eq!(model("MiniLMV12").path, embed.join("text/MiniLMV12"));
+        assert_eq!(model("CLIP").size, 6);
+        assert!(!embed.join("text/1_Pooling").exists());
+        let leftover = entries
+            .iter()
+            .find(|entry| entry.path == embed.join("image/notes.txt"))
+            .unwrap();
+        assert_eq!(leftover.kind, StoreEntryKind::Other);
+
+        for file in minilm.required_files() {
+            assert!(embed.join("text/MiniLMV6").join(file).is_file());
+        }
+        assert!(embed.join("text/MiniLMV12/1_Pooling/config.json").is_file());
+        assert!(embed.join("image/notes.txt").is_file());
+        assert!(!embed.join("text/Unknown").exists());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("2K"), Some(2048));
+        assert_eq!(parse_size("1.5GiB"), Some(1610612736));
+        assert_eq!(parse_size("10 GB"), Some(10 * (1 << 30)));
+        assert_eq!(parse_size("abc"), None);
+    }
+}
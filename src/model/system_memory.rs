@@ -1,98 +1,131 @@
 use log::{ debug, info };
-use sysinfo::System;
+use serde::{ Deserialize, Serialize };
+use sysinfo::{ Pid, ProcessesToUpdate, System };
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
+use super::eviction::EvictionPolicy;
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+/// Memory kept free for the OS and other processes when loading models.
+pub const DEFAULT_HEADROOM_GB: f32 = 1.0;
+
+fn to_gb(bytes: u64) -> f32 {
+    ((bytes as f64) / BYTES_PER_GB) as f32
+}
+
 pub struct SystemMemory {
     sys: Arc<RwLock<System>>,
+    headroom_gb: f32,
 }
 
 impl SystemMemory {
     pub fn new() -> Self {
-        let mut sys = System::new_all();
-        sys.refresh_all(); // Initial refresh
+        let mut sys = System::new();
+        sys.refresh_memory(); // Initial refresh
 
         Self {
             sys: Arc::new(RwLock::new(sys)),
+            headroom_gb: DEFAULT_HEADROOM_GB,
         }
     }
 
-    /// Returns available memory in gigabytes
-    pub async fn get_available_gb(&self) -> f32 {
-        let mut sys = self.sys.write().await;
-        sys.refresh_all();
+    pub fn with_headroom(mut self, headroom_gb: f32) -> Self {
+        self.headroom_gb = headroom_gb.max(0.0);
+        self
+    }
+
+    pub fn headroom_gb(&self) -> f32 {
+        self.headroom_gb
+    }
 
-        let total_kb = sys.total_memory();
-        let used_kb = sys.used_memory();
-        let available_kb = total_kb - used_kb;
+    /// Total and available bytes as seen by this process. Inside a container
+    /// the cgroup limit is used when it is tighter than the host's memory.
+    fn measure(sys: &mut System) -> (u64, u64, bool) {
+        sys.refresh_memory();
 
-        debug!(
-            "Memory stats (KB): total={}, used={}, available={}",
-            total_kb,
-            used_kb,
-            available_kb
-        );
+        let total = sys.total_memory();
+        let available = sys.available_memory();
+
+        match sys.cgroup_limits() {
+            Some(limits) if limits.total_memory > 0 && limits.total_memory < total => {
+                (limits.total_memory, limits.free_memory.min(available), true)
+            }
+            _ => (total, available, false),
+        }
+    }
+
+    /// Returns available memory in gigabytes, before headroom
+    pub async fn get_available_gb(&self) -> f32 {
+        let mut sys = self.sys.write().await;
+        let (total, available, _) = Self::measure(&mut sys);
 
-        let available_gb = (available_kb as f64) / (1024.0 * 1024.0 * 1024.0);
+        debug!("Memory stats (bytes): total={}, available={}", total, available);
+        let available_gb = to_gb(available);
         debug!("Available memory: {:.2} GB", available_gb);
-        available_gb as f32
+        available_gb
     }
 
     /// Returns total memory in gigabytes
     pub async fn get_total_gb(&self) -> f32 {
         let mut sys = self.sys.write().await;
-        sys.refresh_all();
+        let (total, _, _) = Self::measure(&mut sys);
 
-        let total_kb = sys.total_memory();
-        let total_gb = (total_kb as f64) / (1024.0 * 1024.0 * 1024.0);
+        let total_gb = to_gb(total);
         debug!("Total memory: {:.2} GB", total_gb);
-        total_gb as f32
+        total_gb
     }
 
     /// Returns used memory in gigabytes
     pub async fn get_used_gb(&self) -> f32 {
         let mut sys = self.sys.write().await;
-        sys.refresh_all();
+        let (total, available, _) = Self::measure(&mut sys);
 
-        let used_kb = sys.used_memory();
-        let used_gb = (used_kb as f64) / (1024.0 * 1024.0 * 1024.0);
+        let used_gb = to_gb(total.saturating_sub(available));
         debug!("Used memory: {:.2} GB", used_gb);
-        used_gb as f32
+        used_gb
     }
 
     /// Returns memory usage as a percentage
     pub async fn get_usage_percentage(&self) -> f32 {
-        let mut sys = self.sys.write().await;
-        sys.refresh_all();
-
-        let total = sys.total_memory() as f64;
-        let used = sys.used_memory() as f64;
-        let percentage = (used / total) * 100.0;
-        debug!("Memory usage: {:.1}%", percentage);
-        percentage as f32
+        let status = self.get_memory_status().await;
+        debug!("Memory usage: {:.1}%", status.usage_percentage);
+        status.usage_percentage
     }
 
-    /// Checks if there's enough memory available for the requested amount
+    /// Checks if the requested amount fits in available memory minus headroom
     pub async fn has_available_memory(&self, required_gb: f32) -> bool {
-        let available = self.get_available_gb().await;
-        debug!("Memory check: {:.2} GB available, {:.2} GB required", available, required_gb);
-        available >= required_gb
+        let usable = self.get_memory_status().await.usable_gb;
+        debug!("Memory check: {:.2} GB usable, {:.2} GB required", usable, required_gb);
+        usable >= required_gb
+    }
+
+    /// Resident set size of a process in gigabytes, or `None` if it is gone.
+    pub async fn process_rss_gb(&self, pid: u32) -> Option<f32> {
+        let mut sys = self.sys.write().await;
+        let pid = Pid::from_u32(pid);
+        sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+        sys.process(pid).map(|process| to_gb(process.memory()))
     }
 
     /// Get memory status summary
     pub async fn get_memory_status(&self) -> MemoryStatus {
         let mut sys = self.sys.write().await;
-        sys.refresh_all();
-
-        let total_kb = sys.total_memory();
-        let used_kb = sys.used_memory();
-        let available_kb = total_kb - used_kb;
+        let (total, available, cgroup_limited) = Self::measure(&mut sys);
+        let used = total.saturating_sub(available);
 
         let status = MemoryStatus {
-            total_gb: ((total_kb as f64) / (1024.0 * 1024.0 * 1024.0)) as f32,
-            available_gb: ((available_kb as f64) / (1024.0 * 1024.0)) as f32,
-            used_gb: ((used_kb as f64) / (1024.0 * 1024.0 * 1024.0)) as f32,
-            usage_percentage: (((used_kb as f64) / (total_kb as f64)) * 100.0) as f32,
+            total_gb: to_gb(total),
+            available_gb: to_gb(available),
+            used_gb: to_gb(used),
+            usage_percentage: if total > 0 {
+                (((used as f64) / (total as f64)) * 100.0) as f32
+            } else {
+                0.0
+            },
+            headroom_gb: self.headroom_gb,
+            usable_gb: (to_gb(available) - self.headroom_gb).max(0.0),
+            cgroup_limited,
         };
 
         debug!("Memory status: {:?}", status);
@@ -102,27 +135,224 @@ impl SystemMemory {
 
 impl SystemMemory {
     pub async fn debug_memory_info(&self) {
-        let mut sys = self.sys.write().await;
-        sys.refresh_all();
-
-        let total_gb = (sys.total_memory() as f64) / (1024.0 * 1024.0 * 1024.0);
-        let used_gb = (sys.used_memory() as f64) / (1024.0 * 1024.0 * 1024.0);
-        let available_gb = total_gb - used_gb;
+        let status = self.get_memory_status().await;
         debug!("");
         debug!("=== Memory Debug Information ===");
-        debug!("Total memory (GB): {:.2}", total_gb);
-        debug!("Used memory (GB): {:.2}", used_gb);
-        debug!("Available memory (GB): {:.2}", available_gb);
-        debug!("Memory usage (%): {:.1}", (used_gb / total_gb) * 100.0);
+        debug!("Total memory (GB): {:.2}", status.total_gb);
+        debug!("Used memory (GB): {:.2}", status.used_gb);
+        debug!("Available memory (GB): {:.2}", status.available_gb);
+        debug!("Usable after headroom (GB): {:.2}", status.usable_gb);
+        debug!("Memory usage (%): {:.1}", status.usage_percentage);
+        if status.cgroup_limited {
+            info!("Memory is limited by the container's cgroup");
+        }
         debug!("==============================");
         debug!("");
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MemoryStatus {
     pub total_gb: f32,
     pub available_gb: f32,
     pub used_gb: f32,
     pub usage_percentage: f32,
+    pub headroom_gb: f32,
+    /// Available memory minus headroom; what a new model may use
+    pub usable_gb: f32,
+    /// Whether a cgroup (container) limit is tighter than physical memory
+    pub cgroup_limited: bool,
+}
+
+/// A loaded model that [`MemoryPlan`] would unload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvictionCandidate {
+    pub name: String,
+    /// Measured RSS of the model's server, or the configured `min_ram_gb`
+    /// when the process could not be measured
+    pub memory_gb: f32,
+    pub measured: bool,
+    pub last_used: chrono::DateTime<chrono::Utc>,
+    /// Requests served since the model was loaded
+    pub use_count: u64,
+    pub priority: i32,
+    /// Requests currently being served; busy models are never evicted
+    pub in_flight: usize,
+    pub pinned: bool,
+}
+
+impl EvictionCandidate {
+    pub fn is_evictable(&self) -> bool {
+        !self.pinned && self.in_flight == 0
+    }
+}
+
+/// Dry-run answer to "what happens if a model needing `required_gb` loads now".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryPlan {
+    pub required_gb: f32,
+    pub status: MemoryStatus,
+    /// Models to unload, in order; empty when the model already fits
+    pub evict: Vec<EvictionCandidate>,
+    /// Loaded models that cannot be evicted because they are pinned or busy
+    pub blocked: Vec<String>,
+    /// Name of the eviction policy that ordered `evict`
+    pub policy: String,
+    pub freed_gb: f32,
+    /// Whether the model fits after the evictions
+    pub satisfiable: bool,
+    pub explanation: String,
+}
+
+impl MemoryPlan {
+    /// Picks evictable models from `candidates`, in the order chosen by
+    /// `policy`, until `required_gb` fits in the usable memory.
+    pub fn build(
+        required_gb: f32,
+        status: MemoryStatus,
+        candidates: Vec<EvictionCandidate>,
+        policy: &dyn EvictionPolicy
+    ) -> Self {
+        let (evictable, blocked): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|c| c.is_evictable());
+        let blocked: Vec<String> = blocked
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+
+        let mut evict = Vec::new();
+        let mut freed_gb = 0.0;
+
+        for candidate in policy.order(evictable, required_gb - status.usable_gb) {
+            if status.usable_gb + freed_gb >= required_gb {
+                break;
+            }
+            freed_gb += candidate.memory_gb;
+            evict.push(candidate);
+        }
+
+        let satisfiable = status.usable_gb + freed_gb >= required_gb;
+        let explanation = if evict.is_empty() && satisfiable {
+            format!(
+                "{:.1} GB required, {:.1} GB usable ({:.1} GB available minus {:.1} GB headroom)",
+                required_gb,
+                status.usable_gb,
+                status.available_gb,
+                status.headroom_gb
+            )
+        } else if satisfiable {
+            format!(
+                "{:.1} GB required, {:.1} GB usable; unloading {} frees {:.1} GB",
+                required_gb,
+                status.usable_gb,
+                evict
+                    .iter()
+                    .map(|c| c.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                freed_gb
+            )
+        } else if blocked.is_empty() {
+            format!(
+                "{:.1} GB required but only {:.1} GB usable even after unloading every eligible model ({:.1} GB)",
+                required_gb,
+                status.usable_gb + freed_gb,
+                freed_gb
+            )
+        } else {
+            format!(
+                "{:.1} GB required but only {:.1} GB usable after unloading every eligible model; {} pinned or busy",
+                required_gb,
+                status.usable_gb + freed_gb,
+                blocked.join(", ")
+            )
+        };
+
+        Self {
+            required_gb,
+            status,
+            evict,
+            blocked,
+            policy: policy.name().to_string(),
+            freed_gb,
+            satisfiable,
+            explanation,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::eviction::LruPolicy;
+
+    fn status(usable_gb: f32) -> MemoryStatus {
+        MemoryStatus {
+            total_gb: 16.0,
+            available_gb: usable_gb + 1.0,
+            used_gb: 15.0 - usable_gb,
+            usage_percentage: 50.0,
+            headroom_gb: 1.0,
+            usable_gb,
+            cgroup_limited: false,
+        }
+    }
+
+    fn candidate(name: &str, memory_gb: f32) -> EvictionCandidate {
+        EvictionCandidate {
+            name: name.to_string(),
+            memory_gb,
+            measured: true,
+            last_used: chrono::Utc::now(),
+            use_count: 0,
+            priority: 0,
+            in_flight: 0,
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn test_plan_evicts_until_it_fits() {
+        let candidates = vec![candidate("a", 2.0), candidate("b", 3.0), candidate("c", 4.0)];
+        let plan = MemoryPlan::build(6.0, status(2.0), candidates, &LruPolicy);
+        assert!(plan.satisfiable);
+        assert_eq!(
+            plan.evict
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert_eq!(plan.freed_gb, 5.0);
+
+        let plan = MemoryPlan::build(1.0, status(2.0), vec![candidate("a", 2.0)], &LruPolicy);
+        assert!(plan.satisfiable && plan.evict.is_empty());
+    }
+
+    #[test]
+    fn test_plan_unsatisfiable() {
+        let plan = MemoryPlan::build(10.0, status(2.0), vec![candidate("a", 2.0)], &LruPolicy);
+        assert!(!plan.satisfiable);
+        assert_eq!(plan.evict.len(), 1);
+    }
+
+    #[test]
+    fn test_plan_skips_pinned_and_busy() {
+        let mut pinned = candidate("pinned", 8.0);
+        pinned.pinned = true;
+        let mut busy = candidate("busy", 8.0);
+        busy.in_flight = 2;
+
+        let plan = MemoryPlan::build(
+            6.0,
+            status(2.0),
+            vec![pinned, busy, candidate("idle", 1.0)],
+            &LruPolicy
+        );
+        assert!(!plan.satisfiable);
+        assert_eq!(plan.blocked, vec!["pinned", "busy"]);
+        assert_eq!(plan.evict.len(), 1);
+        assert!(plan.explanation.contains("pinned or busy"));
+    }
 }
@@ -1,9 +1,10 @@
 use serde::{ Deserialize, Serialize };
 use std::path::PathBuf;
 use std::collections::HashMap;
+use std::ops::RangeInclusive;
 use chrono::{ DateTime, Utc };
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ModelConfig {
     pub model_config: ModelSpecificConfig,
     pub memory_config: ModelMemoryConfig,
@@ -22,11 +23,14 @@ impl Default for ModelConfig {
                 model_kind: "default".to_string(),
                 model_url: None,
                 download_if_not_exist: false,
+                sha256: None,
             },
             memory_config: ModelMemoryConfig {
                 min_ram_gb: 0.0,
                 recommended_ram_gb: 0.0,
                 gpu_memory_gb: None,
+                priority: 0,
+                pinned: false,
             },
             prompt_template: PromptTemplate {
                 template: "".to_string(),
@@ -44,7 +48,7 @@ impl Default for ModelConfig {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ModelSpecificConfig {
     pub name: String,
     pub model_path: PathBuf,
@@ -52,9 +56,12 @@ pub struct ModelSpecificConfig {
     pub model_kind: String,
     pub model_url: Option<String>,
     pub download_if_not_exist: bool,
+    // Expected SHA-256 of the downloaded file, checked after download
+    #[serde(default)]
+    pub sha256: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ServerConfig {
     // Network configuration
     pub host: String,
@@ -68,6 +75,60 @@ pub struct ServerConfig {
     pub use_mmap: bool,
     pub use_gpu: bool,
 
+    // llama-server binary, resolved from ADAPTERS_HOME and PATH when unset
+    #[serde(default)]
+    pub binary: Option<PathBuf>,
+
+    // Optional features, only passed when the detected llama.cpp build supports them
+    #[serde(default)]
+    pub jinja: bool,
+    #[serde(default)]
+    pub flash_attn: bool,
+    #[serde(default)]
+    pub embeddings: bool,
+
+    // Concurrent request slots (`--parallel`); the context is split between them
+    #[serde(default = "default_parallel")]
+    pub parallel: usize,
+    // Longest a request waits for a free slot before failing, None to wait indefinitely
+    #[serde(default = "default_queue_timeout_secs")]
+    pub queue_timeout_secs: Option<u64>,
+
+    // Server processes started for this model, on consecutive ports from `port`
+    #[serde(default = "default_replicas")]
+    pub replicas: usize,
+    // CPU range (`--cpu-range`, e.g. "0-3") for each replica, by replica index
+    #[serde(default)]
+    pub replica_cpu_ranges: Vec<String>,
+    // CPU range for a single server; replica_cpu_ranges takes precedence
+    #[serde(default)]
+    pub cpu_range: Option<String>,
+
+    // Speculative decoding with a smaller registry model
+    #[serde(default)]
+    pub draft: Option<DraftConfig>,
+    // KV cache element types (`--cache-type-k`/`-v`); a quantized V cache needs flash_attn
+    #[serde(default)]
+    pub cache_type_k: Option<KvCacheType>,
+    #[serde(default)]
+    pub cache_type_v: Option<KvCacheType>,
+    // Continuous batching, None to keep the server's default
+    #[serde(default)]
+    pub cont_batching: Option<bool>,
+    // Lock the weights in RAM so they are never swapped out
+    #[serde(default)]
+    pub mlock: bool,
+    #[serde(default)]
+    pub rope_scaling: Option<RopeScaling>,
+
+    // Directory for slot save/restore (`--slot-save-path`), relative to the model home
+    #[serde(default)]
+    pub slot_save_dir: Option<PathBuf>,
+
+    // LoRA adapters loaded with the model; requests pick them by name
+    #[serde(default)]
+    pub lora_adapters: Vec<LoraAdapter>,
+
     // Additional configuration
     pub extra_args: HashMap<String, String>,
 }
@@ -83,11 +144,209 @@ impl Default for ServerConfig {
             num_threads: None,
             use_mmap: true,
             use_gpu: false,
+            binary: None,
+            jinja: false,
+            flash_attn: false,
+            embeddings: false,
+            parallel: default_parallel(),
+            queue_timeout_secs: default_queue_timeout_secs(),
+            replicas: default_replicas(),
+            replica_cpu_ranges: Vec::new(),
+            cpu_range: None,
+            draft: None,
+            cache_type_k: None,
+            cache_type_v: None,
+            cont_batching: None,
+            mlock: false,
+            rope_scaling: None,
+            slot_save_dir: None,
+            lora_adapters: Vec::new(),
             extra_args: HashMap::new(),
         }
     }
 }
 
+impl ServerConfig {
+    /// Ports of the model's replicas, `port` up to `port + replicas - 1`. None
+    /// without a port, or when the last one would be past 65535.
+    pub fn port_range(&self) -> Option<RangeInclusive<u16>> {
+        let port = self.port?;
+        let last = port.checked_add(u16::try_from(self.replicas.max(1) - 1).ok()?)?;
+        Some(port..=last)
+    }
+}
+
+/// A draft model proposing tokens for the main model to verify
+/// (`--model-draft`, `--draft-max`, `--draft-min`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DraftConfig {
+    /// Registry name of the draft model, downloaded like any other model
+    pub model: String,
+    /// Most tokens drafted per step
+    #[serde(default)]
+    pub max: Option<usize>,
+    /// Fewest tokens drafted per step
+    #[serde(default)]
+    pub min: Option<usize>,
+    /// Layers of the draft model offloaded to the GPU
+    #[serde(default)]
+    pub gpu_layers: Option<i32>,
+}
+
+/// Element type of the KV cache, as accepted by `--cache-type-k`/`--cache-type-v`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KvCacheType {
+    F32,
+    F16,
+    Bf16,
+    Q8_0,
+    Q4_0,
+    Q4_1,
+    Iq4Nl,
+    Q5_0,
+    Q5_1,
+}
+
+impl KvCacheType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KvCacheType::F32 => "f32",
+            KvCacheType::F16 => "f16",
+            KvCacheType::Bf16 => "bf16",
+            KvCacheType::Q8_0 => "q8_0",
+            KvCacheType::Q4_0 => "q4_0",
+            KvCacheType::Q4_1 => "q4_1",
+            KvCacheType::Iq4Nl => "iq4_nl",
+            KvCacheType::Q5_0 => "q5_0",
+            KvCacheType::Q5_1 => "q5_1",
+        }
+    }
+
+    /// Average size of one cache element, including block scales.
+    pub fn bytes_per_element(&self) -> f64 {
+        match self {
+            KvCacheType::F32 => 4.0,
+            KvCacheType::F16 | KvCacheType::Bf16 => 2.0,
+            KvCacheType::Q8_0 => 34.0 / 32.0,
+            KvCacheType::Q4_0 | KvCacheType::Iq4Nl => 18.0 / 32.0,
+            KvCacheType::Q4_1 => 20.0 / 32.0,
+            KvCacheType::Q5_0 => 22.0 / 32.0,
+            KvCacheType::Q5_1 => 24.0 / 32.0,
+        }
+    }
+
+    pub fn is_quantized(&self) -> bool {
+        !matches!(self, KvCacheType::F32 | KvCacheType::F16 | KvCacheType::Bf16)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RopeScalingType {
+    None,
+    Linear,
+    Yarn,
+}
+
+/// RoPE scaling for running a model past its training context
+/// (`--rope-scaling`, `--rope-scale`, `--rope-freq-base`, `--yarn-orig-ctx`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RopeScaling {
+    #[serde(rename = "type")]
+    pub kind: RopeScalingType,
+    #[serde(default)]
+    pub factor: Option<f32>,
+    #[serde(default)]
+    pub freq_base: Option<f32>,
+    /// Training context of the model, for YaRN
+    #[serde(default)]
+    pub original_ctx: Option<usize>,
+}
+
+/// A LoRA adapter applied on top of the base model (`--lora`/`--lora-scaled`).
+/// Its id on the server is its position in `lora_adapters`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LoraAdapter {
+    /// Name requests select the adapter by
+    pub name: String,
+    /// GGUF file, relative to the model home like `model_path`
+    pub path: PathBuf,
+    /// Downloaded to `path` when the file is missing
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Scale used by requests that don't select adapters; 0 loads it disabled
+    #[serde(default = "default_lora_scale")]
+    pub scale: f32,
+}
+
+fn default_lora_scale() -> f32 {
+    1.0
+}
+
+impl ModelConfig {
+    /// Checks settings that llama-server would reject or that contradict each other.
+    pub fn validate(&self) -> Result<(), String> {
+        let server = &self.server_config;
+        if let Some(draft) = &server.draft {
+            if draft.model == self.model_config.name {
+                return Err("draft.model cannot be the model itself".to_string());
+            }
+            if let (Some(min), Some(max)) = (draft.min, draft.max) {
+                if min > max {
+                    return Err(format!("draft.min ({}) is larger than draft.max ({})", min, max));
+                }
+            }
+            if draft.max == Some(0) {
+                return Err("draft.max must be at least 1".to_string());
+            }
+        }
+        if server.cache_type_v.map_or(false, |cache_type| cache_type.is_quantized()) && !server.flash_attn {
+            return Err("a quantized cache_type_v needs flash_attn".to_string());
+        }
+        if let Some(rope) = &server.rope_scaling {
+            if rope.factor.map_or(false, |factor| !(factor > 0.0)) {
+                return Err("rope_scaling.factor must be positive".to_string());
+            }
+            if rope.freq_base.map_or(false, |base| !(base > 0.0)) {
+                return Err("rope_scaling.freq_base must be positive".to_string());
+            }
+        }
+        if server.replicas > 1 {
+            let Some(port) = server.port else {
+                return Err("replicas above 1 need a port to number them from".to_string());
+            };
+            if server.port_range().is_none() {
+                let replicas = server.replicas;
+                return Err(format!("{} replicas from port {} run past port 65535", replicas, port));
+            }
+        }
+        for (i, adapter) in server.lora_adapters.iter().enumerate() {
+            if server.lora_adapters[..i].iter().any(|other| other.name == adapter.name) {
+                return Err(format!("lora adapter {} is listed twice", adapter.name));
+            }
+            if !(adapter.scale >= 0.0) {
+                return Err(format!("lora adapter {} has a negative scale", adapter.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_parallel() -> usize {
+    1
+}
+
+fn default_replicas() -> usize {
+    1
+}
+
+fn default_queue_timeout_secs() -> Option<u64> {
+    Some(300)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ModelType {
     Text,
@@ -111,20 +370,26 @@ pub enum AudioModelKind {
     // Add more as needed
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ModelMemoryConfig {
     pub min_ram_gb: f32,
     pub recommended_ram_gb: f32,
     pub gpu_memory_gb: Option<f32>,
+    /// Higher priority models are evicted later by the priority policy
+    #[serde(default)]
+    pub priority: i32,
+    /// Pinned models are never evicted to make room for another model
+    #[serde(default)]
+    pub pinned: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct PromptTemplate {
     pub template: String,
     pub required_keys: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ModelDefaults {
     pub temperature: f32,
     pub top_p: f32,
@@ -158,3 +423,95 @@ pub enum ModelStatus {
     Stopped,
     Error(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_performance_settings() {
+        let mut config = ModelConfig::default();
+        config.model_config.name = "qwen-7b".to_string();
+        config.server_config.draft = Some(DraftConfig {
+            model: "qwen-0.5b".to_string(),
+            max: Some(16),
+            min: Some(4),
+            gpu_layers: None,
+        });
+        config.server_config.cache_type_k = Some(KvCacheType::Q8_0);
+        assert!(config.validate().is_ok());
+
+        let mut own_draft = config.clone();
+        own_draft.server_config.draft.as_mut().unwrap().model = "qwen-7b".to_string();
+        assert!(own_draft.validate().is_err());
+
+        let mut inverted = config.clone();
+        inverted.server_config.draft.as_mut().unwrap().min = Some(32);
+        assert!(inverted.validate().is_err());
+
+        config.server_config.cache_type_v = Some(KvCacheType::Q8_0);
+        assert!(config.validate().is_err());
+        config.server_config.flash_attn = true;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_server_settings() {
+        let server: ServerConfig = serde_json::from_value(serde_json::json!({
+            "host": "localhost",
+            "ctx_size": 8192,
+            "gpu_layers": 0,
+            "batch_size": 512,
+            "use_mmap": true,
+            "use_gpu": false,
+            "cache_type_k": "q8_0",
+            "rope_scaling": { "type": "yarn", "factor": 4.0, "original_ctx": 32768 },
+            "draft": { "model": "qwen-0.5b", "max": 16 },
+            "extra_args": {}
+        })).unwrap();
+        assert_eq!(server.cache_type_k, Some(KvCacheType::Q8_0));
+        assert_eq!(server.rope_scaling.unwrap().kind, RopeScalingType::Yarn);
+        assert_eq!(server.draft.unwrap().min, None);
+    }
+
+    #[test]
+    fn test_lora_adapters() {
+        let mut config = ModelConfig::default();
+        config.server_config = serde_json::from_value(serde_json::json!({
+            "host": "localhost",
+            "ctx_size": 4096,
+            "gpu_layers": 0,
+            "batch_size": 512,
+            "use_mmap": true,
+            "use_gpu": false,
+            "lora_adapters": [
+                { "name": "sql", "path": "qwen/sql-lora.gguf" },
+                { "name": "legal", "path": "qwen/legal-lora.gguf", "scale": 0.5 }
+            ],
+            "extra_args": {}
+        })).unwrap();
+        assert_eq!(config.server_config.lora_adapters[0].scale, 1.0);
+        assert!(config.validate().is_ok());
+
+        config.server_config.lora_adapters[1].name = "sql".to_string();
+        assert!(config.validate().is_err());
+        config.server_config.lora_adapters[1].name = "legal".to_string();
+        config.server_config.lora_adapters[1].scale = -1.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_replica_ports() {
+        let mut config = ModelConfig::default();
+        config.server_config.replicas = 3;
+        assert!(config.validate().is_err());
+
+        config.server_config.port = Some(5010);
+        assert_eq!(config.server_config.port_range(), Some(5010..=5012));
+        assert!(config.validate().is_ok());
+
+        config.server_config.port = Some(65534);
+        assert_eq!(config.server_config.port_range(), None);
+        assert!(config.validate().is_err());
+    }
+}
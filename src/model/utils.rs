@@ -1,17 +1,26 @@
 use std::collections::HashMap;
 use std::env;
+use std::sync::Once;
 use dotenv::dotenv;
 
+static DOTENV: Once = Once::new();
+
+// Load the .env file the first time any variable is looked up
+fn load_dotenv() {
+    DOTENV.call_once(|| {
+        dotenv().ok();
+    });
+}
+
 pub fn get_env_vars() -> HashMap<String, String> {
-    // Load .env file
-    dotenv().ok();
-    
+    load_dotenv();
+
     // Collect all environment variables into HashMap
     env::vars().collect()
 }
 
 // Example usage function
 pub fn get_env_var(key: &str) -> Option<String> {
-    dotenv().ok();
+    load_dotenv();
     env::var(key).ok()
-}
\ No newline at end of file
+}
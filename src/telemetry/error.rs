@@ -0,0 +1,14 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum TelemetryError {
+    #[error("IO error: {0}")] Io(#[from] std::io::Error),
+
+    #[error("Invalid trace exporter `{0}`")] InvalidExporter(String),
+
+    #[error("Failed to install tracing subscriber: {0}")] Init(String),
+
+    #[error("OTLP exporter error: {0}")] Otlp(String),
+}
+
+pub type TelemetryResult<T> = std::result::Result<T, TelemetryError>;
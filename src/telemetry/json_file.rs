@@ -0,0 +1,183 @@
+use std::fs::{ File, OpenOptions };
+use std::io::{ BufWriter, Write };
+use std::path::Path;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::{ SystemTime, UNIX_EPOCH };
+use parking_lot::Mutex;
+use serde_json::{ json, Map, Value };
+use tracing::field::{ Field, Visit };
+use tracing::span::{ Attributes, Id, Record };
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use super::error::TelemetryResult;
+
+/// Writes every closed span as one JSON line shaped like an OpenTelemetry
+/// span (trace and span ids, parent, start/end in unix nanos, attributes), so
+/// traces can be inspected or imported later without a collector.
+pub struct JsonFileLayer {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl JsonFileLayer {
+    /// Appends to `path`, creating it and its directory if needed.
+    pub fn new(path: &Path) -> TelemetryResult<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { writer: Mutex::new(BufWriter::new(file)) })
+    }
+}
+
+// Per-span data kept in the registry's extensions until the span closes
+struct SpanData {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    start: u64,
+    attributes: Map<String, Value>,
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+}
+
+fn unix_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+fn new_id(len: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(1);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mixed = (unix_nanos() as u128) << 64 | ((count as u128) * 0x9e37_79b9_7f4a_7c15);
+    let hex = format!("{:032x}", mixed ^ ((std::process::id() as u128) << 32));
+    hex[hex.len() - len..].to_string()
+}
+
+impl<S> Layer<S> for JsonFileLayer where S: Subscriber + for<'a> LookupSpan<'a> {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let parent = span
+            .parent()
+            .and_then(|parent| {
+                parent
+                    .extensions()
+                    .get::<SpanData>()
+                    .map(|data| (data.trace_id.clone(), data.span_id.clone()))
+            });
+
+        let mut attributes = Map::new();
+        attrs.record(&mut JsonVisitor(&mut attributes));
+        let data = SpanData {
+            trace_id: parent.as_ref().map_or_else(|| new_id(32), |(trace_id, _)| trace_id.clone()),
+            span_id: new_id(16),
+            parent_span_id: parent.map(|(_, span_id)| span_id),
+            start: unix_nanos(),
+            attributes,
+        };
+        span.extensions_mut().insert(data);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                values.record(&mut JsonVisitor(&mut data.attributes));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        let Some(data) = extensions.get::<SpanData>() else {
+            return;
+        };
+        let line = json!({
+            "trace_id": data.trace_id,
+            "span_id": data.span_id,
+            "parent_span_id": data.parent_span_id,
+            "name": span.name(),
+            "target": span.metadata().target(),
+            "start_time_unix_nano": data.start,
+            "end_time_unix_nano": unix_nanos(),
+            "attributes": data.attributes,
+        });
+
+        let mut writer = self.writer.lock();
+        let _ = writeln!(writer, "{}", line);
+        let _ = writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_spans_written_as_json_lines() {
+        let path = std::env::temp_dir().join(
+            format!("pyano-traces-{}/spans.jsonl", std::process::id())
+        );
+        let layer = JsonFileLayer::new(&path).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let chain = tracing::info_span!("chain.run", agents = 2);
+            let _chain = chain.enter();
+            let llm = tracing::info_span!("llm.request", model = "qwen", predicted_tokens = tracing::field::Empty);
+            llm.record("predicted_tokens", 40u64);
+            drop(llm);
+        });
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let spans: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(spans.len(), 2);
+
+        let (llm, chain) = (&spans[0], &spans[1]);
+        assert_eq!(llm["name"], "llm.request");
+        assert_eq!(llm["attributes"]["model"], "qwen");
+        assert_eq!(llm["attributes"]["predicted_tokens"], 40);
+        assert_eq!(llm["trace_id"], chain["trace_id"]);
+        assert_eq!(llm["parent_span_id"], chain["span_id"]);
+        assert!(chain["parent_span_id"].is_null());
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
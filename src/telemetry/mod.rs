@@ -0,0 +1,150 @@
+pub mod error;
+pub mod json_file;
+
+pub use error::{ TelemetryError, TelemetryResult };
+pub use json_file::JsonFileLayer;
+
+use std::path::PathBuf;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{ EnvFilter, Layer, Registry };
+
+/// Default collector endpoint for OTLP over gRPC.
+pub const DEFAULT_OTLP_ENDPOINT: &str = "http://localhost:4317";
+
+/// Where spans from chains, agents, LLM calls and tools are sent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceExporter {
+    /// One JSON line per finished span, for offline use
+    JsonFile(PathBuf),
+    /// An OpenTelemetry collector; needs the `otel` feature
+    Otlp {
+        endpoint: String,
+    },
+}
+
+impl TraceExporter {
+    /// Reads `PYANO_TRACE_EXPORTER`: `json:<path>`, `otlp` or `otlp:<endpoint>`.
+    pub fn from_env() -> TelemetryResult<Option<Self>> {
+        match std::env::var("PYANO_TRACE_EXPORTER") {
+            Ok(value) if !value.is_empty() => value.parse().map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl std::str::FromStr for TraceExporter {
+    type Err = TelemetryError;
+
+    fn from_str(value: &str) -> TelemetryResult<Self> {
+        match value.split_once(':') {
+            Some(("json", path)) if !path.is_empty() => Ok(TraceExporter::JsonFile(path.into())),
+            Some(("otlp", endpoint)) if !endpoint.is_empty() => {
+                Ok(TraceExporter::Otlp { endpoint: endpoint.to_string() })
+            }
+            None if value == "otlp" => {
+                Ok(TraceExporter::Otlp { endpoint: DEFAULT_OTLP_ENDPOINT.to_string() })
+            }
+            _ => Err(TelemetryError::InvalidExporter(value.to_string())),
+        }
+    }
+}
+
+/// Flushes pending spans when dropped; keep it alive for the program's lifetime.
+pub struct TracingGuard {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            let _ = provider.shutdown();
+        }
+    }
+}
+
+/// Installs a global `tracing` subscriber filtered by `RUST_LOG` (default
+/// `info`) that sends spans to `exporter`. The crate's `log` output is not
+/// affected, so this can be combined with `env_logger`.
+pub fn init_tracing(exporter: TraceExporter) -> TelemetryResult<TracingGuard> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let (layer, guard): (Box<dyn Layer<Registry> + Send + Sync>, TracingGuard) = match exporter {
+        TraceExporter::JsonFile(path) => {
+            let guard = TracingGuard {
+                #[cfg(feature = "otel")]
+                provider: None,
+            };
+            (Box::new(JsonFileLayer::new(&path)?), guard)
+        }
+        #[cfg(feature = "otel")]
+        TraceExporter::Otlp { endpoint } => {
+            let (layer, provider) = otlp_layer(&endpoint)?;
+            (layer, TracingGuard { provider: Some(provider) })
+        }
+        #[cfg(not(feature = "otel"))]
+        TraceExporter::Otlp { .. } => {
+            return Err(
+                TelemetryError::Otlp("pyano was built without the `otel` feature".to_string())
+            );
+        }
+    };
+
+    tracing_subscriber
+        ::registry()
+        .with(layer.with_filter(filter))
+        .try_init()
+        .map_err(|e| TelemetryError::Init(e.to_string()))?;
+    Ok(guard)
+}
+
+#[cfg(feature = "otel")]
+fn otlp_layer(
+    endpoint: &str
+) -> TelemetryResult<
+    (Box<dyn Layer<Registry> + Send + Sync>, opentelemetry_sdk::trace::TracerProvider)
+> {
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter
+        ::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| TelemetryError::Otlp(e.to_string()))?;
+    let provider = opentelemetry_sdk::trace::TracerProvider
+        ::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(
+            opentelemetry_sdk::Resource::new(vec![KeyValue::new("service.name", "pyano")])
+        )
+        .build();
+    let tracer = provider.tracer("pyano");
+    Ok((Box::new(tracing_opentelemetry::layer().with_tracer(tracer)), provider))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exporter() {
+        assert_eq!(
+            "json:/tmp/spans.jsonl".parse::<TraceExporter>().unwrap(),
+            TraceExporter::JsonFile("/tmp/spans.jsonl".into())
+        );
+        assert_eq!(
+            "otlp".parse::<TraceExporter>().unwrap(),
+            TraceExporter::Otlp { endpoint: DEFAULT_OTLP_ENDPOINT.to_string() }
+        );
+        assert_eq!(
+            "otlp:http://collector:4317".parse::<TraceExporter>().unwrap(),
+            TraceExporter::Otlp { endpoint: "http://collector:4317".to_string() }
+        );
+        assert!("zipkin".parse::<TraceExporter>().is_err());
+    }
+}
@@ -0,0 +1,685 @@
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::Duration;
+use futures_util::StreamExt;
+use indicatif::{ ProgressBar, ProgressStyle };
+use log::{ debug, info, warn };
+use reqwest::{ header, redirect, Client, RequestBuilder, StatusCode };
+use sha2::{ Digest, Sha256 };
+use tokio::fs::{ self, OpenOptions };
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+
+use super::error::{ DownloadError, DownloadResult };
+
+/// Snapshot passed to progress callbacks after every received chunk.
+#[derive(Debug, Clone)]
+pub struct DownloadProgress {
+    pub url: String,
+    pub file_name: String,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// What a HEAD request tells us about a remote file.
+#[derive(Debug, Clone, Default)]
+struct RemoteFile {
+    size: Option<u64>,
+    accepts_ranges: bool,
+    sha256: Option<String>,
+}
+
+/// Downloads files into `.part` files, resuming with HTTP `Range` requests,
+/// optionally splitting large files across several connections, and verifying
+/// SHA-256 before moving the result into place.
+#[derive(Clone)]
+pub struct Downloader {
+    client: Client,
+    probe_client: Client,
+    max_retries: u32,
+    retry_delay: Duration,
+    connections: usize,
+    min_chunk_size: u64,
+    progress: Vec<ProgressCallback>,
+    bearer_token: Option<String>,
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Downloader {
+    pub fn new() -> Self {
+        Self {
+            client: Client::new(),
+            // Hugging Face puts the LFS checksum on the redirect, so it must not be followed
+            probe_client: Client::builder()
+                .redirect(redirect::Policy::none())
+                .build()
+                .unwrap_or_else(|_| Client::new()),
+            max_retries: 3,
+            retry_delay: Duration::from_secs(2),
+            connections: 1,
+            min_chunk_size: 64 * 1024 * 1024,
+            progress: Vec::new(),
+            bearer_token: None,
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Number of parallel ranged connections used for files larger than
+    /// twice `min_chunk_size` on servers that accept ranges.
+    pub fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections.max(1);
+        self
+    }
+
+    pub fn with_min_chunk_size(mut self, min_chunk_size: u64) -> Self {
+        self.min_chunk_size = min_chunk_size.max(1);
+        self
+    }
+
+    /// Sends `token` as a bearer token on every request, e.g. a Hugging Face
+    /// token for gated repos. reqwest drops it on redirects to another host.
+    pub fn with_bearer_token(mut self, token: &str) -> Self {
+        self.bearer_token = Some(token.to_string());
+        self
+    }
+
+    /// Adds a progress callback; every callback added is called.
+    pub fn with_progress<F>(mut self, progress: F) -> Self
+        where F: Fn(&DownloadProgress) + Send + Sync + 'static
+    {
+        self.progress.push(Arc::new(progress));
+        self
+    }
+
+    /// Reports progress on an indicatif bar in the terminal.
+    pub fn with_progress_bar(self) -> Self {
+        let pb = ProgressBar::new(0);
+        if
+            let Ok(style) = ProgressStyle::default_bar().template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}"
+            )
+        {
+            pb.set_style(style.progress_chars("#>-"));
+        }
+        self.with_progress(move |progress| {
+            if let Some(total) = progress.total {
+                pb.set_length(total);
+            }
+            pb.set_message(progress.file_name.clone());
+            pb.set_position(progress.downloaded);
+            if progress.total == Some(progress.downloaded) {
+                pb.finish_with_message("Download complete");
+            }
+        })
+    }
+
+    /// Downloads `url` to `dest`. The checksum is taken from `expected_sha256`,
+    /// or from the `X-Linked-Etag` header Hugging Face sends for LFS files.
+    /// An existing `dest` that matches the checksum is left untouched.
+    pub async fn download(
+        &self,
+        url: &str,
+        dest: &Path,
+        expected_sha256: Option<&str>
+    ) -> DownloadResult<PathBuf> {
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let remote = self.probe(url).await.unwrap_or_else(|e| {
+            warn!("Could not probe {}: {}", url, e);
+            RemoteFile::default()
+        });
+        let expected = expected_sha256
+            .map(|s| s.to_lowercase())
+            .or_else(|| remote.sha256.clone());
+
+        if dest.exists() {
+            match &expected {
+                Some(expected) if &sha256_file(dest).await? != expected => {
+                    warn!("{} does not match its checksum, downloading again", dest.display());
+                    fs::remove_file(dest).await?;
+                }
+                _ => {
+                    debug!("{} already present", dest.display());
+                    return Ok(dest.to_path_buf());
+                }
+            }
+        }
+
+        let part_file = part_path(dest, None);
+        let chunks = self.plan_chunks(&remote);
+        let total = remote.size;
+
+        save_chunk_plan(dest, &chunks).await?;
+        if chunks.len() <= 1 {
+            let downloaded = Arc::new(AtomicU64::new(file_len(&part_file).await));
+            self.with_retries(url, || {
+                self.download_range(url, &part_file, 0, None, total, &downloaded)
+            }).await?;
+        } else {
+            info!("Downloading {} over {} connections", url, chunks.len());
+            let mut already = 0;
+            for i in 0..chunks.len() {
+                already += file_len(&part_path(dest, Some(i))).await;
+            }
+            let downloaded = Arc::new(AtomicU64::new(already));
+
+            let downloads = chunks
+                .iter()
+                .enumerate()
+                .map(|(i, &(start, end))| {
+                    let chunk_path = part_path(dest, Some(i));
+                    let downloaded = downloaded.clone();
+                    async move {
+                        self.with_retries(url, || {
+                            self.download_range(
+                                url,
+                                &chunk_path,
+                                start,
+                                Some(end),
+                                total,
+                                &downloaded
+                            )
+                        }).await
+                    }
+                });
+            futures::future::try_join_all(downloads).await?;
+            assemble_chunks(dest, chunks.len(), &part_file).await?;
+            fs::remove_file(plan_path(dest)).await?;
+        }
+
+        if let Some(expected) = &expected {
+            let actual = sha256_file(&part_file).await?;
+            if &actual != expected {
+                fs::remove_file(&part_file).await?;
+                return Err(DownloadError::ChecksumMismatch {
+                    file: dest.display().to_string(),
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+            debug!("Checksum verified for {}", dest.display());
+        }
+
+        fs::rename(&part_file, dest).await?;
+        info!("Downloaded {} to {}", url, dest.display());
+        Ok(dest.to_path_buf())
+    }
+
+    async fn probe(&self, url: &str) -> DownloadResult<RemoteFile> {
+        let mut remote = RemoteFile::default();
+
+        let first = self.authorized(self.probe_client.head(url)).send().await?;
+        remote.sha256 = first
+            .headers()
+            .get("x-linked-etag")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim_matches('"').to_lowercase())
+            .filter(|v| v.len() == 64 && v.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let response = if first.status().is_redirection() {
+            self.authorized(self.client.head(url)).send().await?
+        } else {
+            first
+        };
+        if !response.status().is_success() {
+            return Err(DownloadError::HttpStatus {
+                url: url.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+
+        remote.size = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+        remote.accepts_ranges = response
+            .headers()
+            .get(header::ACCEPT_RANGES)
+            .and_then(|v| v.to_str().ok())
+            .map_or(false, |v| v.contains("bytes"));
+        Ok(remote)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.bearer_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Inclusive byte ranges, one per connection.
+    fn plan_chunks(&self, remote: &RemoteFile) -> Vec<(u64, u64)> {
+        let size = match remote.size {
+            Some(size) if remote.accepts_ranges && self.connections > 1 => size,
+            _ => {
+                return Vec::new();
+            }
+        };
+        let count = ((size / self.min_chunk_size) as usize).min(self.connections);
+        if count < 2 {
+            return Vec::new();
+        }
+        let chunk_size = size.div_ceil(count as u64);
+        (0..count as u64)
+            .map(|i| (i * chunk_size, ((i + 1) * chunk_size).min(size) - 1))
+            .collect()
+    }
+
+    async fn with_retries<F, Fut>(&self, url: &str, mut attempt: F) -> DownloadResult<()>
+        where F: FnMut() -> Fut, Fut: std::future::Future<Output = DownloadResult<()>>
+    {
+        let mut tries = 0;
+        loop {
+            match attempt().await {
+                Ok(()) => {
+                    return Ok(());
+                }
+                Err(e) if tries < self.max_retries && is_retryable(&e) => {
+                    tries += 1;
+                    let delay = self.retry_delay * (1 << (tries - 1));
+                    warn!(
+                        "Download of {} failed ({}), retry {}/{} in {:?}",
+                        url,
+                        e,
+                        tries,
+                        self.max_retries,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Appends bytes `start..=end` of `url` to `path`, skipping whatever the
+    /// file already holds from an earlier attempt. `downloaded` must already
+    /// include those existing bytes.
+    async fn download_range(
+        &self,
+        url: &str,
+        path: &Path,
+        start: u64,
+        end: Option<u64>,
+        total: Option<u64>,
+        downloaded: &AtomicU64
+    ) -> DownloadResult<()> {
+        let existing = file_len(path).await;
+        let offset = start + existing;
+        let last_byte = end.or(total.map(|t| t.saturating_sub(1)));
+        if let Some(last_byte) = last_byte {
+            if existing > 0 && offset > last_byte {
+                self.report(url, path, downloaded.load(Ordering::SeqCst), total);
+                return Ok(());
+            }
+        }
+
+        let mut request = self.authorized(self.client.get(url));
+        if offset > 0 || end.is_some() {
+            let range = match end {
+                Some(end) => format!("bytes={}-{}", offset, end),
+                None => format!("bytes={}-", offset),
+            };
+            request = request.header(header::RANGE, range);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+
+        let resume = match status {
+            StatusCode::PARTIAL_CONTENT => true,
+            s if s.is_success() => {
+                if start > 0 || end.is_some() {
+                    return Err(
+                        DownloadError::Other(format!("{} ignored the requested byte range", url))
+                    );
+                }
+                false
+            }
+            s => {
+                return Err(DownloadError::HttpStatus {
+                    url: url.to_string(),
+                    status: s.as_u16(),
+                });
+            }
+        };
+
+        let mut file = if resume {
+            if existing > 0 {
+                debug!("Resuming {} at byte {}", path.display(), offset);
+            }
+            OpenOptions::new().create(true).append(true).open(path).await?
+        } else {
+            // The server sent the whole file, so start over
+            downloaded.fetch_sub(existing, Ordering::SeqCst);
+            fs::File::create(path).await?
+        };
+
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            file.write_all(&chunk).await?;
+            let now = downloaded.fetch_add(chunk.len() as u64, Ordering::SeqCst) +
+            (chunk.len() as u64);
+            self.report(url, path, now, total);
+        }
+        file.flush().await?;
+        Ok(())
+    }
+
+    fn report(&self, url: &str, path: &Path, downloaded: u64, total: Option<u64>) {
+        if !self.progress.is_empty() {
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let snapshot = DownloadProgress {
+                url: url.to_string(),
+                file_name: file_name.split(".part").next().unwrap_or_default().to_string(),
+                downloaded,
+                total,
+            };
+            for progress in &self.progress {
+                progress(&snapshot);
+            }
+        }
+    }
+}
+
+async fn file_len(path: &Path) -> u64 {
+    fs::metadata(path).await.map_or(0, |metadata| metadata.len())
+}
+
+fn part_path(dest: &Path, chunk: Option<usize>) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    match chunk {
+        Some(i) => name.push(format!(".part{}", i)),
+        None => name.push(".part"),
+    }
+    dest.with_file_name(name)
+}
+
+/// File listing the byte ranges of `dest`'s `.partN` files.
+fn plan_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".parts");
+    dest.with_file_name(name)
+}
+
+/// Records the byte ranges `dest` is downloaded in. Part files left by a
+/// different plan (another connection count, chunk size or remote size) hold
+/// other ranges, so they are deleted rather than resumed.
+async fn save_chunk_plan(dest: &Path, chunks: &[(u64, u64)]) -> DownloadResult<()> {
+    let plan_file = plan_path(dest);
+    let previous: Option<Vec<(u64, u64)>> = fs
+        ::read(&plan_file).await
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok());
+    if previous.as_deref() == Some(chunks) {
+        return Ok(());
+    }
+
+    let stale = previous.map_or(0, |plan| plan.len()).max(chunks.len());
+    for i in 0..stale {
+        let chunk_path = part_path(dest, Some(i));
+        if chunk_path.exists() {
+            debug!("Discarding {} from another chunk plan", chunk_path.display());
+            fs::remove_file(&chunk_path).await?;
+        }
+    }
+    if chunks.len() > 1 {
+        let plan = serde_json::to_vec(chunks).map_err(|e| DownloadError::Other(e.to_string()))?;
+        fs::write(&plan_file, plan).await?;
+    } else if plan_file.exists() {
+        fs::remove_file(&plan_file).await?;
+    }
+    Ok(())
+}
+
+async fn assemble_chunks(dest: &Path, count: usize, part_file: &Path) -> DownloadResult<()> {
+    let mut out = fs::File::create(part_file).await?;
+    for i in 0..count {
+        let chunk_path = part_path(dest, Some(i));
+        let mut chunk = fs::File::open(&chunk_path).await?;
+        tokio::io::copy(&mut chunk, &mut out).await?;
+    }
+    out.flush().await?;
+    for i in 0..count {
+        fs::remove_file(part_path(dest, Some(i))).await?;
+    }
+    Ok(())
+}
+
+fn is_retryable(err: &DownloadError) -> bool {
+    match err {
+        DownloadError::RequestError(_) | DownloadError::IoError(_) => true,
+        DownloadError::HttpStatus { status, .. } => *status >= 500 || *status == 429,
+        _ => false,
+    }
+}
+
+/// Hex-encoded SHA-256 of a file on disk.
+pub async fn sha256_file(path: &Path) -> DownloadResult<String> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        extract::State,
+        http::{ HeaderMap, StatusCode as HttpStatus },
+        response::Response,
+        routing::get,
+        Router,
+    };
+    use std::sync::atomic::AtomicUsize;
+
+    #[derive(Clone)]
+    struct TestServer {
+        data: Arc<Vec<u8>>,
+        range_requests: Arc<AtomicUsize>,
+        // Bearer token every request must carry, like a gated repo
+        token: Option<&'static str>,
+    }
+
+    async fn serve_file(State(server): State<TestServer>, headers: HeaderMap) -> Response {
+        if let Some(token) = server.token {
+            let authorization = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+            if authorization != Some(format!("Bearer {}", token).as_str()) {
+                return Response::builder()
+                    .status(HttpStatus::UNAUTHORIZED)
+                    .body(Body::empty())
+                    .unwrap();
+            }
+        }
+        let len = server.data.len();
+        let range = headers
+            .get(header::RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("bytes="))
+            .map(|v| {
+                let (start, end) = v.split_once('-').unwrap();
+                let start: usize = start.parse().unwrap();
+                let end: usize = end.parse().unwrap_or(len - 1);
+                (start, end)
+            });
+
+        match range {
+            Some((start, end)) => {
+                server.range_requests.fetch_add(1, Ordering::SeqCst);
+                Response::builder()
+                    .status(HttpStatus::PARTIAL_CONTENT)
+                    .header(header::ACCEPT_RANGES, "bytes")
+                    .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len))
+                    .body(Body::from(server.data[start..=end].to_vec()))
+                    .unwrap()
+            }
+            None =>
+                Response::builder()
+                    .header(header::ACCEPT_RANGES, "bytes")
+                    .header(header::CONTENT_LENGTH, len)
+                    .body(Body::from(server.data.to_vec()))
+                    .unwrap(),
+        }
+    }
+
+    async fn start_server(data: Vec<u8>) -> (String, TestServer) {
+        start_server_with_token(data, None).await
+    }
+
+    async fn start_server_with_token(
+        data: Vec<u8>,
+        token: Option<&'static str>
+    ) -> (String, TestServer) {
+        let server = TestServer {
+            data: Arc::new(data),
+            range_requests: Arc::new(AtomicUsize::new(0)),
+            token,
+        };
+        let app = Router::new().route("/model.gguf", get(serve_file)).with_state(server.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}/model.gguf", addr), server)
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pyano-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0..300_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn test_parallel_download_with_checksum() {
+        let data = test_data();
+        let expected = format!("{:x}", Sha256::digest(&data));
+        let (url, _server) = start_server(data.clone()).await;
+        let dir = test_dir("download-parallel");
+        let dest = dir.join("model.gguf");
+
+        let last_progress = Arc::new(AtomicU64::new(0));
+        let progress = last_progress.clone();
+        let downloader = Downloader::new()
+            .with_connections(4)
+            .with_min_chunk_size(50_000)
+            .with_progress(move |p| {
+                progress.fetch_max(p.downloaded, Ordering::SeqCst);
+            });
+
+        downloader.download(&url, &dest, Some(&expected)).await.unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        assert_eq!(last_progress.load(Ordering::SeqCst), data.len() as u64);
+        assert!(!part_path(&dest, None).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_resume_partial_download() {
+        let data = test_data();
+        let (url, server) = start_server(data.clone()).await;
+        let dir = test_dir("download-resume");
+        let dest = dir.join("model.gguf");
+        std::fs::write(part_path(&dest, None), &data[..1000]).unwrap();
+
+        Downloader::new().download(&url, &dest, None).await.unwrap();
+
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        assert_eq!(server.range_requests.load(Ordering::SeqCst), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_parts_from_another_plan_are_discarded() {
+        let data = test_data();
+        let (url, _server) = start_server(data.clone()).await;
+        let dir = test_dir("download-replan");
+        let dest = dir.join("model.gguf");
+        let downloader = || Downloader::new().with_min_chunk_size(50_000);
+
+        // Parts of a two-connection download, then resumed over four connections
+        let two = vec![(0, 149_999), (150_000, 299_999)];
+        std::fs::write(plan_path(&dest), serde_json::to_vec(&two).unwrap()).unwrap();
+        std::fs::write(part_path(&dest, Some(0)), &data[..1000]).unwrap();
+        std::fs::write(part_path(&dest, Some(1)), &data[150_000..151_000]).unwrap();
+        downloader().with_connections(4).download(&url, &dest, None).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        assert!(!plan_path(&dest).exists());
+
+        // Parts without a recorded plan can't be trusted either
+        std::fs::remove_file(&dest).unwrap();
+        std::fs::write(part_path(&dest, Some(1)), &data[..1000]).unwrap();
+        downloader().with_connections(2).download(&url, &dest, None).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token() {
+        let data = test_data();
+        let (url, _server) = start_server_with_token(data.clone(), Some("hf_secret")).await;
+        let dir = test_dir("download-token");
+        let dest = dir.join("model.gguf");
+        let downloader = Downloader::new().with_connections(2).with_min_chunk_size(50_000);
+
+        let result = downloader.clone().with_max_retries(0).download(&url, &dest, None).await;
+        assert!(matches!(result, Err(DownloadError::HttpStatus { status: 401, .. })));
+
+        // The probe and every ranged request carry the token
+        downloader.with_bearer_token("hf_secret").download(&url, &dest, None).await.unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), data);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch() {
+        let (url, _server) = start_server(test_data()).await;
+        let dir = test_dir("download-mismatch");
+        let dest = dir.join("model.gguf");
+
+        let result = Downloader::new().download(&url, &dest, Some(&"0".repeat(64))).await;
+
+        assert!(matches!(result, Err(DownloadError::ChecksumMismatch { .. })));
+        assert!(!dest.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    #[error("Request error: {0}")] RequestError(#[from] reqwest::Error),
+
+    #[error("I/O error: {0}")] IoError(#[from] std::io::Error),
+
+    #[error("Download of {url} failed with status {status}")] HttpStatus {
+        url: String,
+        status: u16,
+    },
+
+    #[error("Checksum mismatch for {file}: expected {expected}, got {actual}")] ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("Download failed: {0}")] Other(String),
+}
+
+pub type DownloadResult<T> = std::result::Result<T, DownloadError>;
@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use log::debug;
+use regex::Regex;
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::model::utils::get_env_var;
+use super::error::{ DownloadError, DownloadResult };
+
+const DEFAULT_ENDPOINT: &str = "https://huggingface.co";
+
+/// A file in a Hugging Face model repo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HfRepoFile {
+    pub path: String,
+    pub size: u64,
+    /// SHA-256 of LFS-tracked files, which includes every GGUF
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TreeEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    path: String,
+    #[serde(default)]
+    size: u64,
+    lfs: Option<LfsInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LfsInfo {
+    oid: String,
+    size: u64,
+}
+
+/// Minimal client for the Hugging Face Hub API. The endpoint defaults to
+/// `HF_ENDPOINT` so a local mirror can stand in for huggingface.co, and
+/// `HF_TOKEN` is sent for gated repos.
+#[derive(Clone)]
+pub struct HuggingFaceHub {
+    endpoint: String,
+    token: Option<String>,
+    client: Client,
+}
+
+impl Default for HuggingFaceHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HuggingFaceHub {
+    pub fn new() -> Self {
+        Self {
+            endpoint: get_env_var("HF_ENDPOINT").unwrap_or(DEFAULT_ENDPOINT.to_string()),
+            token: get_env_var("HF_TOKEN"),
+            client: Client::new(),
+        }
+    }
+
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// The token sent for gated repos; file downloads need it as well.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Lists every file in `repo` (e.g. `bartowski/Qwen2.5-7B-Instruct-GGUF`).
+    pub async fn list_files(&self, repo: &str, revision: &str) -> DownloadResult<Vec<HfRepoFile>> {
+        let url = format!("{}/api/models/{}/tree/{}?recursive=1", self.endpoint, repo, revision);
+        debug!("Listing files of {} from {}", repo, url);
+
+        let mut request = self.client.get(&url);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(DownloadError::HttpStatus {
+                url,
+                status: response.status().as_u16(),
+            });
+        }
+
+        let entries: Vec<TreeEntry> = response.json().await?;
+        Ok(
+            entries
+                .into_iter()
+                .filter(|entry| entry.kind == "file")
+                .map(|entry| HfRepoFile {
+                    size: entry.lfs.as_ref().map_or(entry.size, |lfs| lfs.size),
+                    sha256: entry.lfs.map(|lfs| lfs.oid),
+                    path: entry.path,
+                })
+                .collect()
+        )
+    }
+
+    pub fn file_url(&self, repo: &str, revision: &str, path: &str) -> String {
+        format!("{}/{}/resolve/{}/{}", self.endpoint, repo, revision, path)
+    }
+}
+
+fn shard_regex() -> Regex {
+    Regex::new(r"^(.*)-(\d{5})-of-(\d{5})\.gguf$").unwrap()
+}
+
+fn is_model_gguf(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    // Vision projectors and imatrix data ship as GGUF too
+    lower.ends_with(".gguf") && !lower.contains("mmproj") && !lower.contains("imatrix")
+}
+
+/// Quantization names found in the repo's GGUF file names, e.g. `Q4_K_M`.
+pub fn available_quants(files: &[HfRepoFile]) -> Vec<String> {
+    let re = Regex::new(r"(?i)(?:^|[-._/])((?:I?Q\d(?:_[0-9A-Z]+)*)|BF16|F16|F32)(?:[-.]|$)").unwrap();
+    let mut quants: Vec<String> = files
+        .iter()
+        .filter(|file| is_model_gguf(&file.path))
+        .filter_map(|file| re.captures(&file.path).map(|caps| caps[1].to_uppercase()))
+        .collect();
+    quants.sort();
+    quants.dedup();
+    quants
+}
+
+/// Picks the GGUF file(s) for `quant`. Split models (`-00001-of-00003.gguf`)
+/// return every shard in order, and all shards must be present.
+pub fn select_quant(files: &[HfRepoFile], quant: &str) -> DownloadResult<Vec<HfRepoFile>> {
+    let quant_re = Regex::new(
+        &format!(r"(?i)(?:^|[-._/]){}(?:[-.]|$)", regex::escape(quant))
+    ).unwrap();
+    let shard_re = shard_regex();
+
+    // Group candidates into single files and shard sets
+    let mut groups: BTreeMap<String, Vec<(usize, usize, HfRepoFile)>> = BTreeMap::new();
+    for file in files.iter().filter(|f| is_model_gguf(&f.path) && quant_re.is_match(&f.path)) {
+        match shard_re.captures(&file.path) {
+            Some(caps) => {
+                let index: usize = caps[2].parse().unwrap_or(0);
+                let count: usize = caps[3].parse().unwrap_or(0);
+                groups
+                    .entry(caps[1].to_string())
+                    .or_default()
+                    .push((index, count, file.clone()));
+            }
+            None => {
+                groups.entry(file.path.clone()).or_default().push((1, 1, file.clone()));
+            }
+        }
+    }
+
+    let mut groups: Vec<_> = groups.into_iter().collect();
+    match groups.len() {
+        0 =>
+            Err(
+                DownloadError::Other(
+                    format!(
+                        "No GGUF file for quant {}. Available quants: {}",
+                        quant,
+                        available_quants(files).join(", ")
+                    )
+                )
+            ),
+        1 => {
+            let (name, mut shards) = groups.remove(0);
+            shards.sort_by_key(|(index, _, _)| *index);
+            let expected = shards[0].1;
+            let complete = shards.len() == expected &&
+            shards.iter().enumerate().all(|(i, (index, _, _))| *index == i + 1);
+            if !complete {
+                return Err(
+                    DownloadError::Other(
+                        format!(
+                            "{} is split into {} shards but only {} are in the repo",
+                            name,
+                            expected,
+                            shards.len()
+                        )
+                    )
+                );
+            }
+            Ok(
+                shards
+                    .into_iter()
+                    .map(|(_, _, file)| file)
+                    .collect()
+            )
+        }
+        _ =>
+            Err(
+                DownloadError::Other(
+                    format!(
+                        "Quant {} matches several models: {}",
+                        quant,
+                        groups
+                            .iter()
+                            .map(|(name, _)| name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                )
+            ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> HfRepoFile {
+        HfRepoFile { path: path.to_string(), size: 1, sha256: None }
+    }
+
+    fn repo_files() -> Vec<HfRepoFile> {
+        vec![
+            file("README.md"),
+            file("Qwen2.5-7B-Instruct-Q4_K.gguf"),
+            file("Qwen2.5-7B-Instruct-Q4_K_M.gguf"),
+            file("Qwen2.5-7B-Instruct-Q8_0/Qwen2.5-7B-Instruct-Q8_0-00002-of-00002.gguf"),
+            file("Qwen2.5-7B-Instruct-Q8_0/Qwen2.5-7B-Instruct-Q8_0-00001-of-00002.gguf"),
+            file("mmproj-Qwen2.5-7B-Instruct-Q4_K_M.gguf")
+        ]
+    }
+
+    #[test]
+    fn test_select_single_file() {
+        let selected = select_quant(&repo_files(), "q4_k_m").unwrap();
+        assert_eq!(selected, vec![file("Qwen2.5-7B-Instruct-Q4_K_M.gguf")]);
+
+        let selected = select_quant(&repo_files(), "Q4_K").unwrap();
+        assert_eq!(selected, vec![file("Qwen2.5-7B-Instruct-Q4_K.gguf")]);
+    }
+
+    #[test]
+    fn test_select_shards_in_order() {
+        let selected = select_quant(&repo_files(), "Q8_0").unwrap();
+        assert_eq!(selected.len(), 2);
+        assert!(selected[0].path.ends_with("-00001-of-00002.gguf"));
+        assert!(selected[1].path.ends_with("-00002-of-00002.gguf"));
+    }
+
+    #[test]
+    fn test_missing_quant_lists_available() {
+        let err = select_quant(&repo_files(), "Q2_K").unwrap_err().to_string();
+        assert!(err.contains("Q4_K_M"));
+        assert!(err.contains("Q8_0"));
+        assert_eq!(available_quants(&repo_files()), vec!["Q4_K", "Q4_K_M", "Q8_0"]);
+    }
+}
@@ -1,2 +1,8 @@
 pub mod download;
+pub mod downloader;
+pub mod error;
+pub mod huggingface;
 pub use download::*;
+pub use downloader::*;
+pub use error::*;
+pub use huggingface::*;
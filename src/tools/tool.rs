@@ -7,6 +7,7 @@ use std::string::String;
 
 use async_trait::async_trait;
 use serde_json::{ json, Value };
+use tracing::Instrument;
 
 #[async_trait]
 pub trait Tool: Send + Sync {
@@ -57,15 +58,23 @@ pub trait Tool: Send + Sync {
     /// This function utilizes `parse_input` to parse the input and then calls `run`.
     /// Its used by the Agent
     async fn call(&self, input: &str) -> Result<String, Box<dyn Error>> {
-        let input = self.parse_input(input).await;
-        let json_result = self.run(input).await?;
-        // Convert the JSON result to a string
-        serde_json::to_string(&json_result).map_err(|e| e.into())
+        let span = tracing::info_span!("tool.call", tool = %self.name(), input_chars = input.len());
+        async move {
+            let input = self.parse_input(input).await;
+            let json_result = self.run(input).await?;
+            // Convert the JSON result to a string
+            serde_json::to_string(&json_result).map_err(|e| e.into())
+        }
+            .instrument(span).await
     }
 
     async fn json_call(&self, input: &str) -> Result<Value, Box<dyn Error>> {
-        let input = self.parse_input(input).await;
-        self.run(input).await
+        let span = tracing::info_span!("tool.call", tool = %self.name(), input_chars = input.len());
+        async move {
+            let input = self.parse_input(input).await;
+            self.run(input).await
+        }
+            .instrument(span).await
     }
 
     /// Executes the core functionality of the tool.
@@ -4,6 +4,7 @@ use sqlx::{ sqlite::{ SqliteConnectOptions, SqlitePoolOptions }, Pool, Sqlite };
 // main.rs
 use super::sqlite_vec::Store;
 use crate::embedding::embedder_trait::Embedder;
+use crate::config::PyanoConfig;
 pub struct StoreBuilder {
     pool: Option<Pool<Sqlite>>,
     connection_url: Option<String>,
@@ -28,7 +29,9 @@ impl StoreBuilder {
     }
 
     pub fn in_memory(mut self) -> Self {
-        let connection_url = std::env::var("DATABASE_URL").unwrap_or("sqlite::memory:".to_string());
+        let connection_url = PyanoConfig::global()
+            .database_url.clone()
+            .unwrap_or("sqlite::memory:".to_string());
 
         self.connection_url = Some(connection_url.into());
         self.pool = None;